@@ -196,12 +196,12 @@ struct Object {
 impl Object {
     pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
         Object {
-            x: x,
-            y: y,
-            char: char,
-            color: color,
+            x,
+            y,
+            char,
+            color,
             name: name.into(),
-            blocks: blocks,
+            blocks,
             alive: false,
             fighter: None,
             ai: None,
@@ -416,7 +416,7 @@ fn ai_basic(monster_id: usize, tcod: &Tcod, game: &mut Game, objects: &mut [Obje
             // move towards player if far away
             let (player_x, player_y) = objects[PLAYER].pos();
             move_towards(monster_id, player_x, player_y, &game.map, objects);
-        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
+        } else if objects[PLAYER].fighter.is_some_and(|f| f.hp > 0) {
             // close enough, attack! (if the player is still alive.)
             let (monster, player) = mut_two(monster_id, PLAYER, objects);
             monster.attack(player, game);
@@ -436,15 +436,22 @@ fn ai_confused(
     if num_turns >= 0 {
         // still confused ...
         // move in a random direction, and decrease the number of turns confused
-        move_by(
-            monster_id,
-            rand::thread_rng().gen_range(-1, 2),
-            rand::thread_rng().gen_range(-1, 2),
-            &game.map,
-            objects,
-        );
+        let (x, y) = objects[monster_id].pos();
+        let mut free_directions = vec![];
+        for dx in -1..2 {
+            for dy in -1..2 {
+                if (dx, dy) != (0, 0) && !is_blocked(x + dx, y + dy, &game.map, objects) {
+                    free_directions.push((dx, dy));
+                }
+            }
+        }
+        if !free_directions.is_empty() {
+            let index = rand::thread_rng().gen_range(0, free_directions.len());
+            let (dx, dy) = free_directions[index];
+            move_by(monster_id, dx, dy, &game.map, objects);
+        }
         Ai::Confused {
-            previous_ai: previous_ai,
+            previous_ai,
             num_turns: num_turns - 1,
         }
     } else {
@@ -531,7 +538,7 @@ fn target_tile(
         // accept the target if the player clicked in FOV, and in case a range
         // is specified, if it's in that range
         let in_fov = (x < MAP_WIDTH) && (y < MAP_HEIGHT) && tcod.fov.is_in_fov(x, y);
-        let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
+        let in_range = max_range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range);
         if tcod.mouse.lbutton_pressed && in_fov && in_range {
             return Some((x, y));
         }
@@ -719,11 +726,22 @@ fn cast_fireball(
     UseResult::UsedUp
 }
 
+/// Make the tile at (x, y) passable
+fn carve(x: i32, y: i32, map: &mut Map) {
+    assert!(
+        (0..MAP_WIDTH).contains(&x) && (0..MAP_HEIGHT).contains(&y),
+        "carving outside the map at ({}, {})",
+        x,
+        y
+    );
+    map[x as usize][y as usize] = Tile::empty();
+}
+
 fn create_room(room: Rect, map: &mut Map) {
     // go through the tiles in the rectangle and make them passable
     for x in (room.x1 + 1)..room.x2 {
         for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+            carve(x, y, map);
         }
     }
 }
@@ -731,14 +749,14 @@ fn create_room(room: Rect, map: &mut Map) {
 fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
     // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
     for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        carve(x, y, map);
     }
 }
 
 fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
     // vertical tunnel
     for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        carve(x, y, map);
     }
 }
 
@@ -774,9 +792,6 @@ fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
             // "paint" it to the map's tiles
             create_room(new_room, &mut map);
 
-            // add some content to this room, such as monsters
-            place_objects(new_room, &map, objects, level);
-
             // center coordinates of the new room, will be useful later
             let (new_x, new_y) = new_room.center();
 
@@ -802,6 +817,9 @@ fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
                 }
             }
 
+            // add some content to this room, such as monsters
+            place_objects(new_room, &map, objects, level);
+
             // finally, append the new room to the list
             rooms.push(new_room);
         }
@@ -1034,6 +1052,7 @@ fn next_level(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
     initialise_fov(tcod, &game.map);
 }
 
+#[allow(clippy::too_many_arguments)]
 fn render_bar(
     panel: &mut Offscreen,
     x: i32,
@@ -1065,7 +1084,7 @@ fn render_bar(
         y,
         BackgroundFlag::None,
         TextAlignment::Center,
-        &format!("{}: {}/{}", name, value, maximum),
+        format!("{}: {}/{}", name, value, maximum),
     );
 }
 
@@ -1126,7 +1145,7 @@ fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recomput
         })
         .collect();
     // sort so that non-blocking objects come first
-    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
+    to_draw.sort_by_key(|o| o.blocks);
     // draw the objects in the list
     for object in &to_draw {
         object.draw(&mut tcod.con);
@@ -1292,7 +1311,7 @@ fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root)
 
 fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option<usize> {
     // how a menu with each item of the inventory as an option
-    let options = if inventory.len() == 0 {
+    let options = if inventory.is_empty() {
         vec!["Inventory is empty.".into()]
     } else {
         inventory.iter().map(|item| item.name.clone()).collect()
@@ -1301,7 +1320,7 @@ fn inventory_menu(inventory: &[Object], header: &str, root: &mut Root) -> Option
     let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
 
     // if an item was chosen, return it
-    if inventory.len() > 0 {
+    if !inventory.is_empty() {
         inventory_index
     } else {
         None
@@ -1597,7 +1616,7 @@ fn play_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
 
         // render the screen
         let fov_recompute = previous_player_position != (objects[PLAYER].pos());
-        render_all(tcod, game, &objects, fov_recompute);
+        render_all(tcod, game, objects, fov_recompute);
 
         tcod.root.flush();
 
@@ -1639,9 +1658,8 @@ fn load_game() -> Result<(Game, Vec<Object>), Box<dyn Error>> {
 }
 
 fn main_menu(tcod: &mut Tcod) {
-    let img = tcod::image::Image::from_file("menu_background.png")
-        .ok()
-        .expect("Background image not found");
+    let img =
+        tcod::image::Image::from_file("menu_background.png").expect("Background image not found");
 
     while !tcod.root.window_closed() {
         // show the background image, at twice the regular console resolution
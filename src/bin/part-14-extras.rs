@@ -0,0 +1,12788 @@
+// This file is generated automatically. Do not edit it directly.
+// See the Contributing section in README on how to make changes to it.
+use std::cmp;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use rand::Rng;
+use rand::{SeedableRng, StdRng};
+use std::env;
+use std::error::Error;
+use std::fs::File;
+use std::io::{Read, Write};
+use tcod::colors::*;
+use tcod::console::*;
+use tcod::input::{self, Event, Key, Mouse};
+use tcod::map::{FovAlgorithm, Map as FovMap};
+
+use serde::{Deserialize, Serialize};
+
+use roguelike_tutorial::map::{create_room, dig_rooms, in_map, MAP_HEIGHT, MAP_WIDTH};
+use roguelike_tutorial::progression::{from_dungeon_level, interpolate_dungeon_level, Transition};
+use roguelike_tutorial::{mut_two, Map, Rect, Tile};
+
+// actual size of the window
+const SCREEN_WIDTH: i32 = 80;
+const SCREEN_HEIGHT: i32 = 50;
+
+// sizes and coordinates relevant for the GUI
+const BAR_WIDTH: i32 = 20;
+const PANEL_HEIGHT: i32 = 7;
+const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+const MSG_X: i32 = BAR_WIDTH + 2;
+const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
+const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+const MESSAGE_HISTORY: usize = 5000; // how many messages we remember
+const INVENTORY_WIDTH: i32 = 50;
+const INVENTORY_SIZE: usize = 52;
+const MENU_PAGE_SIZE: usize = 26; // one option for every letter
+const THROW_RANGE: i32 = 6;
+const CHARACTER_SCREEN_WIDTH: i32 = 30;
+const LEVEL_SCREEN_WIDTH: i32 = 40;
+const SLOT_MENU_WIDTH: i32 = 50;
+
+/// How many games can be saved at the same time.
+const SAVE_SLOTS: usize = 3;
+
+/// The version of the save file format. Bump this whenever a change
+/// to the saved data needs a migration in `migrate_save`.
+const SAVE_VERSION: u64 = 2;
+
+const CHASE_NUM_TURNS: i32 = 10;
+const PACK_ATTACK_BONUS: i32 = 2;
+const APPRENTICE_RANGE: f32 = 6.0;
+const APPRENTICE_LIGHTNING_DAMAGE: i32 = 10;
+const APPRENTICE_COOLDOWN: i32 = 4;
+const SLIME_SPLIT_CHANCE: f32 = 0.5;
+const SLIME_MIN_SPLIT_HP: i32 = 6;
+const CURSE_CHANCE: f32 = 0.15;
+const MAX_ENCHANTMENT: u32 = 5;
+const BASE_HIT_CHANCE: i32 = 90; // percent
+const DODGE_PER_DEFENSE: i32 = 3; // percent
+const CRIT_CHANCE: f32 = 0.05;
+const FIRE_TURNS: i32 = 2; // how long the floor burns after a fireball
+const BURN_DAMAGE: i32 = 3;
+const BURN_TURNS: i32 = 2;
+const NORMAL_SPEED: i32 = 100;
+const ACTION_COST: i32 = 100; // the energy needed to take any action
+const MONSTER_TIERS: [&str; 3] = ["", "veteran", "elite"];
+const GOLD_CHANCE: f32 = 0.5; // of a room having a pile of gold
+const GOLD_PER_XP: f32 = 0.2; // how much gold a monster drops
+const SHOP_CHANCE: f32 = 0.3; // of a level having a shop
+const SHOP_MIN_LEVEL: u32 = 2;
+const SHOP_STOCK_SIZE: usize = 6;
+// hunger, in turns without food
+const HUNGRY: i32 = 1000;
+const WEAK: i32 = 1500;
+const STARVING: i32 = 1800;
+const STARVE_INTERVAL: u32 = 5; // turns between losing a hit point to starvation
+const WEAK_POWER_PENALTY: i32 = 2;
+const FOOD_NUTRITION: i32 = 1000; // how much hunger a food ration takes away
+const SPELL_MANA: i32 = 10; // more max mana for every spell learned
+const MISSILE_DAMAGE: i32 = 15;
+const MISSILE_RANGE: i32 = 8;
+const BLINK_RANGE: i32 = 6;
+const BOW_RANGE: i32 = 8;
+const ARROW_FALLOFF: f32 = 4.0; // tiles for every point of damage lost in flight
+const ARROW_RECOVER_CHANCE: f32 = 0.5; // of a missed arrow being found again
+const VITALITY_BONUS: i32 = 10;
+const TELEPORT_TRIES: u32 = 1000;
+const HASTE_AMOUNT: i32 = 50; // extra speed
+const HASTE_TURNS: i32 = 20;
+const SLOW_AMOUNT: i32 = 40; // lost speed
+const SLOW_TURNS: i32 = 15;
+const SLOW_RANGE: i32 = 8;
+const TORCH_FUEL: u32 = 300; // turns a new torch burns for
+const INVISIBLE_TURNS: i32 = 30;
+const INVISIBLE_SPOT_CHANCE: f32 = 0.1; // per turn, for each monster
+const LOOT_SPREAD: i32 = 3; // how far dropped items may scatter
+const CORPSE_ROT_TURNS: u32 = 200; // how long a corpse lasts
+const CORPSE_NUTRITION: i32 = 300;
+const CHEST_CHANCE: f32 = 0.2; // of a room having a chest
+const CHEST_ITEMS: u32 = 3; // the most a chest holds
+const MEAT_PORTIONS: u32 = 2; // what a butchered corpse makes
+const ROGUE_KNIVES: u32 = 5;
+const MAX_NAME_LENGTH: usize = 20;
+const NAME_ENTRY_WIDTH: i32 = 40;
+const PAUSE_MENU_WIDTH: i32 = 40;
+/// The fonts that ship with the game. The first one is the default.
+const FONTS: &[&str] = &["arial10x10.png"];
+const FPS_LIMITS: [i32; 4] = [10, 20, 30, 60];
+const OPTIONS_MENU_WIDTH: i32 = 40;
+/// The order of the headings on the help screen.
+const HELP_CATEGORIES: [&str; 4] = [
+    "help.movement",
+    "help.items",
+    "help.information",
+    "help.system",
+];
+
+/// Keys that are handled outside of the key map and can't be rebound:
+/// heading, keys and description.
+const FIXED_KEYS: &[(&str, &str, &str)] = &[
+    ("help.movement", "Shift+direction", "help.run"),
+    ("help.movement", "Left click", "help.travel"),
+    ("help.information", "Ctrl+P", "help.command.History"),
+    ("help.system", "Alt+Enter", "help.fullscreen"),
+    ("help.system", "Escape", "help.pause"),
+];
+/// How long every frame of an animation stays on the screen.
+const ANIMATION_FRAME: Duration = Duration::from_millis(50);
+const BOLT_FRAMES: u32 = 4;
+const HIT_FLASH_FRAMES: u32 = 2;
+/// Below this percentage of their maximum health, the player gets a
+/// warning around the screen.
+const LOW_HP_PERCENT: i32 = 25;
+/// Monsters per room from which a level feels dangerous.
+const DANGER_PER_ROOM: f32 = 1.5;
+/// Items per room from which a level feels rich.
+const TREASURE_PER_ROOM: f32 = 1.0;
+/// Monsters per room up to which a level feels quiet.
+const QUIET_PER_ROOM: f32 = 0.3;
+
+/// How many turns pass between two ambient messages.
+const AMBIENT_MIN_TURNS: u32 = 30;
+const AMBIENT_MAX_TURNS: u32 = 60;
+
+/// The ambient messages, by the first dungeon level they're heard on.
+const AMBIENT_MESSAGES: &[(u32, &[&str])] = &[
+    (1, &["ambient.drip", "ambient.scurry", "ambient.draft"]),
+    (4, &["ambient.chant", "ambient.howl", "ambient.bones"]),
+    (7, &["ambient.roar", "ambient.footsteps", "ambient.whisper"]),
+];
+/// How many frames the frame rate is measured over.
+const FRAME_CLOCK_SIZE: usize = 60;
+const CONFUSED_BUMP_CHANCE: f32 = 0.3; // of a stuck confused monster showing it
+
+const FINAL_LEVEL: u32 = 10;
+const BOSS_ROOM_WIDTH: i32 = 20;
+const BOSS_ROOM_HEIGHT: i32 = 14;
+const BOSS_SUMMON_COOLDOWN: i32 = 5;
+const BOSS_SUMMON_COUNT: i32 = 2;
+const BOSS_DESPERATE_POWER_BONUS: i32 = 4;
+
+const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic; // default FOV algorithm
+
+const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
+const COLOR_LIGHT_WALL: Color = Color {
+    r: 130,
+    g: 110,
+    b: 50,
+};
+const COLOR_DARK_GROUND: Color = Color {
+    r: 50,
+    g: 50,
+    b: 150,
+};
+const COLOR_LIGHT_GROUND: Color = Color {
+    r: 200,
+    g: 180,
+    b: 50,
+};
+const COLOR_TARGET_AREA: Color = Color {
+    r: 255,
+    g: 130,
+    b: 130,
+};
+const COLOR_TARGET_CURSOR: Color = LIGHT_YELLOW;
+
+// player will always be the first object
+const PLAYER: usize = 0;
+
+/// A unique number identifying an object. Unlike the object's index
+/// in the `objects` list, it stays the same for the whole game.
+type ObjectId = u64;
+
+/// Translate a message id, filling in any positional arguments:
+/// `tr!(game.strings, "combat.attack", attacker, target, damage)`
+macro_rules! tr {
+    ($strings:expr, $key:expr) => {
+        $strings.get($key).to_string()
+    };
+    ($strings:expr, $key:expr, $($arg:expr),+ $(,)?) => {
+        $strings.format($key, &[$(&$arg as &dyn Display),+])
+    };
+}
+
+/// The built-in English text for every message id in the game. A
+/// translation only needs to list the ids it translates: anything
+/// missing falls back to this table.
+const DEFAULT_STRINGS: &[(&str, &str)] = &[
+    ("title.name", "TOMBS OF THE ANCIENT KINGS"),
+    ("title.author", "By Yours Truly"),
+    ("menu.new_game", "Play a new game"),
+    ("menu.continue", "Continue last game"),
+    ("menu.quit", "Quit"),
+    ("menu.no_saved_game", "\nNo saved game to load.\n"),
+    ("panel.hp", "HP"),
+    ("panel.dungeon_level", "Dungeon level: {0}"),
+    ("inventory.empty", "Inventory is empty."),
+    (
+        "inventory.use_header",
+        "Press the key next to an item to choose it, or any other to cancel.\n",
+    ),
+    (
+        "inventory.drop_header",
+        "Press the key next to an item to drop it, or any other to cancel.\n",
+    ),
+    ("inventory.equipped_on", "{0} (on {1})"),
+    (
+        "character.info",
+        "Character information\n\n{8} the {7}\nLevel: {0}\nExperience: {1}\n\
+         Experience to level up: {2}\nTurns: {9}\n\nMaximum HP: {3}\nAttack: {4}\nDamage: {6}\nDefense: {5}",
+    ),
+    (
+        "level_up.message",
+        "Your battle skills grow stronger, {1}! You reached level {0}!",
+    ),
+    ("level_up.header", "Level up! Choose a stat to raise:\n"),
+    ("level_up.constitution", "Constitution (+20 HP, from {0} to {1})"),
+    ("level_up.strength", "Strength (+1 attack, from {0} to {1})"),
+    ("level_up.agility", "Agility (+1 defense, from {0} to {1})"),
+    ("combat.attack", "{0} attacks {1} for {2} hit points."),
+    (
+        "combat.attack_no_effect",
+        "{0} attacks {1} but it has no effect!",
+    ),
+    ("combat.player_died", "{0} died!"),
+    (
+        "combat.monster_died",
+        "{0} is dead! You gain {1} experience points.",
+    ),
+    ("ai.no_longer_confused", "The {0} is no longer confused!"),
+    (
+        "item.inventory_full",
+        "Your inventory is full, cannot pick up {0}.",
+    ),
+    ("item.picked_up", "You picked up a {0}!"),
+    ("item.dropped", "You dropped a {0}."),
+    ("item.cannot_be_used", "The {0} cannot be used."),
+    ("item.cancelled", "Cancelled"),
+    ("item.equipped", "Equipped {0} on {1}."),
+    ("item.dequipped", "Dequipped {0} from {1}."),
+    ("spell.no_enemy", "No enemy is close enough to strike."),
+    ("spell.heal.full_health", "You are already at full health."),
+    ("spell.heal.cast", "Your wounds start to feel better!"),
+    (
+        "spell.lightning.cast",
+        "A lightning bolt strikes the {0} with a loud thunder! \
+         The damage is {1} hit points.",
+    ),
+    (
+        "spell.confuse.prompt",
+        "Left-click an enemy to confuse it, or right-click to cancel.",
+    ),
+    (
+        "spell.confuse.cast",
+        "The eyes of {0} look vacant, as he starts to stumble around!",
+    ),
+    (
+        "spell.fireball.prompt",
+        "Left-click a target tile for the fireball, or right-click to cancel.",
+    ),
+    (
+        "spell.fireball.cast",
+        "The fireball explodes, burning everything within {0} tiles!",
+    ),
+    (
+        "spell.fireball.burn",
+        "The {0} gets burned for {1} hit points.",
+    ),
+    (
+        "level.rest",
+        "You take a moment to rest, and recover your strength.",
+    ),
+    (
+        "level.descend",
+        "After a rare moment of peace, you descend deeper into \
+         the heart of the dungeon...",
+    ),
+    (
+        "game.welcome",
+        "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.",
+    ),
+    ("tile.title", "Things here"),
+    ("tile.keys", "letter: choose  *: all  Enter: take"),
+    ("tile.pick_up", "pick up {0}"),
+    ("tile.open", "open {0}"),
+    ("tile.close", "close {0}"),
+    ("tile.take", "take {0}"),
+    ("tile.butcher", "butcher {0}"),
+    ("thief.steal", "The {0} steals your {1}!"),
+    ("thief.drop", "The {0} drops your {1}."),
+    ("thief.escaped", "The {0} got away with your {1}!"),
+    ("slime.split", "The {0} splits in two!"),
+    ("npc.bump", "The {0} looks at you nervously.\n"),
+    ("npc.talk", "Talk"),
+    ("npc.attack", "Attack"),
+    (
+        "npc.lost_adventurer.says",
+        "\"Have you seen the way out? I came down here looking for \
+         treasure and now I can't even find the stairs...\"",
+    ),
+    ("npc.provoked", "The {0} turns on you!"),
+    ("boss.enraged", "The {0} howls and calls for its servants!"),
+    ("boss.summon", "The {0} summons help!"),
+    ("boss.desperate", "The {0} fights desperately!"),
+    (
+        "boss.defeated",
+        "The {0} falls! Its crown rolls to your feet.",
+    ),
+    (
+        "spell.confuse.resisted",
+        "The {0} shrugs off the confusion!",
+    ),
+    (
+        "victory",
+        "You have defeated the ancient king!\n\n\
+         The Tombs of the Ancient Kings are finally at peace. \
+         You may keep exploring, but your quest is complete.",
+    ),
+    ("menu.choose_slot", "Choose a save slot:\n"),
+    (
+        "menu.slot",
+        "Slot {0}: {4}, level {1}, dungeon level {2}, {3}",
+    ),
+    ("menu.slot_unknown", "Slot {0}: saved game"),
+    ("menu.slot_empty", "Slot {0}: empty"),
+    (
+        "menu.overwrite",
+        "There's already a game in slot {0}. Start over anyway?\n",
+    ),
+    ("menu.yes", "Yes"),
+    ("menu.no", "No"),
+    ("time.minutes_ago", "{0} minutes ago"),
+    ("time.hours_ago", "{0} hours ago"),
+    ("time.days_ago", "{0} days ago"),
+    ("menu.hall_of_heroes", "Hall of heroes"),
+    ("hall.header", "Hall of heroes\n"),
+    ("hall.empty", "No heroes yet. Be the first!"),
+    (
+        "hall.entry",
+        "{0}. {6}, {1} points ({7}): level {2}, dungeon level {3}, {4} turns, {5}",
+    ),
+    ("hall.killed_by", "killed by {0}"),
+    ("hall.victory", "defeated the ancient king"),
+    ("cause.lightning", "a lightning bolt"),
+    ("cause.fireball", "a fireball"),
+    ("morgue.killed_by", "Killed by {0} on dungeon level {1}."),
+    ("morgue.victory", "Defeated the ancient king."),
+    ("morgue.quit", "Still exploring dungeon level {0}."),
+    (
+        "morgue.character",
+        "{4} the {5}, playing on {6}\n\
+         Level {0}, {1} experience points\n\
+         Dungeon level reached: {2}\n\
+         Turns: {3}",
+    ),
+    ("morgue.inventory", "Inventory:"),
+    ("morgue.messages", "Last messages:"),
+    (
+        "morgue.saved",
+        "(Saved to {0}. Use the arrow keys to scroll, any other key to close.)",
+    ),
+    (
+        "menu.corrupt_save",
+        "\nThe saved game is damaged and can't be loaded.\n",
+    ),
+    (
+        "menu.incompatible_save",
+        "\nThis game was saved by a newer version of the game \
+         (save format {0}) and can't be loaded.\n",
+    ),
+    (
+        "look.hint",
+        "Looking around. Movement keys move, Enter examines, x or Escape stops.",
+    ),
+    ("look.unexplored", "You haven't been there yet."),
+    ("look.out_of_sight", "You can't see it from here."),
+    ("look.wall", "A stone wall."),
+    ("look.floor", "Stone floor."),
+    ("describe.player", "That's you."),
+    ("describe.orc", "A brutish orc, all muscle and no manners."),
+    (
+        "describe.troll",
+        "A huge troll. It hits hard and takes a lot of beating.",
+    ),
+    (
+        "describe.wolf",
+        "A hungry wolf. Where there's one, there's a pack.",
+    ),
+    (
+        "describe.dark apprentice",
+        "A student of the dark arts, dangerous from a distance.",
+    ),
+    (
+        "describe.thief",
+        "A sneaky thief with an eye for your belongings.",
+    ),
+    (
+        "describe.slime",
+        "A wobbling slime. Cutting it up only makes more of it.",
+    ),
+    (
+        "describe.ancient king",
+        "The ancient king himself, risen from his tomb.",
+    ),
+    ("describe.giant rat", "A rat the size of a dog."),
+    (
+        "describe.lost adventurer",
+        "Another adventurer, lost in the dungeon. Leave them be.",
+    ),
+    ("describe.healing potion", "Heals some of your wounds."),
+    (
+        "describe.scroll of lightning bolt",
+        "Strikes the closest enemy with lightning.",
+    ),
+    (
+        "describe.scroll of fireball",
+        "Bursts into flames wherever you aim it, burning everyone around.",
+    ),
+    (
+        "describe.scroll of confusion",
+        "Sends an enemy stumbling around aimlessly for a while.",
+    ),
+    (
+        "describe.dagger",
+        "A small blade. Better than your bare hands.",
+    ),
+    ("describe.sword", "A sharp sword. Makes you hit harder."),
+    ("describe.shield", "Makes you harder to hurt."),
+    ("describe.helmet", "Protects your head."),
+    ("describe.chain mail", "Heavy, but it turns a lot of blows."),
+    (
+        "describe.crown of the ancient kings",
+        "The crown of the ancient kings, and the proof of your victory.",
+    ),
+    ("describe.stairs", "Stairs leading further down."),
+    ("describe.monster", "It doesn't look friendly."),
+    ("describe.item", "You could pick it up."),
+    (
+        "history.title",
+        "Messages (Up/Down, PgUp/PgDn, Escape to close)",
+    ),
+    ("health_bars.on", "Monster health is shown."),
+    ("health_bars.off", "Monster health is hidden."),
+    ("names.detail", "{0} ({1})"),
+    ("health.wounded", "wounded"),
+    ("health.badly_wounded", "badly wounded"),
+    ("health.almost_dead", "almost dead"),
+    (
+        "equipment.header",
+        "Equipment (pick a slot to change it):\n",
+    ),
+    ("equipment.slot", "{0}: {1}"),
+    ("equipment.empty", "-- empty --"),
+    ("equipment.power", "{0} power"),
+    ("equipment.defense", "{0} defense"),
+    ("equipment.max_hp", "{0} HP"),
+    ("equipment.choose", "What do you want on your {0}?\n"),
+    ("equipment.take_off", "Take off the {0}"),
+    ("equipment.nothing", "You have nothing to wear on your {0}."),
+    ("item_menu.use", "Use"),
+    ("item_menu.equip", "Equip"),
+    ("item_menu.take_off", "Take off"),
+    ("item_menu.drop", "Drop"),
+    ("item_menu.throw", "Throw"),
+    ("item_menu.examine", "Examine"),
+    ("item.thrown", "You throw the {0}."),
+    ("item.cant_throw", "You can't throw it there."),
+    ("item.stack", "{0} (x{1})"),
+    ("details.heal", "Restores {0} hit points."),
+    (
+        "details.lightning",
+        "Deals {0} damage to the closest enemy within {1} tiles.",
+    ),
+    (
+        "details.confuse",
+        "Confuses an enemy within {0} tiles for {1} turns.",
+    ),
+    (
+        "details.fireball",
+        "Deals {0} damage to everyone within {1} tiles of where it lands.",
+    ),
+    ("details.equipment", "It goes on your {0}."),
+    ("item.charges", "{0} ({1})"),
+    (
+        "item.wand_crumbles",
+        "Your {0} fizzles and crumbles to dust.",
+    ),
+    (
+        "describe.wand of lightning",
+        "Strikes the closest enemy with lightning, while it lasts.",
+    ),
+    (
+        "describe.wand of confusion",
+        "Confuses an enemy, while it lasts.",
+    ),
+    (
+        "item.curse_revealed",
+        "The {0} is cursed! It clings to you.",
+    ),
+    ("item.cursed", "You can't remove the {0} -- it's cursed!"),
+    ("equipment.cursed", "cursed"),
+    (
+        "spell.remove_curse.lifted",
+        "A soothing light washes over your belongings.",
+    ),
+    (
+        "spell.remove_curse.nothing",
+        "You feel as if someone is watching over you.",
+    ),
+    (
+        "describe.scroll of remove curse",
+        "Lifts the curses from everything you carry.",
+    ),
+    (
+        "spell.enchant.prompt",
+        "Choose an item to enchant, or any other key to cancel.\n",
+    ),
+    ("spell.enchant.nothing", "You have nothing to enchant."),
+    (
+        "spell.enchant.done",
+        "Your {0} glows with a faint blue light.",
+    ),
+    (
+        "spell.enchant.wasted",
+        "The {0} can't hold any more magic. The scroll is wasted.",
+    ),
+    (
+        "describe.scroll of enchantment",
+        "Makes a weapon or a piece of armor a little better.",
+    ),
+    ("describe.leather armor", "Light armor of boiled leather."),
+    (
+        "describe.plate armor",
+        "Heavy steel plates. Hard to get hurt in.",
+    ),
+    (
+        "describe.ring of strength",
+        "A plain iron ring. Wearing it makes you stronger.",
+    ),
+    (
+        "describe.ring of vitality",
+        "A ring that pulses faintly, like a heartbeat.",
+    ),
+    ("combat.miss", "{0} misses {1}."),
+    (
+        "details.weapon",
+        "It goes on your {0} and deals {1} damage.",
+    ),
+    (
+        "combat.critical",
+        "{0} critically hits {1} for {2} hit points!",
+    ),
+    ("effect.poisoned.start", "{0} is poisoned!"),
+    (
+        "effect.poisoned.tick",
+        "{0} takes {1} damage from the poison.",
+    ),
+    ("effect.poisoned.end", "{0} is no longer poisoned."),
+    ("effect.poisoned.source", "poison"),
+    ("effect.burning.start", "{0} catches fire!"),
+    ("effect.burning.tick", "{0} burns for {1} hit points."),
+    ("effect.burning.end", "The flames on {0} die out."),
+    ("effect.burning.source", "fire"),
+    ("effect.stunned.start", "{0} is stunned!"),
+    ("effect.stunned.tick", "{0} is too dazed to act."),
+    ("effect.stunned.end", "{0} is no longer stunned."),
+    (
+        "describe.snake",
+        "A long green snake. Its bite is venomous.",
+    ),
+    (
+        "describe.mace",
+        "A heavy mace. A good blow leaves the enemy dazed.",
+    ),
+    (
+        "describe.giant bat",
+        "A bat with a wingspan of a man's arms. It's hard to get away from.",
+    ),
+    (
+        "describe.zombie",
+        "A shambling corpse. It's strong, but very slow.",
+    ),
+    ("panel.gold", "Gold: {0}"),
+    ("gold.pick_up", "You pick up {0} gold."),
+    ("describe.gold", "A pile of gold coins."),
+    ("npc.trade", "Trade"),
+    ("shop.header", "You have {0} gold.\n"),
+    ("shop.buy", "Buy"),
+    ("shop.sell", "Sell"),
+    ("shop.buy_header", "Buy what? You have {0} gold.\n"),
+    ("shop.sell_header", "Sell what? You have {0} gold.\n"),
+    ("shop.price", "{0} - {1} gold"),
+    ("shop.bought", "You buy the {0} for {1} gold."),
+    ("shop.sold", "You sell the {0} for {1} gold."),
+    ("shop.too_expensive", "You can't afford the {0}."),
+    (
+        "shop.not_interested",
+        "The shopkeeper isn't interested in the {0}.",
+    ),
+    ("shop.empty", "The shop is sold out."),
+    (
+        "describe.shopkeeper",
+        "He'll sell you anything, for a price.",
+    ),
+    ("hunger.hungry", "You are getting hungry."),
+    ("hunger.weak", "You feel weak with hunger."),
+    ("hunger.starving", "You are starving!"),
+    ("panel.hungry", "Hungry"),
+    ("panel.weak", "Weak"),
+    ("panel.starving", "Starving"),
+    ("cause.starvation", "starvation"),
+    ("food.eat", "That hit the spot."),
+    ("food.not_hungry", "You're not hungry."),
+    (
+        "describe.food ration",
+        "Dried meat and hard bread. It keeps you going.",
+    ),
+    ("panel.mana", "MP"),
+    ("level_up.learn", "Learn {0} ({1} mana)"),
+    ("spellbook.magic_missile", "magic missile"),
+    ("spellbook.heal", "heal"),
+    ("spellbook.blink", "blink"),
+    ("spellbook.learned", "You learn to cast {0}."),
+    ("spellbook.empty", "You don't know any spells."),
+    ("spellbook.header", "Cast which spell? You have {0} mana.\n"),
+    ("spellbook.option", "{0} ({1} mana)"),
+    (
+        "spellbook.no_mana",
+        "You don't have enough mana to cast {0}.",
+    ),
+    (
+        "spell.magic_missile.prompt",
+        "Left-click an enemy to hit it, or right-click to cancel.",
+    ),
+    (
+        "spell.magic_missile.cast",
+        "A magic missile strikes the {0} for {1} hit points.",
+    ),
+    ("cause.magic_missile", "a magic missile"),
+    (
+        "spell.blink.prompt",
+        "Left-click where to blink to, or right-click to cancel.",
+    ),
+    ("spell.blink.blocked", "Something is in the way."),
+    ("spell.blink.cast", "You blink across the room."),
+    ("fire.no_bow", "You don't have a bow ready."),
+    ("fire.no_arrows", "You're out of arrows."),
+    (
+        "fire.prompt",
+        "Left-click an enemy to shoot it, or right-click to cancel.",
+    ),
+    ("fire.miss", "The arrow misses the {0}."),
+    ("fire.hit", "The arrow hits the {0} for {1} hit points."),
+    ("fire.no_effect", "The arrow bounces off the {0}."),
+    ("cause.arrow", "an arrow"),
+    ("describe.bow", "A short bow. Press f to shoot it."),
+    ("describe.arrow", "Goes with a bow."),
+    (
+        "potion.strength",
+        "You feel strong! Your power goes up by 1.",
+    ),
+    (
+        "potion.vitality",
+        "You feel hale and hearty! Your maximum HP goes up.",
+    ),
+    (
+        "potion.agility",
+        "You feel nimble! Your defense goes up by 1.",
+    ),
+    ("details.strength", "Raises your power by 1 for good."),
+    (
+        "details.vitality",
+        "Raises your maximum HP by {0} for good.",
+    ),
+    ("details.agility", "Raises your defense by 1 for good."),
+    (
+        "describe.potion of strength",
+        "Thick and red. It smells of iron.",
+    ),
+    (
+        "describe.potion of vitality",
+        "It sparkles green in the light.",
+    ),
+    (
+        "describe.potion of agility",
+        "Thin and pale blue, it's gone in one gulp.",
+    ),
+    (
+        "spell.teleport.cast",
+        "The world twists around you. You're somewhere else!",
+    ),
+    (
+        "spell.teleport.failed",
+        "The scroll flickers, but nothing happens.",
+    ),
+    (
+        "describe.scroll of teleportation",
+        "Takes you somewhere else on the level. Anywhere.",
+    ),
+    ("effect.hasted.start", "{0} speeds up!"),
+    ("effect.hasted.end", "{0} slows back down."),
+    ("effect.slowed.start", "{0} slows down!"),
+    ("effect.slowed.end", "{0} is no longer slowed."),
+    (
+        "spell.slow.prompt",
+        "Left-click an enemy to slow it, or right-click to cancel.",
+    ),
+    ("describe.scroll of haste", "Makes you faster for a while."),
+    (
+        "describe.scroll of slow",
+        "Makes an enemy slower for a while.",
+    ),
+    ("light.torch_died", "Your torch sputters and dies!"),
+    (
+        "light.carried",
+        "Your {0} lights the way as long as you carry it.",
+    ),
+    ("details.light", "Lets you see {0} tiles around you."),
+    (
+        "describe.torch",
+        "A stick with a rag soaked in pitch. It won't burn forever.",
+    ),
+    (
+        "describe.lantern",
+        "A brass lantern. It lights more than a torch, and for good.",
+    ),
+    ("effect.invisible.start", "{0} fades from sight!"),
+    ("effect.invisible.end", "{0} is visible again."),
+    (
+        "details.invisibility",
+        "Makes you invisible for {0} turns, or until you attack.",
+    ),
+    (
+        "describe.potion of invisibility",
+        "Clear as water. You can barely see the flask.",
+    ),
+    ("corpse.eat", "You force down the {0}. Ugh."),
+    ("corpse.rotted", "Something in your pack has rotted away."),
+    ("corpse.burned", "The {0} burn to ashes."),
+    ("butcher.done", "You cut up the {0}."),
+    ("butcher.meat", "{0} meat"),
+    ("menu.choose_class", "Choose your class:\n"),
+    ("class.warrior", "Warrior"),
+    ("class.rogue", "Rogue"),
+    ("class.mage", "Mage"),
+    (
+        "class.warrior.choice",
+        "Warrior: tough and strong, with a sword and a shield",
+    ),
+    (
+        "class.rogue.choice",
+        "Rogue: hard to hit, strikes true, throws knives",
+    ),
+    (
+        "class.mage.choice",
+        "Mage: frail, but knows magic missile from the start",
+    ),
+    ("level_up.mana", "Magic (+10 mana, from {0} to {1})"),
+    (
+        "throw.prompt",
+        "Left-click an enemy to throw the knife at it, or right-click to cancel.",
+    ),
+    ("throw.miss", "The knife misses the {0}."),
+    ("throw.hit", "The knife hits the {0} for {1} hit points."),
+    ("throw.no_effect", "The knife bounces off the {0}."),
+    ("cause.knife", "a throwing knife"),
+    (
+        "describe.throwing knife",
+        "Balanced for throwing. Use it to throw it.",
+    ),
+    ("menu.enter_name", "What is your name, adventurer?\n"),
+    ("menu.choose_difficulty", "Choose the difficulty:\n"),
+    ("difficulty.easy", "Easy"),
+    ("difficulty.normal", "Normal"),
+    ("difficulty.hard", "Hard"),
+    (
+        "difficulty.easy.choice",
+        "Easy: fewer monsters and more health",
+    ),
+    (
+        "difficulty.normal.choice",
+        "Normal: the dungeon as it's meant to be",
+    ),
+    (
+        "difficulty.hard.choice",
+        "Hard: more and tougher monsters, no rest between levels",
+    ),
+    ("panel.turns", "T:{0}"),
+    ("pause.header", "Paused\n"),
+    ("pause.resume", "Resume"),
+    ("pause.save_and_quit", "Save and quit to the main menu"),
+    ("pause.quit_without_saving", "Quit without saving"),
+    (
+        "pause.confirm_quit",
+        "Quit without saving? Everything since the last save will be lost.\n",
+    ),
+    ("menu.options", "Options"),
+    ("pause.options", "Options"),
+    ("options.header", "Options\n"),
+    ("options.on", "on"),
+    ("options.off", "off"),
+    ("options.fullscreen", "Fullscreen: {0}"),
+    ("options.fps", "Frame rate limit: {0} FPS"),
+    ("options.font", "Font: {0}"),
+    ("options.colored_messages", "Colored messages: {0}"),
+    (
+        "options.restart_note",
+        "The new font will be used after a restart.\n",
+    ),
+    ("help.title", "Help"),
+    ("help.scroll", "PageUp/PageDown to scroll"),
+    ("help.unbound", "(not bound)"),
+    ("help.movement", "Movement"),
+    ("help.items", "Items and spells"),
+    ("help.information", "Information"),
+    ("help.system", "System"),
+    ("help.command.MoveN", "Move or attack north"),
+    ("help.command.MoveNE", "Move or attack north-east"),
+    ("help.command.MoveE", "Move or attack east"),
+    ("help.command.MoveSE", "Move or attack south-east"),
+    ("help.command.MoveS", "Move or attack south"),
+    ("help.command.MoveSW", "Move or attack south-west"),
+    ("help.command.MoveW", "Move or attack west"),
+    ("help.command.MoveNW", "Move or attack north-west"),
+    ("help.command.Wait", "Wait a turn"),
+    ("help.command.Descend", "Go down the stairs"),
+    ("help.command.PickUp", "See what's here and take it"),
+    ("help.command.Inventory", "Use an item"),
+    ("help.command.Drop", "Drop an item"),
+    ("help.command.Equipment", "Show the equipment"),
+    ("help.command.Cast", "Cast a spell"),
+    ("help.command.Fire", "Shoot the bow"),
+    ("help.command.Character", "Show the character"),
+    ("help.command.Look", "Look around"),
+    ("help.command.History", "Read the message history"),
+    ("help.command.HealthBars", "Show or hide monster health"),
+    ("help.command.Help", "Show this help"),
+    ("help.run", "Run in that direction"),
+    ("help.travel", "Walk to a spot on the map"),
+    ("help.fullscreen", "Toggle fullscreen"),
+    ("help.pause", "Pause menu"),
+    ("menu.last_save", "Last game: {0}, level {1}, dungeon level {2}, {3}"),
+    ("menu.delete_save", "Delete saved game"),
+    (
+        "menu.confirm_delete",
+        "Delete the game in slot {0}? This can't be undone.\n",
+    ),
+    (
+        "level_up.must_choose",
+        "You must choose before you can continue. Which stat to raise?\n",
+    ),
+    ("options.animations", "Animations: {0}"),
+    ("options.damage_flash", "Flash when hurt: {0}"),
+    ("options.low_hp_warning", "Low health warning: {0}"),
+    ("feeling.boss", "You feel a royal presence watching you."),
+    ("feeling.danger", "You hear distant growling..."),
+    ("feeling.treasure", "You sense great treasure here."),
+    ("feeling.shop", "You hear the clink of coins somewhere nearby."),
+    ("feeling.quiet", "It is eerily quiet."),
+    ("feeling.ordinary", "Nothing seems out of the ordinary here."),
+    ("ambient.drip", "Water drips somewhere in the dark."),
+    ("ambient.scurry", "Something small scurries through the walls."),
+    ("ambient.draft", "A cold draft brushes past you."),
+    ("ambient.chant", "Faint chanting echoes from far below."),
+    ("ambient.howl", "A howl rises and dies away."),
+    ("ambient.bones", "Bones rattle somewhere nearby."),
+    ("ambient.roar", "A deep roar shakes dust from the ceiling."),
+    ("ambient.footsteps", "Heavy footsteps echo, then stop."),
+    ("ambient.whisper", "Something whispers your name."),
+    ("help.command.Screenshot", "Save the screen to a text file"),
+    ("screenshot.saved", "Screenshot saved to {0}."),
+    ("screenshot.failed", "Could not save the screenshot: {0}"),
+    (
+        "wizard.keys",
+        "Wizard mode: F1 map, F2 heal, F3 spawn, F4 stairs, F5 level, F6 god mode.",
+    ),
+    ("wizard.spawn", "Spawn what?\n"),
+    ("wizard.which_level", "Go to which level?\n"),
+    ("wizard.no_room", "There's no room next to you."),
+    ("wizard.no_stairs", "There are no stairs on this level."),
+    ("wizard.god_mode_on", "You feel invulnerable."),
+    ("wizard.god_mode_off", "You feel mortal again."),
+    ("help.command.DebugOverlay", "Show or hide the debug overlay"),
+    ("debug.fps", "FPS: {0}"),
+    ("debug.objects", "Objects: {0}, actors: {1}"),
+    ("debug.position", "Player at {0}, {1}"),
+    ("debug.dungeon_level", "Dungeon level {0}"),
+    ("names.wall", "a wall"),
+    ("names.floor", "a floor"),
+    ("ai.confused_bump", "The {0} crashes into the wall!"),
+    (
+        "spell.enchant.uncursed",
+        "Your {0} glows with a pure white light. Its curse is gone.",
+    ),
+    ("game.save_failed", "Could not save the game: {0}"),
+    ("replay.over", "That's the end of the replay."),
+    ("morgue.mods", "Played with the mods: {0}."),
+    ("thief.followed", "The {0} is hiding somewhere on this level with your {1}."),
+    ("slot.left_hand", "left hand"),
+    ("slot.right_hand", "right hand"),
+    ("slot.head", "head"),
+    ("slot.body", "body"),
+    ("slot.left_ring", "left ring"),
+    ("slot.right_ring", "right ring"),
+    ("slot.back", "back"),
+    ("item.enchanted", "{0} +{1}"),
+];
+
+/// Translated user-facing text, looked up by message id.
+#[derive(Default)]
+struct Strings {
+    translations: HashMap<String, String>,
+}
+
+impl Strings {
+    /// Load the translations from `lang/<language>.json` and layer the
+    /// ones from `mods` on top.
+    pub fn load(language: &str, mods: &[PathBuf]) -> Self {
+        let file = Path::new("lang").join(format!("{}.json", language));
+        let mut translations = HashMap::new();
+        for (source, path) in data_layers(&file, mods) {
+            for (id, text) in read_overrides(&path, &source) {
+                match text {
+                    Some(text) => {
+                        translations.insert(id, text);
+                    }
+                    // `null` brings back the built-in text
+                    None => {
+                        translations.remove(&id);
+                    }
+                }
+            }
+        }
+        Strings { translations }
+    }
+
+    /// Return the translated text for `key`, or the built-in English one.
+    pub fn get<'a>(&'a self, key: &'a str) -> &'a str {
+        if let Some(text) = self.translations.get(key) {
+            return text;
+        }
+        DEFAULT_STRINGS
+            .iter()
+            .find(|&&(id, _)| id == key)
+            .map_or(key, |&(_, text)| text)
+    }
+
+    /// Look up `key` and replace the `{0}`, `{1}`, ... placeholders with `args`.
+    pub fn format(&self, key: &str, args: &[&dyn Display]) -> String {
+        let mut text = self.get(key).to_string();
+        for (index, arg) in args.iter().enumerate() {
+            text = text.replace(&format!("{{{}}}", index), &arg.to_string());
+        }
+        text
+    }
+}
+
+/// Mod directories to layer over the built-in data, in load order: the
+/// subdirectories of `mods/` sorted by name, then any `--mods <dir>`
+/// passed on the command line.
+fn active_mods() -> Vec<PathBuf> {
+    let mut mods: Vec<PathBuf> = fs::read_dir("mods")
+        .map(|entries| {
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .filter(|path| path.is_dir())
+                .collect()
+        })
+        .unwrap_or_default();
+    mods.sort();
+    let args: Vec<String> = std::env::args().collect();
+    for pair in args.windows(2) {
+        if pair[0] == "--mods" {
+            mods.push(PathBuf::from(&pair[1]));
+        }
+    }
+    mods
+}
+
+/// The name we show for a mod: the name of its directory.
+fn mod_name(path: &Path) -> String {
+    path.file_name().map_or_else(
+        || path.display().to_string(),
+        |name| name.to_string_lossy().into_owned(),
+    )
+}
+
+/// Read a JSON file mapping ids to values. A missing file gives an
+/// empty map, a broken one is reported (naming `source`) and ignored.
+fn read_overrides(path: &Path, source: &str) -> HashMap<String, Option<String>> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(_) => return HashMap::new(),
+    };
+    serde_json::from_str(&json).unwrap_or_else(|e| {
+        eprintln!("Warning: {}: can't load {}: {}", source, path.display(), e);
+        HashMap::new()
+    })
+}
+
+/// Where to look for a data file: the game's own copy first, then the
+/// one in each of the `mods`, in load order. Each comes with a name to
+/// use in messages.
+fn data_layers(file: &Path, mods: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let mut layers = vec![("base game".to_string(), file.to_path_buf())];
+    for dir in mods {
+        layers.push((format!("mod {}", mod_name(dir)), dir.join(file)));
+    }
+    layers
+}
+
+/// Read one layer of a data file. A missing file gives `None`, a broken
+/// one an error saying whose file it is.
+fn read_layer<T: serde::de::DeserializeOwned>(
+    source: &str,
+    path: &Path,
+) -> Result<Option<T>, String> {
+    let json = match fs::read_to_string(path) {
+        Ok(json) => json,
+        Err(_) => return Ok(None),
+    };
+    serde_json::from_str(&json)
+        .map(Some)
+        .map_err(|e| format!("{} ({}): {}", source, path.display(), e))
+}
+
+/// Player preferences that persist between games.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    language: String,
+    /// Delete the saved game when the player dies.
+    permadeath: bool,
+    /// Tint the monsters' tiles by their health.
+    show_health_bars: bool,
+    /// Start the game in fullscreen. Alt+Enter changes it, too.
+    fullscreen: bool,
+    /// Frames per second, or `None` to use `limit_fps` from the config.
+    limit_fps: Option<i32>,
+    /// One of `FONTS`. It only takes effect after a restart.
+    font: String,
+    /// Show each message in its own color rather than all in white.
+    colored_messages: bool,
+    /// Play short animations for spells and hits.
+    animations: bool,
+    /// Flash the screen edge when the player gets hurt.
+    damage_flash: bool,
+    /// Frame the screen in red while the player is nearly dead.
+    low_hp_warning: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            language: "en".into(),
+            permadeath: true,
+            show_health_bars: true,
+            fullscreen: false,
+            limit_fps: None,
+            font: FONTS[0].into(),
+            colored_messages: true,
+            animations: true,
+            damage_flash: true,
+            low_hp_warning: true,
+        }
+    }
+}
+
+impl Settings {
+    /// Read `settings.json` from the data directory, falling back to the
+    /// one older versions left in the current directory and then to the
+    /// defaults if it's missing or broken.
+    pub fn load(paths: &Paths) -> Self {
+        fs::read_to_string(paths.settings())
+            .or_else(|_| fs::read_to_string("settings.json"))
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    /// Write the settings back to `settings.json` in the data directory.
+    /// They're not worth interrupting the game over, so any errors are
+    /// ignored.
+    pub fn save(&self, paths: &Paths) {
+        if let Ok(json) = serde_json::to_string_pretty(self) {
+            let _ = fs::write(paths.settings(), json);
+        }
+    }
+
+    /// The color to show a message in.
+    pub fn message_color(&self, color: Color) -> Color {
+        if self.colored_messages {
+            color
+        } else {
+            WHITE
+        }
+    }
+}
+
+/// Game balance and other numbers worth tweaking without a recompile.
+/// Read from `config.json`; anything missing there keeps its default.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct GameConfig {
+    // dungeon generator
+    room_max_size: i32,
+    room_min_size: i32,
+    max_rooms: i32,
+    // spells
+    heal_amount: i32,
+    lightning_damage: i32,
+    lightning_range: i32,
+    confuse_range: i32,
+    confuse_num_turns: i32,
+    fireball_radius: i32,
+    fireball_damage: i32,
+    // experience and level-ups
+    level_up_base: i32,
+    level_up_factor: i32,
+    // natural regeneration
+    regen_interval: i32,
+    regen_delay: i32,
+    mana_regen_interval: i32,
+    /// Whether the player needs to eat.
+    hunger: bool,
+    // field of view
+    torch_radius: i32,
+    lantern_radius: i32,
+    /// How far the player sees without a light.
+    dark_radius: i32,
+    fov_light_walls: bool,
+    limit_fps: i32,
+    /// The monsters and items, read from `spawns.json`.
+    #[serde(skip)]
+    spawns: SpawnTables,
+    /// What the monsters do, with the changes from `ai.json`.
+    #[serde(skip)]
+    ai: AiRegistry,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            room_max_size: 10,
+            room_min_size: 6,
+            max_rooms: 30,
+            heal_amount: 40,
+            lightning_damage: 40,
+            lightning_range: 5,
+            confuse_range: 8,
+            confuse_num_turns: 10,
+            fireball_radius: 3,
+            fireball_damage: 25,
+            level_up_base: 200,
+            level_up_factor: 150,
+            regen_interval: 10,
+            regen_delay: 10,
+            mana_regen_interval: 5,
+            hunger: true,
+            torch_radius: 10,
+            lantern_radius: 12,
+            dark_radius: 4,
+            fov_light_walls: true,
+            limit_fps: 20, // 20 frames-per-second maximum
+            spawns: SpawnTables::default(),
+            ai: AiRegistry::default(),
+        }
+    }
+}
+
+impl GameConfig {
+    /// Read `config.json`, then the `mods`' changes to it: a value a mod
+    /// sets replaces the one before it.
+    fn load(mods: &[PathBuf]) -> Result<Self, String> {
+        let mut values = serde_json::Map::new();
+        let mut files = vec![];
+        for (source, path) in data_layers(Path::new("config.json"), mods) {
+            let layer: serde_json::Map<String, serde_json::Value> =
+                match read_layer(&source, &path)? {
+                    Some(layer) => layer,
+                    None => continue,
+                };
+            let file = format!("{} ({})", source, path.display());
+            // catch a value of the wrong type while we know whose it is
+            serde_json::from_value::<GameConfig>(layer.clone().into())
+                .map_err(|e| format!("{}: {}", file, e))?;
+            values.extend(layer);
+            files.push(file);
+        }
+        let mut config: GameConfig =
+            serde_json::from_value(values.into()).map_err(|e| format!("config.json: {}", e))?;
+        config
+            .validate()
+            .map_err(|e| format!("{}:\n{}", files.join(", "), e))?;
+        config.spawns = SpawnTables::load(mods)?;
+        config.ai = AiRegistry::load(mods)?;
+        Ok(config)
+    }
+
+    /// Check the values make sense, so we don't crash later on.
+    fn validate(&self) -> Result<(), String> {
+        let mut problems = vec![];
+        if self.room_min_size < 3 {
+            problems.push("room_min_size must be at least 3".to_string());
+        }
+        if self.room_max_size < self.room_min_size {
+            problems.push("room_max_size can't be smaller than room_min_size".to_string());
+        }
+        if self.room_max_size >= cmp::min(MAP_WIDTH, MAP_HEIGHT) {
+            problems.push(format!(
+                "room_max_size must be smaller than the map ({}x{})",
+                MAP_WIDTH, MAP_HEIGHT
+            ));
+        }
+        if self.max_rooms < 1 {
+            problems.push("max_rooms must be at least 1".to_string());
+        }
+        let non_negative = [
+            ("heal_amount", self.heal_amount),
+            ("lightning_damage", self.lightning_damage),
+            ("lightning_range", self.lightning_range),
+            ("confuse_range", self.confuse_range),
+            ("confuse_num_turns", self.confuse_num_turns),
+            ("fireball_radius", self.fireball_radius),
+            ("fireball_damage", self.fireball_damage),
+            ("level_up_factor", self.level_up_factor),
+            ("regen_delay", self.regen_delay),
+            ("torch_radius", self.torch_radius),
+            ("lantern_radius", self.lantern_radius),
+            ("dark_radius", self.dark_radius),
+            ("limit_fps", self.limit_fps),
+        ];
+        for &(name, value) in non_negative.iter() {
+            if value < 0 {
+                problems.push(format!("{} can't be negative", name));
+            }
+        }
+        if self.level_up_base < 1 {
+            problems.push("level_up_base must be at least 1".to_string());
+        }
+        if self.regen_interval < 1 {
+            problems.push("regen_interval must be at least 1".to_string());
+        }
+        if self.mana_regen_interval < 1 {
+            problems.push("mana_regen_interval must be at least 1".to_string());
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+}
+
+/// Everything the player can bind a key to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Command {
+    MoveN,
+    MoveNE,
+    MoveE,
+    MoveSE,
+    MoveS,
+    MoveSW,
+    MoveW,
+    MoveNW,
+    Wait,
+    PickUp,
+    Inventory,
+    Drop,
+    Character,
+    Descend,
+    Look,
+    History,
+    HealthBars,
+    Equipment,
+    Cast,
+    Fire,
+    Help,
+    Screenshot,
+    DebugOverlay,
+}
+
+impl Command {
+    const ALL: [Command; 23] = [
+        Command::MoveN,
+        Command::MoveNE,
+        Command::MoveE,
+        Command::MoveSE,
+        Command::MoveS,
+        Command::MoveSW,
+        Command::MoveW,
+        Command::MoveNW,
+        Command::Wait,
+        Command::PickUp,
+        Command::Inventory,
+        Command::Drop,
+        Command::Character,
+        Command::Descend,
+        Command::Look,
+        Command::History,
+        Command::HealthBars,
+        Command::Equipment,
+        Command::Cast,
+        Command::Fire,
+        Command::Help,
+        Command::Screenshot,
+        Command::DebugOverlay,
+    ];
+
+    fn from_name(name: &str) -> Option<Command> {
+        Command::ALL
+            .iter()
+            .cloned()
+            .find(|command| format!("{:?}", command) == name)
+    }
+}
+
+/// A key the player pressed: either a special key or a typed character.
+#[derive(Clone, Debug, PartialEq)]
+enum Binding {
+    Code(tcod::input::KeyCode),
+    Text(String),
+}
+
+/// The special keys that can be bound by their name.
+fn named_keys() -> &'static [tcod::input::KeyCode] {
+    use tcod::input::KeyCode::*;
+    &[
+        Up,
+        Down,
+        Left,
+        Right,
+        Home,
+        End,
+        PageUp,
+        PageDown,
+        Insert,
+        Delete,
+        Enter,
+        Spacebar,
+        Tab,
+        Backspace,
+        NumPad0,
+        NumPad1,
+        NumPad2,
+        NumPad3,
+        NumPad4,
+        NumPad5,
+        NumPad6,
+        NumPad7,
+        NumPad8,
+        NumPad9,
+        NumPadAdd,
+        NumPadSubtract,
+        NumPadDivide,
+        NumPadMultiply,
+        NumPadDecimal,
+        NumPadEnter,
+        F1,
+        F2,
+        F3,
+        F4,
+        F5,
+        F6,
+        F7,
+        F8,
+        F9,
+        F10,
+        F11,
+        F12,
+    ]
+}
+
+impl Binding {
+    /// `"Up"`, `"NumPad8"` or a single character like `"g"`.
+    fn from_name(name: &str) -> Option<Binding> {
+        if name.chars().count() == 1 {
+            return Some(Binding::Text(name.to_string()));
+        }
+        named_keys()
+            .iter()
+            .find(|code| format!("{:?}", code) == name)
+            .map(|&code| Binding::Code(code))
+    }
+
+    fn matches(&self, key: Key) -> bool {
+        match self {
+            Binding::Code(code) => key.code == *code,
+            Binding::Text(text) => key.code == tcod::input::KeyCode::Text && key.text() == text,
+        }
+    }
+}
+
+impl Display for Binding {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Binding::Code(code) => write!(f, "{:?}", code),
+            Binding::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+/// Which keys trigger which command.
+struct KeyMap {
+    bindings: Vec<(Command, Vec<Binding>)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        use tcod::input::KeyCode::*;
+        let code = Binding::Code;
+        let text = |text: &str| Binding::Text(text.to_string());
+
+        KeyMap {
+            bindings: vec![
+                (Command::MoveN, vec![code(Up), code(NumPad8)]),
+                (Command::MoveNE, vec![code(PageUp), code(NumPad9)]),
+                (Command::MoveE, vec![code(Right), code(NumPad6)]),
+                (Command::MoveSE, vec![code(PageDown), code(NumPad3)]),
+                (Command::MoveS, vec![code(Down), code(NumPad2)]),
+                (Command::MoveSW, vec![code(End), code(NumPad1)]),
+                (Command::MoveW, vec![code(Left), code(NumPad4)]),
+                (Command::MoveNW, vec![code(Home), code(NumPad7)]),
+                (Command::Wait, vec![code(NumPad5)]),
+                (Command::PickUp, vec![text("g")]),
+                (Command::Inventory, vec![text("i")]),
+                (Command::Drop, vec![text("d")]),
+                (Command::Character, vec![text("c")]),
+                (Command::Descend, vec![text("<")]),
+                (Command::Look, vec![text("x")]),
+                (Command::History, vec![text("P")]),
+                (Command::HealthBars, vec![text("H")]),
+                (Command::Equipment, vec![text("e")]),
+                (Command::Cast, vec![text("m")]),
+                (Command::Fire, vec![text("f")]),
+                (Command::Help, vec![text("?")]),
+                (Command::Screenshot, vec![code(F12)]),
+                (Command::DebugOverlay, vec![code(F10)]),
+            ],
+        }
+    }
+}
+
+impl KeyMap {
+    /// Read `keys.json`, then the `mods`' changes to it: a command a
+    /// later file binds loses the keys an earlier one gave it.
+    fn load(mods: &[PathBuf]) -> Result<Self, String> {
+        let mut keymap = KeyMap::default();
+        for (source, path) in data_layers(Path::new("keys.json"), mods) {
+            let layer: HashMap<String, Vec<String>> = match read_layer(&source, &path)? {
+                Some(layer) => layer,
+                None => continue,
+            };
+            let file = format!("{} ({})", source, path.display());
+            for (name, keys) in layer {
+                let command = match Command::from_name(&name) {
+                    Some(command) => command,
+                    None => {
+                        eprintln!("{}: unknown command `{}`, ignoring it", file, name);
+                        continue;
+                    }
+                };
+                let mut bindings = vec![];
+                for key in keys {
+                    match Binding::from_name(&key) {
+                        Some(binding) => bindings.push(binding),
+                        None => {
+                            eprintln!("{}: unknown key `{}` for {}, ignoring it", file, key, name)
+                        }
+                    }
+                }
+                keymap.set(command, bindings);
+            }
+        }
+
+        keymap.check_conflicts()?;
+        Ok(keymap)
+    }
+
+    fn set(&mut self, command: Command, keys: Vec<Binding>) {
+        if let Some(entry) = self.bindings.iter_mut().find(|(c, _)| *c == command) {
+            entry.1 = keys;
+        }
+    }
+
+    /// Every key can only do one thing.
+    fn check_conflicts(&self) -> Result<(), String> {
+        let escape = Binding::Code(tcod::input::KeyCode::Escape);
+        let mut problems = vec![];
+        for (i, (command, keys)) in self.bindings.iter().enumerate() {
+            for key in keys {
+                if *key == escape {
+                    problems.push(format!(
+                        "{} is reserved, it can't be bound to {:?}",
+                        key, command
+                    ));
+                }
+                for (other, other_keys) in &self.bindings[i + 1..] {
+                    if other_keys.contains(key) {
+                        problems.push(format!(
+                            "{} is bound to both {:?} and {:?}",
+                            key, command, other
+                        ));
+                    }
+                }
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems.join("\n"))
+        }
+    }
+
+    /// The command bound to this key, if any.
+    fn command_for(&self, key: Key) -> Option<Command> {
+        self.bindings
+            .iter()
+            .find(|(_, keys)| keys.iter().any(|binding| binding.matches(key)))
+            .map(|&(command, _)| command)
+    }
+}
+
+impl Command {
+    /// The direction of a movement command.
+    fn direction(self) -> Option<(i32, i32)> {
+        match self {
+            Command::MoveN => Some((0, -1)),
+            Command::MoveNE => Some((1, -1)),
+            Command::MoveE => Some((1, 0)),
+            Command::MoveSE => Some((1, 1)),
+            Command::MoveS => Some((0, 1)),
+            Command::MoveSW => Some((-1, 1)),
+            Command::MoveW => Some((-1, 0)),
+            Command::MoveNW => Some((-1, -1)),
+            _ => None,
+        }
+    }
+}
+
+impl Command {
+    /// The heading this command is listed under on the help screen.
+    fn help_category(self) -> &'static str {
+        use Command::*;
+        match self {
+            MoveN | MoveNE | MoveE | MoveSE | MoveS | MoveSW | MoveW | MoveNW | Wait | Descend => {
+                "help.movement"
+            }
+            PickUp | Inventory | Drop | Equipment | Cast | Fire => "help.items",
+            Character | Look | History | HealthBars | Help => "help.information",
+            Screenshot | DebugOverlay => "help.system",
+        }
+    }
+}
+
+/// What the debug overlay shows, if anything.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum DebugOverlay {
+    Off,
+    /// The frame rate and a few numbers about the game.
+    Stats,
+    /// The stats, and which tiles the FOV map can see through and walk on.
+    Fov,
+}
+
+impl DebugOverlay {
+    fn next(self) -> Self {
+        match self {
+            DebugOverlay::Off => DebugOverlay::Stats,
+            DebugOverlay::Stats => DebugOverlay::Fov,
+            DebugOverlay::Fov => DebugOverlay::Off,
+        }
+    }
+}
+
+/// When the latest frames were shown, to measure the real frame rate.
+struct FrameClock {
+    stamps: std::collections::VecDeque<Instant>,
+}
+
+impl FrameClock {
+    fn new() -> Self {
+        FrameClock {
+            stamps: std::collections::VecDeque::with_capacity(FRAME_CLOCK_SIZE),
+        }
+    }
+
+    fn tick(&mut self) {
+        if self.stamps.len() == FRAME_CLOCK_SIZE {
+            self.stamps.pop_front();
+        }
+        self.stamps.push_back(Instant::now());
+    }
+
+    /// Frames per second over the remembered frames.
+    fn fps(&self) -> f32 {
+        match (self.stamps.front(), self.stamps.back()) {
+            (Some(first), Some(last)) if last > first => {
+                let seconds = last.duration_since(*first).as_secs_f32();
+                (self.stamps.len() - 1) as f32 / seconds
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+struct Tcod {
+    root: Root,
+    con: Offscreen,
+    panel: Offscreen,
+    fov: FovMap,
+    key: Key,
+    mouse: Mouse,
+    settings: Settings,
+    paths: Paths,
+    config: GameConfig,
+    keymap: KeyMap,
+    targeting: Option<Targeting>,
+    /// Scratch space for `render_all`.
+    draw_order: Vec<usize>,
+    /// The background of every tile, kept between frames.
+    map_layer: Offscreen,
+    /// The color of each tile on `map_layer`, indexed by `y * MAP_WIDTH + x`.
+    map_colors: Vec<Option<Color>>,
+    /// Started with `--wizard`: new games get the cheat keys.
+    wizard: bool,
+    debug_overlay: DebugOverlay,
+    frame_clock: FrameClock,
+    /// The objects we've already warned about, so a broken one doesn't
+    /// print the same warning every turn.
+    warned: HashSet<ObjectId>,
+    /// Where the player's decisions are written down, or read back from.
+    replay: Replay,
+}
+
+impl Tcod {
+    /// Is the position in the player's field of view? Unlike
+    /// `fov.is_in_fov`, this is fine to call with positions outside the map.
+    fn in_fov(&self, x: i32, y: i32) -> bool {
+        in_fov_map(&self.fov, x, y)
+    }
+}
+
+/// The map tile shown at a cell of the root console, or `None` if the
+/// cell isn't showing the map (the panel, say). Everything that turns a
+/// mouse position into a map position goes through here, so it's the
+/// one place to change if the map ever moves on the screen.
+fn screen_to_map(x: i32, y: i32) -> Option<(i32, i32)> {
+    // `con` is blitted to the top left corner of `root`, at 1:1 scale
+    if in_map(x, y) {
+        Some((x, y))
+    } else {
+        None
+    }
+}
+
+fn in_fov_map(fov: &FovMap, x: i32, y: i32) -> bool {
+    in_map(x, y) && fov.is_in_fov(x, y)
+}
+
+/// A line in the message log.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+struct Message {
+    text: String,
+    color: Color,
+    /// How many more times the same message came right after it.
+    #[serde(default)]
+    repeats: u32,
+}
+
+impl Display for Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}", self.text)?;
+        if self.repeats > 0 {
+            write!(f, " (x{})", self.repeats + 1)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Messages {
+    messages: Vec<Message>,
+}
+
+impl Messages {
+    pub fn new() -> Self {
+        Self { messages: vec![] }
+    }
+
+    /// add the new message with its color, or count it again if it's the
+    /// same as the last one
+    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
+        let text = message.into();
+        if let Some(last) = self.messages.last_mut() {
+            if last.text == text && last.color == color {
+                last.repeats += 1;
+                return;
+            }
+        }
+        self.messages.push(Message {
+            text,
+            color,
+            repeats: 0,
+        });
+        // forget the oldest ones, so a long game doesn't eat all the memory
+        if self.messages.len() > MESSAGE_HISTORY {
+            let excess = self.messages.len() - MESSAGE_HISTORY;
+            self.messages.drain(..excess);
+        }
+    }
+
+    /// Create a `DoubleEndedIterator` over the messages
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Message> {
+        self.messages.iter()
+    }
+
+    /// The latest message, if there is one.
+    pub fn last(&self) -> Option<&Message> {
+        self.messages.last()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Game {
+    map: Map,
+    messages: Messages,
+    inventory: Vec<Object>,
+    dungeon_level: u32,
+    #[serde(skip)]
+    strings: Strings,
+    /// Objects created during this turn, waiting to be added to the map.
+    #[serde(default)]
+    pending_spawns: Vec<Object>,
+    /// The mods that were active when this game was started.
+    #[serde(default)]
+    mods: Vec<String>,
+    #[serde(default)]
+    boss_defeated: bool,
+    /// The ID the next new object will get.
+    #[serde(default)]
+    next_object_id: ObjectId,
+    #[serde(skip)]
+    save_slot: usize,
+    /// What dealt the last damage, for the morgue file.
+    #[serde(default)]
+    last_damage_source: String,
+    #[serde(default)]
+    turns: u32,
+    /// The direction the player is running in, if they are.
+    #[serde(skip)]
+    pending_run: Option<(i32, i32)>,
+    #[serde(skip)]
+    run_snapshot: RunSnapshot,
+    #[serde(default)]
+    fires: Vec<Fire>,
+    /// The turn the player last took damage, which holds off regeneration.
+    #[serde(default)]
+    last_hurt_turn: u32,
+    #[serde(default)]
+    gold: i32,
+    /// Turns since the player last ate (more or less, food only takes off
+    /// so much).
+    #[serde(default)]
+    hunger: i32,
+    #[serde(default)]
+    spells: Vec<Spell>,
+    #[serde(default)]
+    class: Class,
+    /// The name the player picked for their character.
+    #[serde(default = "default_player_name")]
+    player_name: String,
+    #[serde(default)]
+    difficulty: Difficulty,
+    /// The IDs of the objects with an AI, in the order they act. This is
+    /// rebuilt when a game starts, so it isn't saved.
+    #[serde(skip)]
+    actors: Vec<ObjectId>,
+    /// Where melee attacks hit since the last time they were shown.
+    #[serde(skip)]
+    hits: Vec<(i32, i32)>,
+    /// Set when the player loses health, until it's been shown.
+    #[serde(skip)]
+    player_hurt: bool,
+    /// The turn on which the next ambient message is heard.
+    #[serde(default)]
+    next_ambient: u32,
+    /// The cheat keys work in this game. It can't get into the hall of
+    /// heroes.
+    #[serde(default)]
+    wizard: bool,
+    /// The player can't get hurt.
+    #[serde(default)]
+    god_mode: bool,
+    /// Where the player is travelling to. The next step is the last one.
+    #[serde(skip)]
+    pending_path: Vec<(i32, i32)>,
+    /// Where all of the game's luck comes from. Seeded with the same number,
+    /// it makes the same things happen, which is what the tests need. It's
+    /// not saved: a loaded game gets a fresh one.
+    #[serde(skip, default = "random_rng")]
+    rng: StdRng,
+    /// Thieves that got away down the stairs with something of the
+    /// player's. They turn up again on the next level.
+    #[serde(default)]
+    fled: Vec<Object>,
+}
+
+/// A random number generator that gives the same numbers for the same
+/// seed.
+fn seeded_rng(seed: usize) -> StdRng {
+    StdRng::from_seed(&[seed][..])
+}
+
+/// A random number generator with a seed nobody can guess.
+fn random_rng() -> StdRng {
+    seeded_rng(rand::random())
+}
+
+/// Print the problems `validate` found, but only the first one for each
+/// object.
+fn warn_once(problems: Vec<(ObjectId, String)>, warned: &mut HashSet<ObjectId>) {
+    for (id, problem) in problems {
+        if warned.insert(id) {
+            eprintln!("Warning: {}.", problem);
+        }
+    }
+}
+
+impl Game {
+    /// Check the objects for the invariants the rest of the code relies
+    /// on. Returns the problems it finds, with the ID of the object each
+    /// one is about. Nothing gets changed: it's up to the caller what to
+    /// do about them.
+    fn validate(&self, objects: &[Object]) -> Vec<(ObjectId, String)> {
+        let mut problems = vec![];
+        match objects.get(PLAYER) {
+            Some(player) if player.fighter.is_some() => {}
+            _ => problems.push((0, "the player is missing or can't fight".to_string())),
+        }
+        for object in objects {
+            if !in_map(object.x, object.y) {
+                problems.push((
+                    object.id,
+                    format!(
+                        "{} is outside the map at ({}, {})",
+                        object.name, object.x, object.y
+                    ),
+                ));
+                continue;
+            }
+            if object.blocks && self.map[(object.x, object.y)].blocked {
+                problems.push((
+                    object.id,
+                    format!(
+                        "{} is stuck inside a wall at ({}, {})",
+                        object.name, object.x, object.y
+                    ),
+                ));
+            }
+        }
+        problems
+    }
+
+    /// Give every object (other than the player) that doesn't have an ID
+    /// yet a fresh one. The new ones that act become actors.
+    fn assign_ids(&mut self, objects: &mut [Object]) {
+        // games saved before we had IDs start counting at 0
+        let highest = objects.iter().map(|object| object.id).max().unwrap_or(0);
+        self.next_object_id = cmp::max(self.next_object_id, highest + 1);
+        for object in objects.iter_mut().skip(1) {
+            if object.id == 0 {
+                object.id = self.next_object_id;
+                self.next_object_id += 1;
+                if object.ai.is_some() {
+                    self.actors.push(object.id);
+                }
+            }
+        }
+    }
+
+    /// Start giving the object with this ID its turns.
+    fn add_actor(&mut self, id: ObjectId) {
+        if !self.actors.contains(&id) {
+            self.actors.push(id);
+        }
+    }
+
+    /// Build the list of actors from scratch, e.g. for a loaded game.
+    fn find_actors(&mut self, objects: &mut [Object]) {
+        self.assign_ids(objects);
+        self.actors = objects
+            .iter()
+            .filter(|object| object.ai.is_some())
+            .map(|object| object.id)
+            .collect();
+    }
+}
+
+/// This is a generic object: the player, a monster, an item, the stairs...
+/// It's always represented by a character on screen.
+#[derive(Debug, Serialize, Deserialize)]
+struct Object {
+    x: i32,
+    y: i32,
+    char: char,
+    color: Color,
+    name: String,
+    blocks: bool,
+    alive: bool,
+    fighter: Option<Fighter>,
+    ai: Option<Ai>,
+    item: Option<Item>,
+    equipment: Option<Equipment>,
+    always_visible: bool,
+    level: i32,
+    /// Cosmetic objects that shouldn't outlive the current session.
+    /// They are left out of save games.
+    #[serde(default)]
+    transient: bool,
+    /// An item this object is carrying around (e.g. stolen loot).
+    #[serde(default)]
+    carried: Option<Box<Object>>,
+    #[serde(default)]
+    faction: Faction,
+    #[serde(default)]
+    id: ObjectId,
+    /// How many items this is, when identical ones are stacked together.
+    #[serde(default = "default_count")]
+    count: u32,
+    /// Uses left in a wand.
+    #[serde(default)]
+    charges: Option<u32>,
+    /// How many times a scroll of enchantment was used on this.
+    #[serde(default)]
+    enchantment: u32,
+    /// The name before any enchantment, e.g. "sword" for "sword +2".
+    #[serde(default)]
+    unenchanted_name: Option<String>,
+    #[serde(default)]
+    effects: Vec<StatusEffect>,
+    /// What this inflicts on anyone it hits.
+    #[serde(default)]
+    on_hit: Option<StatusEffect>,
+    /// What this inflicts on anyone it hits critically.
+    #[serde(default)]
+    on_critical: Option<StatusEffect>,
+    /// How close this is to getting its next action.
+    #[serde(default = "default_energy")]
+    energy: i32,
+    #[serde(default)]
+    tier: u32,
+    /// How much gold this is, if it's a pile of gold.
+    #[serde(default)]
+    gold: u32,
+    /// The items a shopkeeper has for sale.
+    #[serde(default)]
+    stock: Vec<Object>,
+    /// The items a monster drops when it dies.
+    #[serde(default)]
+    loot: Vec<Object>,
+    /// The things inside, if this is a container.
+    #[serde(default)]
+    contents: Option<Vec<Object>>,
+    /// The turn this rots away on, if it ever does.
+    #[serde(default)]
+    rots_at: Option<u32>,
+    /// Set only on the player's object.
+    #[serde(default)]
+    is_player: bool,
+    #[serde(default)]
+    render_order: RenderOrder,
+}
+
+fn default_count() -> u32 {
+    1
+}
+
+impl Object {
+    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
+        Object {
+            x,
+            y,
+            char,
+            color,
+            name: name.into(),
+            blocks,
+            alive: false,
+            fighter: None,
+            ai: None,
+            item: None,
+            equipment: None,
+            always_visible: false,
+            level: 1,
+            transient: false,
+            carried: None,
+            faction: Faction::Dungeon,
+            id: 0,
+            count: 1,
+            charges: None,
+            enchantment: 0,
+            unenchanted_name: None,
+            effects: vec![],
+            on_hit: None,
+            on_critical: None,
+            energy: 0,
+            tier: 0,
+            gold: 0,
+            stock: vec![],
+            loot: vec![],
+            contents: None,
+            rots_at: None,
+            is_player: false,
+            render_order: if blocks {
+                RenderOrder::Actor
+            } else {
+                RenderOrder::Item
+            },
+        }
+    }
+
+    /// set the color and then draw the character that represents this object at its position
+    pub fn draw(&self, con: &mut dyn Console) {
+        // there's nowhere to draw it
+        if !in_map(self.x, self.y) {
+            return;
+        }
+        // an invisible object is only a faint shimmer
+        let color = if self.is_invisible() {
+            tcod::colors::lerp(self.color, BLACK, 0.6)
+        } else {
+            self.color
+        };
+        con.set_default_foreground(color);
+        con.put_char(self.x, self.y, self.char, BackgroundFlag::None);
+    }
+
+    pub fn pos(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    pub fn set_pos(&mut self, x: i32, y: i32) {
+        debug_assert!(
+            in_map(x, y),
+            "{} moved off the map to ({}, {})",
+            self.name,
+            x,
+            y
+        );
+        self.x = x;
+        self.y = y;
+    }
+
+    /// return the distance to another object
+    pub fn distance_to(&self, other: &Object) -> f32 {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+    }
+
+    /// return the distance to some coordinates
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
+    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
+        // a wizard can make themself invulnerable
+        if self.is_player && game.god_mode {
+            return None;
+        }
+        // apply damage if possible
+        if let Some(fighter) = self.fighter.as_mut() {
+            if damage > 0 {
+                fighter.hp -= damage;
+            }
+        }
+        // slimes may split in two when they get hurt
+        if damage > 0 && self.ai == Some(Ai::Slime) {
+            split_slime(self, game);
+        }
+        // neutral characters don't take kindly to getting hurt
+        if damage > 0 && self.faction == Faction::Neutral && self.fighter.is_some_and(|f| f.hp > 0)
+        {
+            self.faction = Faction::Dungeon;
+            self.ai = Some(Ai::Basic);
+            game.add_actor(self.id);
+            game.messages
+                .add(tr!(game.strings, "npc.provoked", self.name), ORANGE);
+        }
+        // getting hurt holds off the player's regeneration
+        if damage > 0 && self.is_player {
+            game.last_hurt_turn = game.turns;
+            game.player_hurt = true;
+        }
+        // check for death, call the death function
+        if let Some(fighter) = self.fighter {
+            if fighter.hp <= 0 {
+                self.alive = false;
+                fighter.on_death.callback(self, game);
+                return Some(fighter.xp);
+            }
+        }
+        None
+    }
+
+    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
+        self.attack_with_bonus(target, game, 0);
+    }
+
+    /// Attack `target`, hitting `bonus` harder than this object's power
+    /// alone would.
+    pub fn attack_with_bonus(&mut self, target: &mut Object, game: &mut Game, bonus: i32) {
+        // lashing out gives away where you are
+        self.become_visible(game);
+
+        // roll to hit: the better the target's defense, the easier it dodges
+        let color = combat_color(self, target, false);
+        let hit_chance = BASE_HIT_CHANCE - DODGE_PER_DEFENSE * target.defense(game);
+        if game.rng.gen_range(0, 100) >= hit_chance {
+            game.messages.add(
+                tr!(game.strings, "combat.miss", self.name, target.name),
+                color,
+            );
+            return;
+        }
+
+        // roll the damage, then take away what the defense stops
+        let mut damage = self.damage_dice(game).roll(&mut game.rng) + self.power(game) + bonus
+            - target.defense(game);
+
+        // every now and then, an attack hits twice as hard
+        let critical = game.rng.gen::<f32>() < self.fighter.map_or(0.0, |f| f.crit_chance);
+        if critical {
+            damage *= 2;
+        }
+
+        if damage > 0 {
+            // make the target take some damage
+            let key = if critical {
+                "combat.critical"
+            } else {
+                "combat.attack"
+            };
+            game.messages.add(
+                tr!(game.strings, key, self.name, target.name, damage),
+                combat_color(self, target, critical),
+            );
+            game.last_damage_source = self.name.clone();
+            game.hits.push(target.pos());
+            if let Some(xp) = target.take_damage(damage, game) {
+                // yield experience to the player
+                if self.faction == Faction::Player {
+                    self.fighter.as_mut().unwrap().xp += xp;
+                }
+            }
+            // venom, or a weapon's special power
+            for effect in self.hit_effects(game, critical) {
+                target.add_effect(effect, game);
+            }
+        } else {
+            game.messages.add(
+                tr!(
+                    game.strings,
+                    "combat.attack_no_effect",
+                    self.name,
+                    target.name
+                ),
+                color,
+            );
+        }
+    }
+
+    /// heal by the given amount, without going over the maximum
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
+        if let Some(ref mut fighter) = self.fighter {
+            fighter.hp += amount;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
+            }
+        }
+    }
+
+    /// Equip object and show a message about it
+    pub fn equip(&mut self, strings: &Strings, messages: &mut Messages) {
+        if self.item.is_none() {
+            messages.add(
+                format!("Can't equip {:?} because it's not an Item.", self),
+                RED,
+            );
+            return;
+        };
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                messages.add(
+                    tr!(
+                        strings,
+                        "item.equipped",
+                        self.name,
+                        equipment.slot.name(strings)
+                    ),
+                    LIGHT_GREEN,
+                );
+                if equipment.cursed && !equipment.curse_known {
+                    // too late to change your mind now
+                    equipment.curse_known = true;
+                    messages.add(tr!(strings, "item.curse_revealed", self.name), RED);
+                }
+            }
+        } else {
+            messages.add(
+                format!("Can't equip {:?} because it's not an Equipment.", self),
+                RED,
+            );
+        }
+    }
+
+    /// Dequip object and show a message about it. Returns `false` if the
+    /// object is still equipped afterwards.
+    pub fn dequip(&mut self, strings: &Strings, messages: &mut Messages) -> bool {
+        if self.item.is_none() {
+            messages.add(
+                format!("Can't dequip {:?} because it's not an Item.", self),
+                RED,
+            );
+            return false;
+        };
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped {
+                if equipment.cursed {
+                    messages.add(tr!(strings, "item.cursed", self.name), RED);
+                    return false;
+                }
+                equipment.equipped = false;
+                messages.add(
+                    tr!(
+                        strings,
+                        "item.dequipped",
+                        self.name,
+                        equipment.slot.name(strings)
+                    ),
+                    LIGHT_YELLOW,
+                );
+            }
+            true
+        } else {
+            messages.add(
+                format!("Can't dequip {:?} because it's not an Equipment.", self),
+                RED,
+            );
+            false
+        }
+    }
+
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.base_power);
+        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.power()).sum();
+        // a starving player can barely lift their weapon
+        let penalty = if self.is_player {
+            Hunger::from_turns(game.hunger).power_penalty()
+        } else {
+            0
+        };
+        base_power + bonus - penalty
+    }
+
+    /// The dice for this object's attacks: its best weapon, or its
+    /// natural attack if it doesn't have one. A bow on the back is only
+    /// good for shooting.
+    pub fn damage_dice(&self, game: &Game) -> Dice {
+        self.get_all_equipped(game)
+            .iter()
+            .filter(|e| e.slot != Slot::Back)
+            .filter_map(|e| e.damage)
+            .max_by_key(|dice| dice.max())
+            .unwrap_or_else(|| self.fighter.map_or(Dice::default(), |f| f.damage))
+    }
+
+    /// The lowest and highest damage an attack can do (before defense).
+    pub fn damage_range(&self, game: &Game) -> String {
+        let dice = self.damage_dice(game);
+        let power = self.power(game);
+        format!("{}-{}", dice.min() + power, dice.max() + power)
+    }
+
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.defense())
+            .sum();
+        base_defense + bonus
+    }
+
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self.get_all_equipped(game).iter().map(|e| e.max_hp()).sum();
+        base_max_hp + bonus
+    }
+
+    /// returns a list of equipped items
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        if self.is_player {
+            game.inventory
+                .iter()
+                .filter(|item| item.equipment.is_some_and(|e| e.equipped))
+                .map(|item| item.equipment.unwrap())
+                .collect()
+        } else {
+            vec![] // other objects have no equipment
+        }
+    }
+}
+
+/// move by the given amount, if the destination is not blocked
+fn move_by(id: usize, dx: i32, dy: i32, map: &Map, objects: &mut [Object]) {
+    let (x, y) = objects[id].pos();
+    if !is_blocked(x + dx, y + dy, map, objects) {
+        objects[id].set_pos(x + dx, y + dy);
+    }
+}
+
+fn move_towards(id: usize, target_x: i32, target_y: i32, map: &Map, objects: &mut [Object]) {
+    // vector from this object to the target, and distance
+    let dx = target_x - objects[id].x;
+    let dy = target_y - objects[id].y;
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    // normalize it to length 1 (preserving direction), then round it and
+    // convert to integer so the movement is restricted to the map grid
+    let dx = (dx as f32 / distance).round() as i32;
+    let dy = (dy as f32 / distance).round() as i32;
+    move_by(id, dx, dy, map, objects);
+}
+
+/// add to the player's inventory and remove from the map
+fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    // a matching stack takes the item without needing another slot
+    let stack = game
+        .inventory
+        .iter()
+        .position(|item| item.stacks_with(&objects[object_id]));
+    if stack.is_none() && game.inventory.len() >= INVENTORY_SIZE {
+        game.messages.add(
+            tr!(game.strings, "item.inventory_full", objects[object_id].name),
+            RED,
+        );
+    } else {
+        let item = objects.swap_remove(object_id);
+        game.messages.add(
+            tr!(
+                game.strings,
+                "item.picked_up",
+                item.stack_name(&game.strings)
+            ),
+            GREEN,
+        );
+        if let Some(index) = stack {
+            game.inventory[index].count += item.count;
+            return;
+        }
+        let index = game.inventory.len();
+        let slot = item.equipment.map(|e| e.slot);
+        game.inventory.push(item);
+
+        // automatically equip, if there's an unused slot it can go in
+        if let Some(slot) = slot {
+            if let Some(free) = free_slot_for(slot, &game.inventory) {
+                game.inventory[index].set_slot(free);
+                game.inventory[index].equip(&game.strings, &mut game.messages);
+            }
+        }
+    }
+}
+
+/// Take the item at `slot` out of the container with the given ID and
+/// put it in the inventory. It stays in there if there's no room.
+fn take_out(container_id: ObjectId, slot: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let index = match object_index(container_id, objects) {
+        Some(index) if objects[index].pos() == (player_x, player_y) => index,
+        _ => return,
+    };
+    let contents = match objects[index].contents.as_mut() {
+        Some(contents) if slot < contents.len() => contents,
+        _ => return,
+    };
+    let fits = game.inventory.len() < INVENTORY_SIZE
+        || game
+            .inventory
+            .iter()
+            .any(|item| item.stacks_with(&contents[slot]));
+    if !fits {
+        game.messages.add(
+            tr!(game.strings, "item.inventory_full", contents[slot].name),
+            RED,
+        );
+        return;
+    }
+    let mut item = contents.remove(slot);
+    item.set_pos(player_x, player_y);
+    objects.push(item);
+    let last = objects.len() - 1;
+    pick_item_up(last, game, objects);
+}
+
+/// Cut up the corpse with the given ID into meat and pick it up. The
+/// meat rots when the corpse would have. Returns whether there was a
+/// corpse under the player to cut up.
+fn butcher(corpse_id: ObjectId, game: &mut Game, objects: &mut Vec<Object>) -> bool {
+    let pos = objects[PLAYER].pos();
+    let index = match object_index(corpse_id, objects) {
+        Some(index) if objects[index].item == Some(Item::Corpse) && objects[index].pos() == pos => {
+            index
+        }
+        _ => return false,
+    };
+    let corpse = &mut objects[index];
+    game.messages
+        .add(tr!(game.strings, "butcher.done", corpse.name), LIGHT_GREEN);
+    let remains = corpse.name.trim_start_matches("remains of ").to_string();
+    corpse.name = tr!(game.strings, "butcher.meat", remains);
+    corpse.item = Some(Item::Meat);
+    corpse.count = MEAT_PORTIONS;
+    corpse.render_order = RenderOrder::Item;
+    // if it doesn't fit in the pack, it stays on the floor
+    pick_item_up(index, game, objects);
+    true
+}
+
+fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+    for (inventory_id, item) in inventory.iter().enumerate() {
+        if item
+            .equipment
+            .as_ref()
+            .is_some_and(|e| e.equipped && e.slot == slot)
+        {
+            return Some(inventory_id);
+        }
+    }
+    None
+}
+
+/// Return the ids of all items lying at the given position
+fn items_at(x: i32, y: i32, objects: &[Object]) -> Vec<usize> {
+    objects
+        .iter()
+        .enumerate()
+        .filter(|&(_, object)| object.pos() == (x, y) && object.item.is_some())
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// What the "things here" menu can do with something on the player's
+/// tile.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Verb {
+    /// Put a loose item in the inventory.
+    PickUp,
+    /// List what's in a container, right under it.
+    Open,
+    /// Stop listing a container's contents.
+    Close,
+    /// Take an item out of an open container.
+    Take,
+    /// Cut a corpse up into meat.
+    Butcher,
+}
+
+/// One line of the "things here" menu.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct TileInteraction {
+    verb: Verb,
+    /// The object on the map this is about.
+    object_id: ObjectId,
+    /// For a thing in a container, where in its `contents` it is.
+    slot: Option<usize>,
+}
+
+impl TileInteraction {
+    /// What the menu says for this, e.g. "open chest".
+    fn describe(&self, objects: &[Object], strings: &Strings) -> String {
+        let object = match object_index(self.object_id, objects) {
+            Some(index) => &objects[index],
+            None => return String::new(),
+        };
+        let name = match (self.slot, &object.contents) {
+            (Some(slot), Some(contents)) => contents[slot].stack_name(strings),
+            _ => object.stack_name(strings),
+        };
+        match self.verb {
+            Verb::PickUp => tr!(strings, "tile.pick_up", name),
+            Verb::Open => tr!(strings, "tile.open", name),
+            Verb::Close => tr!(strings, "tile.close", name),
+            Verb::Take => tr!(strings, "tile.take", name),
+            Verb::Butcher => tr!(strings, "tile.butcher", name),
+        }
+    }
+}
+
+/// Everything the player can do with the things at (x, y). The
+/// containers in `open` have their contents listed right after them.
+fn tile_interactions(
+    x: i32,
+    y: i32,
+    objects: &[Object],
+    open: &[ObjectId],
+) -> Vec<TileInteraction> {
+    let mut interactions = vec![];
+    for object in objects.iter().filter(|object| object.pos() == (x, y)) {
+        let entry = |verb, slot| TileInteraction {
+            verb,
+            object_id: object.id,
+            slot,
+        };
+        if let Some(contents) = &object.contents {
+            if open.contains(&object.id) {
+                interactions.push(entry(Verb::Close, None));
+                interactions.extend((0..contents.len()).map(|slot| entry(Verb::Take, Some(slot))));
+            } else {
+                interactions.push(entry(Verb::Open, None));
+            }
+        } else if object.item == Some(Item::Corpse) {
+            interactions.push(entry(Verb::Butcher, None));
+        } else if object.item.is_some() {
+            interactions.push(entry(Verb::PickUp, None));
+        }
+    }
+    interactions
+}
+
+/// The "things here" menu without the window: which containers are
+/// open and which things are marked to be taken.
+struct TileMenu {
+    x: i32,
+    y: i32,
+    open: Vec<ObjectId>,
+    marked: Vec<TileInteraction>,
+}
+
+impl TileMenu {
+    fn new(x: i32, y: i32) -> Self {
+        TileMenu {
+            x,
+            y,
+            open: vec![],
+            marked: vec![],
+        }
+    }
+
+    fn entries(&self, objects: &[Object]) -> Vec<TileInteraction> {
+        tile_interactions(self.x, self.y, objects, &self.open)
+    }
+
+    fn is_marked(&self, entry: &TileInteraction) -> bool {
+        self.marked.contains(entry)
+    }
+
+    /// The line for an entry, with a box for the things that can be
+    /// marked and the contents of a container indented under it.
+    fn label(&self, entry: &TileInteraction, objects: &[Object], strings: &Strings) -> String {
+        let indent = if entry.slot.is_some() { "    " } else { "" };
+        let mark = match entry.verb {
+            Verb::PickUp | Verb::Take if self.is_marked(entry) => "[x] ",
+            Verb::PickUp | Verb::Take => "[ ] ",
+            _ => "",
+        };
+        format!("{}{}{}", indent, mark, entry.describe(objects, strings))
+    }
+
+    /// Do what the chosen entry says. Returns the actions to take if
+    /// that's the end of the menu.
+    fn choose(&mut self, entry: TileInteraction) -> Option<Vec<Action>> {
+        match entry.verb {
+            Verb::PickUp | Verb::Take => {
+                if self.is_marked(&entry) {
+                    self.marked.retain(|marked| *marked != entry);
+                } else {
+                    self.marked.push(entry);
+                }
+                None
+            }
+            Verb::Open => {
+                self.open.push(entry.object_id);
+                None
+            }
+            Verb::Close => {
+                // what isn't listed can't stay marked
+                self.open.retain(|&id| id != entry.object_id);
+                self.marked
+                    .retain(|marked| marked.object_id != entry.object_id);
+                None
+            }
+            Verb::Butcher => {
+                // butchering takes a turn, so it comes after the rest
+                let mut actions = self.actions();
+                actions.push(Action::Butcher(entry.object_id));
+                Some(actions)
+            }
+        }
+    }
+
+    /// Mark every loose item.
+    fn mark_all(&mut self, objects: &[Object]) {
+        for entry in self.entries(objects) {
+            if entry.verb == Verb::PickUp && !self.is_marked(&entry) {
+                self.marked.push(entry);
+            }
+        }
+    }
+
+    /// What's marked, as actions. Things come out of a container from
+    /// the back, so the rest keep their place in it.
+    fn actions(&self) -> Vec<Action> {
+        let mut marked = self.marked.clone();
+        marked.sort_by_key(|entry| cmp::Reverse(entry.slot));
+        marked
+            .iter()
+            .map(|entry| match entry.slot {
+                Some(slot) => Action::TakeOut(entry.object_id, slot),
+                None => Action::PickUp(entry.object_id),
+            })
+            .collect()
+    }
+}
+
+fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+    // first test the map tile
+    if map[(x, y)].blocked {
+        return true;
+    }
+    // now check for any blocking objects
+    objects
+        .iter()
+        .any(|object| object.blocks && object.pos() == (x, y))
+}
+
+/// Find an unblocked tile next to the given position
+fn free_tile_near(x: i32, y: i32, map: &Map, objects: &[Object]) -> Option<(i32, i32)> {
+    for dx in -1..2 {
+        for dy in -1..2 {
+            let (x, y) = (x + dx, y + dy);
+            if in_map(x, y) && !is_blocked(x, y, map, objects) {
+                return Some((x, y));
+            }
+        }
+    }
+    None
+}
+
+/// Put the objects from `pending_spawns` on the map. Blocking objects
+/// that would end up on an occupied tile are moved next to it, or
+/// dropped if there's no room. Items that would land on another item
+/// move to the closest empty floor, if there's any nearby.
+fn add_pending_spawns(game: &mut Game, objects: &mut Vec<Object>) {
+    let spawns: Vec<Object> = game.pending_spawns.drain(..).collect();
+    for mut object in spawns {
+        if object.blocks && is_blocked(object.x, object.y, &game.map, objects) {
+            match free_tile_near(object.x, object.y, &game.map, objects) {
+                Some((x, y)) => object.set_pos(x, y),
+                None => continue,
+            }
+        }
+        if object.item.is_some() && !is_empty_floor(object.x, object.y, &game.map, objects) {
+            if let Some((x, y)) = empty_floor_near(object.x, object.y, &game.map, objects) {
+                object.set_pos(x, y);
+            }
+        }
+        objects.push(object);
+    }
+}
+
+/// Is (x, y) a floor tile nobody's standing on and no item's lying on?
+/// Corpses don't count, there's always room on top of those.
+fn is_empty_floor(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
+    let item_there = |object: &Object| {
+        object.item.is_some() && object.item != Some(Item::Corpse) && object.pos() == (x, y)
+    };
+    in_map(x, y) && !is_blocked(x, y, map, objects) && !objects.iter().any(item_there)
+}
+
+/// Find the closest empty floor tile to the given position, up to
+/// `LOOT_SPREAD` tiles away.
+fn empty_floor_near(x: i32, y: i32, map: &Map, objects: &[Object]) -> Option<(i32, i32)> {
+    for distance in 1..=LOOT_SPREAD {
+        for dx in -distance..=distance {
+            for dy in -distance..=distance {
+                let ring = dx.abs() == distance || dy.abs() == distance;
+                if ring && is_empty_floor(x + dx, y + dy, map, objects) {
+                    return Some((x + dx, y + dy));
+                }
+            }
+        }
+    }
+    None
+}
+
+// combat-related properties and methods (monster, player, NPC).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Fighter {
+    hp: i32,
+    base_max_hp: i32,
+    base_defense: i32,
+    base_power: i32,
+    /// The natural attack, used when not wielding a weapon.
+    #[serde(default)]
+    damage: Dice,
+    /// The chance (0 to 1) of an attack doing double damage.
+    #[serde(default = "default_crit_chance")]
+    crit_chance: f32,
+    /// How quickly this acts. At `NORMAL_SPEED`, it gets one action
+    /// per turn.
+    #[serde(default = "default_speed")]
+    speed: i32,
+    /// Magic left for casting spells. Only the player has any.
+    #[serde(default)]
+    mana: i32,
+    #[serde(default)]
+    max_mana: i32,
+    xp: i32,
+    on_death: DeathCallback,
+}
+
+fn default_crit_chance() -> f32 {
+    CRIT_CHANCE
+}
+
+fn default_speed() -> i32 {
+    NORMAL_SPEED
+}
+
+fn default_energy() -> i32 {
+    ACTION_COST
+}
+
+/// A dice roll like "2d6+1": `count` dice with `sides` sides, plus `bonus`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Dice {
+    count: i32,
+    sides: i32,
+    bonus: i32,
+}
+
+impl Dice {
+    fn new(count: i32, sides: i32, bonus: i32) -> Self {
+        Dice {
+            count,
+            sides,
+            bonus,
+        }
+    }
+
+    fn roll(self, rng: &mut StdRng) -> i32 {
+        let total: i32 = (0..self.count)
+            .map(|_| rng.gen_range(1, self.sides + 1))
+            .sum();
+        total + self.bonus
+    }
+
+    fn min(self) -> i32 {
+        self.count + self.bonus
+    }
+
+    fn max(self) -> i32 {
+        self.count * self.sides + self.bonus
+    }
+}
+
+/// A weak punch. This is also what fighters from games saved before
+/// dice existed attack with.
+impl Default for Dice {
+    fn default() -> Self {
+        Dice::new(1, 2, 0)
+    }
+}
+
+impl std::fmt::Display for Dice {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+        match self.bonus {
+            0 => Ok(()),
+            bonus => write!(f, "{:+}", bonus),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum DeathCallback {
+    Player,
+    Monster,
+    Boss,
+}
+
+/// Who's on whose side. Objects attack anyone from a different faction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum Faction {
+    Player,
+    #[default]
+    Dungeon,
+    Wildlife,
+    Neutral,
+}
+
+impl Faction {
+    /// Neutral objects don't fight and nobody fights them.
+    fn is_hostile_to(self, other: Faction) -> bool {
+        self != other && self != Faction::Neutral && other != Faction::Neutral
+    }
+}
+
+impl DeathCallback {
+    fn callback(self, object: &mut Object, game: &mut Game) {
+        use DeathCallback::*;
+        let callback = match self {
+            Player => player_death,
+            Monster => monster_death,
+            Boss => boss_death,
+        };
+        callback(object, game);
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Ai {
+    Basic,
+    Confused {
+        previous_ai: Box<Ai>,
+        num_turns: i32,
+    },
+    Chasing {
+        last_seen: (i32, i32),
+        num_turns: i32,
+    },
+    Pack {
+        pack_id: usize,
+        /// The leader picks what the pack hunts.
+        #[serde(default)]
+        leader: bool,
+        /// What the leader picked, as the pack saw it last.
+        #[serde(default)]
+        target: Option<ObjectId>,
+    },
+    Caster {
+        cooldown: i32,
+    },
+    Thief,
+    Fleeing,
+    Slime,
+    Boss {
+        phase: u32,
+        summon_cooldown: i32,
+    },
+    Wander,
+    Shopkeeper,
+}
+
+/// What the monsters know about the world besides the `Game` and the
+/// objects: what the player can see, and the game's data. The game makes
+/// one out of `Tcod`; the tests make up their own.
+struct View<'a> {
+    in_fov: Box<dyn Fn(i32, i32) -> bool + 'a>,
+    config: &'a GameConfig,
+}
+
+impl<'a> View<'a> {
+    fn of(tcod: &'a Tcod) -> Self {
+        View {
+            in_fov: Box::new(move |x, y| tcod.in_fov(x, y)),
+            config: &tcod.config,
+        }
+    }
+
+    fn in_fov(&self, x: i32, y: i32) -> bool {
+        (self.in_fov)(x, y)
+    }
+}
+
+impl Ai {
+    /// What the behaviour is called in the `AiRegistry` and in `ai.json`.
+    fn name(&self) -> &'static str {
+        use Ai::*;
+        match self {
+            Basic => "basic",
+            Confused { .. } => "confused",
+            Chasing { .. } => "chasing",
+            Pack { .. } => "pack",
+            Caster { .. } => "caster",
+            Thief => "thief",
+            Fleeing => "fleeing",
+            Slime => "slime",
+            Boss { .. } => "boss",
+            Wander => "wander",
+            Shopkeeper => "shopkeeper",
+        }
+    }
+}
+
+/// One of the ways a monster can act. The `Ai` a monster carries names
+/// its behaviour and holds what the monster remembers between turns. The
+/// behaviours themselves are code, so they live in the `AiRegistry` and
+/// never get saved.
+trait AiBehavior {
+    /// Run one turn for the given monster, whose `ai` names this
+    /// behaviour, and return the `Ai` it should have on the next turn.
+    fn take_turn(
+        &self,
+        ai: Ai,
+        monster_id: usize,
+        view: &View,
+        game: &mut Game,
+        objects: &mut [Object],
+    ) -> Ai;
+}
+
+impl<F> AiBehavior for F
+where
+    F: Fn(Ai, usize, &View, &mut Game, &mut [Object]) -> Ai,
+{
+    fn take_turn(
+        &self,
+        ai: Ai,
+        monster_id: usize,
+        view: &View,
+        game: &mut Game,
+        objects: &mut [Object],
+    ) -> Ai {
+        self(ai, monster_id, view, game, objects)
+    }
+}
+
+/// Every behaviour, by the name `Ai::name` gives it.
+struct AiRegistry {
+    behaviors: HashMap<&'static str, Box<dyn AiBehavior>>,
+    /// From `ai.json`: a monster that's about to act out one of these
+    /// behaviours switches to the given `Ai` for good.
+    replacements: HashMap<String, Ai>,
+}
+
+impl Default for AiRegistry {
+    fn default() -> Self {
+        use Ai::*;
+        let mut registry = AiRegistry {
+            behaviors: HashMap::new(),
+            replacements: HashMap::new(),
+        };
+        // each behaviour only ever gets the `Ai` it's registered for;
+        // anything else, it hands back untouched
+        registry.register(
+            "basic",
+            |_, id, view: &View, game: &mut Game, objects: &mut [Object]| {
+                ai_basic(id, view, game, objects)
+            },
+        );
+        registry.register(
+            "confused",
+            |ai, id, view: &View, game: &mut Game, objects: &mut [Object]| match ai {
+                Confused {
+                    previous_ai,
+                    num_turns,
+                } => ai_confused(id, view, game, objects, previous_ai, num_turns),
+                ai => ai,
+            },
+        );
+        registry.register(
+            "chasing",
+            |ai, id, view: &View, game: &mut Game, objects: &mut [Object]| match ai {
+                Chasing {
+                    last_seen,
+                    num_turns,
+                } => ai_chasing(id, view, game, objects, last_seen, num_turns),
+                ai => ai,
+            },
+        );
+        registry.register(
+            "pack",
+            |ai, id, view: &View, game: &mut Game, objects: &mut [Object]| match ai {
+                Pack {
+                    pack_id,
+                    leader,
+                    target,
+                } => ai_pack(id, view, game, objects, pack_id, leader, target),
+                ai => ai,
+            },
+        );
+        registry.register(
+            "caster",
+            |ai, id, view: &View, game: &mut Game, objects: &mut [Object]| match ai {
+                Caster { cooldown } => ai_caster(id, view, game, objects, cooldown),
+                ai => ai,
+            },
+        );
+        registry.register(
+            "thief",
+            |_, id, view: &View, game: &mut Game, objects: &mut [Object]| {
+                ai_thief(id, view, game, objects)
+            },
+        );
+        registry.register(
+            "fleeing",
+            |_, id, view: &View, game: &mut Game, objects: &mut [Object]| {
+                ai_fleeing(id, view, game, objects)
+            },
+        );
+        registry.register(
+            "slime",
+            |_, id, view: &View, game: &mut Game, objects: &mut [Object]| {
+                ai_basic(id, view, game, objects);
+                Slime
+            },
+        );
+        registry.register(
+            "boss",
+            |ai, id, view: &View, game: &mut Game, objects: &mut [Object]| match ai {
+                Boss {
+                    phase,
+                    summon_cooldown,
+                } => ai_boss(id, view, game, objects, phase, summon_cooldown),
+                ai => ai,
+            },
+        );
+        registry.register(
+            "wander",
+            |_, id, view: &View, game: &mut Game, objects: &mut [Object]| {
+                ai_wander(id, view, game, objects)
+            },
+        );
+        registry.register(
+            "shopkeeper",
+            |ai, _, _: &View, _: &mut Game, _: &mut [Object]| ai,
+        );
+        registry
+    }
+}
+
+impl AiRegistry {
+    /// Add a behaviour, or put a new one in place of the one with the
+    /// same name.
+    fn register(&mut self, name: &'static str, behavior: impl AiBehavior + 'static) {
+        self.behaviors.insert(name, Box::new(behavior));
+    }
+
+    /// Run one turn of the monster's behaviour and return the `Ai` it
+    /// should have on the next turn.
+    fn take_turn(
+        &self,
+        ai: Ai,
+        monster_id: usize,
+        view: &View,
+        game: &mut Game,
+        objects: &mut [Object],
+    ) -> Ai {
+        let ai = match self.replacements.get(ai.name()) {
+            Some(replacement) => replacement.clone(),
+            None => ai,
+        };
+        match self.behaviors.get(ai.name()) {
+            Some(behavior) => behavior.take_turn(ai, monster_id, view, game, objects),
+            // nothing knows how to do it, so the monster waits
+            None => ai,
+        }
+    }
+
+    /// Read `ai.json`, then the `mods`' changes to it. Each file maps the
+    /// names of behaviours to the `Ai` that replaces them, the same way a
+    /// monster's `ai` is written in `spawns.json`; a later file's
+    /// replacement wins.
+    fn load(mods: &[PathBuf]) -> Result<Self, String> {
+        let mut registry = AiRegistry::default();
+        for (source, path) in data_layers(Path::new("ai.json"), mods) {
+            let layer: HashMap<String, Ai> = match read_layer(&source, &path)? {
+                Some(layer) => layer,
+                None => continue,
+            };
+            let file = format!("{} ({})", source, path.display());
+            for (name, ai) in layer {
+                if !registry.behaviors.contains_key(name.as_str()) {
+                    return Err(format!("{}: there's no behaviour called `{}`", file, name));
+                }
+                registry.replacements.insert(name, ai);
+            }
+        }
+        Ok(registry)
+    }
+}
+
+fn ai_take_turn(monster_id: usize, view: &View, game: &mut Game, objects: &mut [Object]) {
+    if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = view
+            .config
+            .ai
+            .take_turn(ai, monster_id, view, game, objects);
+        objects[monster_id].ai = Some(new_ai);
+    }
+}
+
+/// Can `observer` see `target`? We only compute the player's field of
+/// view, but it's symmetric: whatever is in it can see the player and
+/// everything else in it. Invisible targets are only noticed right
+/// next to the observer, or by chance.
+fn can_see(view: &View, observer: &Object, target: &Object, rng: &mut StdRng) -> bool {
+    if !view.in_fov(observer.x, observer.y) || !view.in_fov(target.x, target.y) {
+        return false;
+    }
+    !target.is_invisible()
+        || observer.distance_to(target) < 2.0
+        || rng.gen::<f32>() < INVISIBLE_SPOT_CHANCE
+}
+
+/// Find the closest living object hostile to the given monster. Only
+/// objects it can see count.
+fn closest_enemy(
+    monster_id: usize,
+    view: &View,
+    objects: &[Object],
+    rng: &mut StdRng,
+) -> Option<usize> {
+    let monster = &objects[monster_id];
+    objects
+        .iter()
+        .enumerate()
+        .filter(|&(id, object)| {
+            id != monster_id
+                && object.alive
+                && object.fighter.is_some_and(|f| f.hp > 0)
+                && monster.faction.is_hostile_to(object.faction)
+                && can_see(view, monster, object, rng)
+        })
+        .min_by_key(|&(_, object)| (object.x - monster.x).pow(2) + (object.y - monster.y).pow(2))
+        .map(|(id, _)| id)
+}
+
+fn ai_basic(monster_id: usize, view: &View, game: &mut Game, objects: &mut [Object]) -> Ai {
+    // a basic monster takes its turn. It goes after anyone it can see
+    match closest_enemy(monster_id, view, objects, &mut game.rng) {
+        Some(target_id) => ai_fight(monster_id, target_id, game, objects),
+        None => Ai::Basic,
+    }
+}
+
+/// Move towards the target, or attack it if it's close enough.
+fn ai_fight(monster_id: usize, target_id: usize, game: &mut Game, objects: &mut [Object]) -> Ai {
+    if objects[monster_id].distance_to(&objects[target_id]) >= 2.0 {
+        // move towards the target if far away
+        let (target_x, target_y) = objects[target_id].pos();
+        move_towards(monster_id, target_x, target_y, &game.map, objects);
+    } else {
+        // close enough, attack!
+        let (monster, target) = mut_two(monster_id, target_id, objects);
+        monster.attack(target, game);
+    }
+    if target_id == PLAYER {
+        // remember where we saw the player
+        Ai::Chasing {
+            last_seen: objects[PLAYER].pos(),
+            num_turns: CHASE_NUM_TURNS,
+        }
+    } else {
+        Ai::Basic
+    }
+}
+
+fn ai_chasing(
+    monster_id: usize,
+    view: &View,
+    game: &mut Game,
+    objects: &mut [Object],
+    last_seen: (i32, i32),
+    num_turns: i32,
+) -> Ai {
+    if let Some(target_id) = closest_enemy(monster_id, view, objects, &mut game.rng) {
+        // someone's in sight again, go get them
+        return ai_fight(monster_id, target_id, game, objects);
+    }
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if (monster_x, monster_y) == last_seen || num_turns <= 0 {
+        // nobody's here; give up
+        return Ai::Basic;
+    }
+    // go look where the player was last seen
+    let (x, y) = last_seen;
+    move_towards(monster_id, x, y, &game.map, objects);
+    Ai::Chasing {
+        last_seen,
+        num_turns: num_turns - 1,
+    }
+}
+
+fn ai_pack(
+    monster_id: usize,
+    view: &View,
+    game: &mut Game,
+    objects: &mut [Object],
+    pack_id: usize,
+    leader: bool,
+    target: Option<ObjectId>,
+) -> Ai {
+    // this one's `ai` is taken out while it's taking its turn, so it's
+    // never counted as part of the pack here
+    let in_pack = |object: &Object| match object.ai {
+        Some(Ai::Pack { pack_id: id, .. }) => object.alive && id == pack_id,
+        _ => false,
+    };
+    let leader_id = objects.iter().position(|object| {
+        in_pack(object) && matches!(object.ai, Some(Ai::Pack { leader: true, .. }))
+    });
+    // a pack that lost its leader follows the first wolf to take a turn
+    let leader = leader || leader_id.is_none();
+
+    let target = match leader_id {
+        Some(leader_id) if !leader => match objects[leader_id].ai {
+            Some(Ai::Pack { target, .. }) => target,
+            _ => None,
+        },
+        _ => {
+            // the leader sticks with its target while it can see it
+            let still_seen = target
+                .and_then(|id| object_index(id, objects))
+                .filter(|&index| {
+                    objects[index].alive
+                        && can_see(view, &objects[monster_id], &objects[index], &mut game.rng)
+                })
+                .map(|index| objects[index].id);
+            // otherwise it goes after the closest enemy it sees. If it
+            // doesn't see any, one the rest of the pack sees will do
+            still_seen.or_else(|| {
+                let pack: Vec<usize> = (0..objects.len())
+                    .filter(|&id| id == monster_id || in_pack(&objects[id]))
+                    .collect();
+                pack.into_iter()
+                    .find_map(|id| closest_enemy(id, view, objects, &mut game.rng))
+                    .map(|index| objects[index].id)
+            })
+        }
+    };
+    let pack_ai = Ai::Pack {
+        pack_id,
+        leader,
+        target,
+    };
+    let target_id = match target.and_then(|id| object_index(id, objects)) {
+        Some(index) if objects[index].alive => index,
+        _ => return pack_ai,
+    };
+
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let (target_x, target_y) = objects[target_id].pos();
+    if objects[monster_id].distance_to(&objects[target_id]) >= 2.0 {
+        // run to the closest free tile next to the target
+        let mut free_tiles = vec![];
+        for dx in -1..2 {
+            for dy in -1..2 {
+                let (x, y) = (target_x + dx, target_y + dy);
+                if in_map(x, y) && !is_blocked(x, y, &game.map, objects) {
+                    free_tiles.push((x, y));
+                }
+            }
+        }
+        let (x, y) = free_tiles
+            .into_iter()
+            .min_by_key(|&(x, y)| (x - monster_x).pow(2) + (y - monster_y).pow(2))
+            .unwrap_or((target_x, target_y));
+        move_towards(monster_id, x, y, &game.map, objects);
+    } else if objects[target_id].fighter.is_some_and(|f| f.hp > 0) {
+        // with another wolf next to the target, it's got nowhere to dodge
+        let flankers = objects
+            .iter()
+            .filter(|&object| in_pack(object) && object.distance_to(&objects[target_id]) < 2.0)
+            .count();
+        let bonus = if flankers > 0 { PACK_ATTACK_BONUS } else { 0 };
+        let (monster, target) = mut_two(monster_id, target_id, objects);
+        monster.attack_with_bonus(target, game, bonus);
+    }
+    pack_ai
+}
+
+fn ai_caster(
+    monster_id: usize,
+    view: &View,
+    game: &mut Game,
+    objects: &mut [Object],
+    cooldown: i32,
+) -> Ai {
+    let player_alive = objects[PLAYER].fighter.is_some_and(|f| f.hp > 0);
+    if cooldown <= 0
+        && player_alive
+        && can_see(view, &objects[monster_id], &objects[PLAYER], &mut game.rng)
+        && objects[monster_id].distance_to(&objects[PLAYER]) <= APPRENTICE_RANGE
+    {
+        // the same bolt as the scroll's, just weaker
+        let bolt = Effect {
+            kind: EffectKind::Damage(APPRENTICE_LIGHTNING_DAMAGE),
+            ..item_effect(Item::Lightning, view.config).unwrap()
+        };
+        affect(&bolt, PLAYER, game, objects);
+        return Ai::Caster {
+            cooldown: APPRENTICE_COOLDOWN,
+        };
+    }
+    // otherwise, behave like any other monster
+    ai_basic(monster_id, view, game, objects);
+    Ai::Caster {
+        cooldown: cooldown - 1,
+    }
+}
+
+fn ai_thief(monster_id: usize, view: &View, game: &mut Game, objects: &mut [Object]) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let next_to_player = objects[monster_id].distance_to(&objects[PLAYER]) < 2.0;
+    if !view.in_fov(monster_x, monster_y) || !next_to_player {
+        // sneak up on the player
+        ai_basic(monster_id, view, game, objects);
+        return Ai::Thief;
+    }
+
+    let unequipped: Vec<usize> = game
+        .inventory
+        .iter()
+        .enumerate()
+        .filter(|&(_, item)| item.equipment.is_none_or(|e| !e.equipped))
+        .map(|(index, _)| index)
+        .collect();
+    if unequipped.is_empty() {
+        // nothing to steal, fight instead
+        ai_basic(monster_id, view, game, objects);
+        return Ai::Thief;
+    }
+
+    let index = unequipped[game.rng.gen_range(0, unequipped.len())];
+    let item = game.inventory.remove(index);
+    game.messages.add(
+        tr!(
+            game.strings,
+            "thief.steal",
+            objects[monster_id].name,
+            item.name
+        ),
+        ORANGE,
+    );
+    objects[monster_id].carried = Some(Box::new(item));
+    Ai::Fleeing
+}
+
+/// Run off with the loot, to the stairs if there are any.
+fn ai_fleeing(monster_id: usize, view: &View, game: &mut Game, objects: &mut [Object]) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let goal = escape_tile((monster_x, monster_y), &game.map, objects);
+    if goal == Some((monster_x, monster_y)) {
+        // cornered at the edge of the map, there's nowhere left to run
+        return Ai::Fleeing;
+    }
+    let map = &game.map;
+    let walkable = |x: i32, y: i32| in_map(x, y) && !map[(x, y)].blocked;
+    let step = goal
+        .and_then(|goal| shortest_path((monster_x, monster_y), goal, walkable))
+        .and_then(|path| path.first().cloned());
+    match step {
+        Some((x, y)) => move_by(monster_id, x - monster_x, y - monster_y, &game.map, objects),
+        None if view.in_fov(monster_x, monster_y) => {
+            // there's no way out, so run directly away from the player
+            let (player_x, player_y) = objects[PLAYER].pos();
+            let dx = (monster_x - player_x).signum();
+            let dy = (monster_y - player_y).signum();
+            move_by(monster_id, dx, dy, &game.map, objects);
+        }
+        None => {}
+    }
+    Ai::Fleeing
+}
+
+/// Where a thief at `from` runs to: the stairs, to get away for good. On
+/// a level without any, the floor tile closest to the edge of the map.
+fn escape_tile(from: (i32, i32), map: &Map, objects: &[Object]) -> Option<(i32, i32)> {
+    if let Some(stairs) = objects.iter().find(|object| object.is_stairs()) {
+        return Some(stairs.pos());
+    }
+    let to_edge = |x: i32, y: i32| {
+        cmp::min(
+            cmp::min(x, MAP_WIDTH - 1 - x),
+            cmp::min(y, MAP_HEIGHT - 1 - y),
+        )
+    };
+    (0..MAP_WIDTH)
+        .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+        .filter(|&(x, y)| !map[(x, y)].blocked)
+        .min_by_key(|&(x, y)| (to_edge(x, y), (x - from.0).pow(2) + (y - from.1).pow(2)))
+}
+
+/// Thieves that made it to the stairs take them, loot and all. They're
+/// kept in `game.fled` until the player follows them down.
+fn escape_down_stairs(game: &mut Game, objects: &mut Vec<Object>) {
+    let stairs = match objects.iter().find(|object| object.is_stairs()) {
+        Some(stairs) => stairs.pos(),
+        None => return,
+    };
+    let mut index = 1;
+    while index < objects.len() {
+        let object = &objects[index];
+        if object.alive && object.carried.is_some() && object.pos() == stairs {
+            let thief = objects.remove(index);
+            if let Some(ref item) = thief.carried {
+                game.messages.add(
+                    tr!(game.strings, "thief.escaped", thief.name, item.name),
+                    RED,
+                );
+            }
+            game.fled.push(thief);
+        } else {
+            index += 1;
+        }
+    }
+}
+
+/// Take the thieves that still have something of the player's off the
+/// old level, along with the ones that already fled down the stairs.
+fn thieves_on_the_run(game: &mut Game, objects: &mut Vec<Object>) -> Vec<Object> {
+    let mut thieves: Vec<Object> = game.fled.drain(..).collect();
+    let mut index = 1;
+    while index < objects.len() {
+        if objects[index].alive && objects[index].carried.is_some() {
+            thieves.push(objects.remove(index));
+        } else {
+            index += 1;
+        }
+    }
+    thieves
+}
+
+/// Hide the thieves somewhere on the new level. If there's no room for
+/// one, it drops what it stole at the player's feet: the player's things
+/// are never lost.
+fn hide_thieves(thieves: Vec<Object>, game: &mut Game, objects: &mut Vec<Object>) {
+    for mut thief in thieves {
+        let mut hideout = None;
+        for _ in 0..TELEPORT_TRIES {
+            let x = game.rng.gen_range(0, MAP_WIDTH);
+            let y = game.rng.gen_range(0, MAP_HEIGHT);
+            if !is_blocked(x, y, &game.map, objects) {
+                hideout = Some((x, y));
+                break;
+            }
+        }
+        match hideout {
+            Some((x, y)) => {
+                if let Some(ref item) = thief.carried {
+                    game.messages.add(
+                        tr!(game.strings, "thief.followed", thief.name, item.name),
+                        ORANGE,
+                    );
+                }
+                thief.set_pos(x, y);
+                // a new level, a new ID and a new place among the actors
+                thief.id = 0;
+                objects.push(thief);
+            }
+            None => {
+                if let Some(mut item) = thief.carried.take() {
+                    let (x, y) = objects[PLAYER].pos();
+                    item.set_pos(x, y);
+                    item.id = 0;
+                    game.messages.add(
+                        tr!(game.strings, "thief.drop", thief.name, item.name),
+                        ORANGE,
+                    );
+                    objects.push(*item);
+                }
+            }
+        }
+    }
+}
+
+/// Maybe split a wounded slime in two. The new half is put into
+/// `pending_spawns`.
+fn split_slime(slime: &mut Object, game: &mut Game) {
+    let fighter = match slime.fighter.as_mut() {
+        Some(fighter) => fighter,
+        None => return,
+    };
+    if fighter.hp < SLIME_MIN_SPLIT_HP || game.rng.gen::<f32>() >= SLIME_SPLIT_CHANCE {
+        return;
+    }
+    let hp = fighter.hp / 2;
+    let max_hp = fighter.base_max_hp / 2;
+    let xp = fighter.xp / 2;
+    fighter.hp -= hp;
+    fighter.base_max_hp -= max_hp;
+    fighter.xp -= xp;
+    let fighter = *fighter;
+
+    // the new half is just like the old one, only smaller
+    let mut half = Object::new(slime.x, slime.y, slime.char, &slime.name, slime.color, true);
+    half.alive = true;
+    half.fighter = Some(Fighter {
+        hp,
+        base_max_hp: max_hp,
+        xp,
+        ..fighter
+    });
+    half.ai = Some(Ai::Slime);
+    half.faction = slime.faction;
+    half.tier = slime.tier;
+    half.on_hit = slime.on_hit;
+    game.messages
+        .add(tr!(game.strings, "slime.split", slime.name), LIGHT_GREEN);
+    game.pending_spawns.push(half);
+}
+
+fn ai_boss(
+    monster_id: usize,
+    view: &View,
+    game: &mut Game,
+    objects: &mut [Object],
+    phase: u32,
+    summon_cooldown: i32,
+) -> Ai {
+    let hp = objects[monster_id].fighter.map_or(0, |f| f.hp);
+    let max_hp = objects[monster_id].max_hp(game);
+    let name = objects[monster_id].name.clone();
+
+    // move on to the next phase when the HP drops low enough
+    let mut phase = phase;
+    if phase < 2 && hp * 2 <= max_hp {
+        phase = 2;
+        game.messages
+            .add(tr!(game.strings, "boss.enraged", name), ORANGE);
+    }
+    if phase < 3 && hp * 4 <= max_hp {
+        phase = 3;
+        if let Some(fighter) = objects[monster_id].fighter.as_mut() {
+            fighter.base_power += BOSS_DESPERATE_POWER_BONUS;
+        }
+        game.messages
+            .add(tr!(game.strings, "boss.desperate", name), RED);
+    }
+
+    let mut summon_cooldown = summon_cooldown - 1;
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    if phase >= 2 && summon_cooldown <= 0 && view.in_fov(monster_x, monster_y) {
+        // call for help instead of attacking
+        game.messages
+            .add(tr!(game.strings, "boss.summon", name), ORANGE);
+        for _ in 0..BOSS_SUMMON_COUNT {
+            let orc = create_monster("orc", monster_x, monster_y, &view.config.spawns);
+            game.pending_spawns.push(orc);
+        }
+        summon_cooldown = BOSS_SUMMON_COOLDOWN;
+    } else {
+        ai_basic(monster_id, view, game, objects);
+    }
+    Ai::Boss {
+        phase,
+        summon_cooldown,
+    }
+}
+
+/// Move the object to a random free tile next to it. Returns `false` if
+/// there's nowhere to go.
+fn step_at_random(id: usize, map: &Map, objects: &mut [Object], rng: &mut StdRng) -> bool {
+    let (x, y) = objects[id].pos();
+    let mut free_directions = vec![];
+    for dx in -1..2 {
+        for dy in -1..2 {
+            if (dx, dy) != (0, 0) && !is_blocked(x + dx, y + dy, map, objects) {
+                free_directions.push((dx, dy));
+            }
+        }
+    }
+    if free_directions.is_empty() {
+        return false;
+    }
+    let index = rng.gen_range(0, free_directions.len());
+    let (dx, dy) = free_directions[index];
+    move_by(id, dx, dy, map, objects);
+    true
+}
+
+fn ai_wander(monster_id: usize, _view: &View, game: &mut Game, objects: &mut [Object]) -> Ai {
+    step_at_random(monster_id, &game.map, objects, &mut game.rng);
+    Ai::Wander
+}
+
+fn ai_confused(
+    monster_id: usize,
+    view: &View,
+    game: &mut Game,
+    objects: &mut [Object],
+    previous_ai: Box<Ai>,
+    num_turns: i32,
+) -> Ai {
+    if num_turns >= 0 {
+        // still confused ...
+        // move in a random direction, and decrease the number of turns confused
+        let moved = step_at_random(monster_id, &game.map, objects, &mut game.rng);
+        let (x, y) = objects[monster_id].pos();
+        if !moved && view.in_fov(x, y) && game.rng.gen::<f32>() < CONFUSED_BUMP_CHANCE {
+            game.messages.add(
+                tr!(game.strings, "ai.confused_bump", objects[monster_id].name),
+                LIGHT_GREEN,
+            );
+        }
+        Ai::Confused {
+            previous_ai,
+            num_turns: num_turns - 1,
+        }
+    } else {
+        // restore the previous AI (this one will be deleted)
+        game.messages.add(
+            tr!(
+                game.strings,
+                "ai.no_longer_confused",
+                objects[monster_id].name
+            ),
+            RED,
+        );
+        *previous_ai
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Item {
+    Heal,
+    Lightning,
+    Confuse,
+    Fireball,
+    Sword,
+    Shield,
+    Crown,
+    Helmet,
+    Armor,
+    LightningWand,
+    ConfusionWand,
+    RemoveCurse,
+    Enchant,
+    LeatherArmor,
+    PlateArmor,
+    RingOfStrength,
+    RingOfVitality,
+    Mace,
+    Food,
+    Bow,
+    Arrows,
+    StrengthPotion,
+    VitalityPotion,
+    AgilityPotion,
+    Teleport,
+    Haste,
+    Slow,
+    Torch,
+    Lantern,
+    Invisibility,
+    Corpse,
+    ThrowingKnife,
+    Meat,
+}
+
+enum UseResult {
+    UsedUp,
+    UsedAndKept,
+    Cancelled,
+}
+
+fn use_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    use Item::*;
+    // just call the "use_function" if it is defined
+    if let Some(item) = game.inventory[inventory_id].item {
+        let on_use = match item {
+            Heal => cast_item_effect,
+            Lightning => cast_item_effect,
+            Confuse => cast_item_effect,
+            Fireball => cast_item_effect,
+            Sword => toggle_equipment,
+            Shield => toggle_equipment,
+            Crown => toggle_equipment,
+            Helmet => toggle_equipment,
+            Armor => toggle_equipment,
+            LightningWand => cast_item_effect,
+            ConfusionWand => cast_item_effect,
+            RemoveCurse => cast_remove_curse,
+            Enchant => cast_enchant,
+            LeatherArmor => toggle_equipment,
+            PlateArmor => toggle_equipment,
+            RingOfStrength => toggle_equipment,
+            RingOfVitality => toggle_equipment,
+            Mace => toggle_equipment,
+            Food => eat,
+            Bow => toggle_equipment,
+            Arrows => shoot,
+            StrengthPotion => drink_strength,
+            VitalityPotion => drink_vitality,
+            AgilityPotion => drink_agility,
+            Teleport => cast_teleport,
+            Haste => cast_item_effect,
+            Slow => cast_slow,
+            Torch => carry_light,
+            Lantern => carry_light,
+            Invisibility => cast_item_effect,
+            Corpse => eat_corpse,
+            ThrowingKnife => throw_knife,
+            Meat => eat_corpse,
+        };
+        match on_use(inventory_id, tcod, game, objects) {
+            UseResult::UsedUp => {
+                // destroy after use, unless it was cancelled for some reason
+                let item = &mut game.inventory[inventory_id];
+                if let Some(charges) = item.charges.as_mut() {
+                    // a wand only loses a charge, until it has none left
+                    *charges -= 1;
+                    if *charges == 0 {
+                        game.messages
+                            .add(tr!(game.strings, "item.wand_crumbles", item.name), ORANGE);
+                        game.inventory.remove(inventory_id);
+                    }
+                } else if item.count > 1 {
+                    item.count -= 1;
+                } else {
+                    game.inventory.remove(inventory_id);
+                }
+            }
+            UseResult::UsedAndKept => {} // do nothing
+            UseResult::Cancelled => {
+                game.messages
+                    .add(tr!(game.strings, "item.cancelled"), WHITE);
+            }
+        }
+    } else {
+        game.messages.add(
+            tr!(
+                game.strings,
+                "item.cannot_be_used",
+                game.inventory[inventory_id].name
+            ),
+            WHITE,
+        );
+    }
+}
+
+/// Take a single item out of the inventory, if the player can let go of it.
+fn take_from_inventory(inventory_id: usize, game: &mut Game) -> Option<Object> {
+    let item = &mut game.inventory[inventory_id];
+    if item.count > 1 {
+        return Some(item.take_one());
+    }
+    if item.equipment.is_some() && !item.dequip(&game.strings, &mut game.messages) {
+        return None;
+    }
+    Some(game.inventory.remove(inventory_id))
+}
+
+/// Put an item on the ground, adding it to a matching stack if there is one.
+fn place_item(mut item: Object, x: i32, y: i32, objects: &mut Vec<Object>) {
+    item.set_pos(x, y);
+    let stack = objects
+        .iter_mut()
+        .find(|object| object.pos() == (x, y) && object.stacks_with(&item));
+    match stack {
+        Some(stack) => stack.count += item.count,
+        None => objects.push(item),
+    }
+}
+
+fn drop_item(inventory_id: usize, game: &mut Game, objects: &mut Vec<Object>) {
+    let item = match take_from_inventory(inventory_id, game) {
+        Some(item) => item,
+        None => return,
+    };
+    game.messages
+        .add(tr!(game.strings, "item.dropped", item.name), YELLOW);
+    let (x, y) = objects[PLAYER].pos();
+    place_item(item, x, y, objects);
+}
+
+/// Throw an item from the inventory to a tile the player picks.
+fn throw_item(inventory_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    let range = Some(THROW_RANGE as f32);
+    let (x, y) = match target_tile(tcod, game, objects, range, 0) {
+        Some(position) => position,
+        None => return,
+    };
+    if game.map[(x, y)].blocked {
+        game.messages
+            .add(tr!(game.strings, "item.cant_throw"), WHITE);
+        return;
+    }
+    let item = match take_from_inventory(inventory_id, game) {
+        Some(item) => item,
+        None => return,
+    };
+    game.messages
+        .add(tr!(game.strings, "item.thrown", item.name), YELLOW);
+    place_item(item, x, y, objects);
+}
+
+/// Where the player is aiming, while they're picking a target.
+#[derive(Clone, Copy)]
+struct Targeting {
+    x: i32,
+    y: i32,
+    /// How far around the cursor the spell reaches.
+    radius: i32,
+}
+
+/// return the position of a tile picked with the mouse or the keyboard
+/// in the player's FOV (optionally in a range), or None if cancelled.
+/// `radius` is the area to highlight around the cursor.
+fn target_tile(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &[Object],
+    max_range: Option<f32>,
+    radius: i32,
+) -> Option<(i32, i32)> {
+    use tcod::input::KeyCode::{Enter, Escape, Tab};
+
+    let can_target = |tcod: &Tcod, x: i32, y: i32| {
+        tcod.in_fov(x, y) && max_range.is_none_or(|range| objects[PLAYER].distance(x, y) <= range)
+    };
+
+    if tcod.replay.is_playing() {
+        return match tcod.replay.next() {
+            Some(Decision::Aim(None)) => None,
+            Some(Decision::Aim(Some((x, y)))) if can_target(tcod, x, y) => Some((x, y)),
+            other => tcod
+                .replay
+                .out_of_sync(&format!("expected somewhere to aim, found {:?}", other)),
+        };
+    }
+
+    // start on the closest enemy, if there is one
+    let search_range = max_range.map_or(tcod.config.torch_radius, |range| range as i32);
+    let (mut x, mut y) = closest_monster(|x, y| tcod.in_fov(x, y), objects, search_range)
+        .and_then(|id| object_index(id, objects))
+        .map_or(objects[PLAYER].pos(), |index| objects[index].pos());
+
+    let target = loop {
+        tcod.targeting = Some(Targeting { x, y, radius });
+        // render the screen. this erases the inventory and shows the names of
+        // objects under the mouse.
+        tcod.root.flush();
+        tcod.key = Default::default();
+        tcod.mouse.lbutton_pressed = false;
+        tcod.mouse.rbutton_pressed = false;
+        match input::check_for_event(input::KEY_PRESS | input::MOUSE).map(|e| e.1) {
+            Some(Event::Mouse(m)) => {
+                tcod.mouse = m;
+                if let Some(pos) = screen_to_map(m.cx as i32, m.cy as i32) {
+                    x = pos.0;
+                    y = pos.1;
+                }
+            }
+            Some(Event::Key(k)) => tcod.key = k,
+            None => {}
+        }
+        render_all(tcod, game, objects, false);
+
+        // accept the target if the player clicked or pressed Enter in FOV,
+        // and in case a range is specified, if it's in that range
+        if (tcod.mouse.lbutton_pressed || tcod.key.code == Enter) && can_target(tcod, x, y) {
+            break Some((x, y));
+        }
+
+        if tcod.mouse.rbutton_pressed || tcod.key.code == Escape {
+            break None; // cancel if the player right-clicked or pressed Escape
+        }
+
+        if tcod.key.code == Tab {
+            // jump to the next monster we can target
+            let monsters: Vec<(i32, i32)> = objects
+                .iter()
+                .enumerate()
+                .filter(|&(id, o)| {
+                    id != PLAYER && o.fighter.is_some() && can_target(tcod, o.x, o.y)
+                })
+                .map(|(_, o)| o.pos())
+                .collect();
+            if !monsters.is_empty() {
+                let next = monsters
+                    .iter()
+                    .position(|&pos| pos == (x, y))
+                    .map_or(0, |index| (index + 1) % monsters.len());
+                x = monsters[next].0;
+                y = monsters[next].1;
+            }
+        }
+
+        let direction = tcod
+            .keymap
+            .command_for(tcod.key)
+            .and_then(Command::direction);
+        if let Some((dx, dy)) = direction {
+            if can_target(tcod, x + dx, y + dy) {
+                x += dx;
+                y += dy;
+            }
+        }
+    };
+
+    tcod.targeting = None;
+    tcod.replay.write(&Decision::Aim(target));
+    target
+}
+
+/// returns a picked monster inside FOV up to a range, or None if cancelled
+fn target_monster(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &[Object],
+    max_range: Option<f32>,
+) -> Option<ObjectId> {
+    loop {
+        match target_tile(tcod, game, objects, max_range, 0) {
+            Some((x, y)) => {
+                // return the first clicked monster, otherwise continue looping
+                for (id, obj) in objects.iter().enumerate() {
+                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
+                        return Some(obj.id);
+                    }
+                }
+            }
+            None => return None,
+        }
+    }
+}
+
+/// Can the player's spells pick `object` on their own? It has to be a
+/// monster that's hostile to the player, in view and within `max_range`.
+/// `in_fov` tells whether the player can see a position.
+fn is_valid_target<F: Fn(i32, i32) -> bool>(
+    in_fov: &F,
+    player: &Object,
+    object: &Object,
+    max_range: i32,
+) -> bool {
+    object.fighter.is_some()
+        && object.ai.is_some()
+        && object.faction.is_hostile_to(player.faction)
+        && in_fov(object.x, object.y)
+        && player.distance_to(object) <= max_range as f32
+}
+
+/// find closest enemy, up to a maximum range, and in the player's FOV.
+/// If two are just as close, the first one wins.
+fn closest_monster<F: Fn(i32, i32) -> bool>(
+    in_fov: F,
+    objects: &[Object],
+    max_range: i32,
+) -> Option<ObjectId> {
+    let mut closest_enemy = None;
+    let mut closest_dist = (max_range + 1) as f32; // start with (slightly more than) maximum range
+
+    for (id, object) in objects.iter().enumerate() {
+        if id != PLAYER && is_valid_target(&in_fov, &objects[PLAYER], object, max_range) {
+            // calculate distance between this object and the player
+            let dist = objects[PLAYER].distance_to(object);
+            if dist < closest_dist {
+                // it's closer, so remember it
+                closest_enemy = Some(object.id);
+                closest_dist = dist;
+            }
+        }
+    }
+    closest_enemy
+}
+
+/// Find the current index of the object with the given ID, if it's
+/// still around.
+fn object_index(id: ObjectId, objects: &[Object]) -> Option<usize> {
+    objects.iter().position(|object| object.id == id)
+}
+
+fn drink_strength(
+    _inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.base_power += 1;
+        game.messages
+            .add(tr!(game.strings, "potion.strength"), ORANGE);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+fn drink_vitality(
+    _inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.base_max_hp += VITALITY_BONUS;
+        fighter.hp += VITALITY_BONUS;
+        game.messages
+            .add(tr!(game.strings, "potion.vitality"), LIGHT_GREEN);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+fn drink_agility(
+    _inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.base_defense += 1;
+        game.messages
+            .add(tr!(game.strings, "potion.agility"), LIGHT_SKY);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+mod effects {
+    use super::*;
+
+    /// Who an effect lands on.
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    pub enum TargetKind {
+        /// whoever uses it
+        Caster,
+        /// the closest monster within the range
+        NearestEnemy(i32),
+        /// a monster the player picks, within the range
+        ChosenMonster(i32),
+        /// everyone within the radius of a tile the player picks
+        ChosenTile(i32),
+    }
+
+    impl TargetKind {
+        pub fn range(self) -> i32 {
+            match self {
+                TargetKind::Caster => 0,
+                TargetKind::NearestEnemy(range) => range,
+                TargetKind::ChosenMonster(range) => range,
+                TargetKind::ChosenTile(radius) => radius,
+            }
+        }
+    }
+
+    /// What an effect does to each of its targets.
+    #[derive(Clone, Debug, PartialEq)]
+    pub enum EffectKind {
+        Damage(i32),
+        Heal(i32),
+        /// Replace the target's AI. A `Confused` AI goes back to the
+        /// target's own one when it wears off.
+        ApplyAi(Ai),
+        ApplyStatus(StatusEffect),
+    }
+
+    /// An item or spell described by what it does, instead of by a
+    /// function that does it.
+    #[derive(Clone, Debug)]
+    pub struct Effect {
+        pub target: TargetKind,
+        pub kind: EffectKind,
+        /// asks the player to pick a target
+        pub prompt: Option<&'static str>,
+        /// shown once, with the range or radius
+        pub cast_message: Option<&'static str>,
+        /// shown for every target, with its name and the amount
+        pub hit_message: Option<&'static str>,
+        pub color: Color,
+        /// goes on the tombstone if this kills the player
+        pub cause: Option<&'static str>,
+        /// leave the floor burning, like a fireball does
+        pub sets_fire: bool,
+    }
+
+    /// The effect of an item, if that's all the item is.
+    pub fn item_effect(item: Item, config: &GameConfig) -> Option<Effect> {
+        let effect = match item {
+            Item::Heal => Effect {
+                target: TargetKind::Caster,
+                kind: EffectKind::Heal(config.heal_amount),
+                prompt: None,
+                cast_message: Some("spell.heal.cast"),
+                hit_message: None,
+                color: LIGHT_VIOLET,
+                cause: None,
+                sets_fire: false,
+            },
+            Item::Lightning | Item::LightningWand => Effect {
+                target: TargetKind::NearestEnemy(config.lightning_range),
+                kind: EffectKind::Damage(config.lightning_damage),
+                prompt: None,
+                cast_message: None,
+                hit_message: Some("spell.lightning.cast"),
+                color: LIGHT_BLUE,
+                cause: Some("cause.lightning"),
+                sets_fire: false,
+            },
+            Item::Confuse | Item::ConfusionWand => Effect {
+                target: TargetKind::ChosenMonster(config.confuse_range),
+                kind: EffectKind::ApplyAi(Ai::Confused {
+                    previous_ai: Box::new(Ai::Basic),
+                    num_turns: config.confuse_num_turns,
+                }),
+                prompt: Some("spell.confuse.prompt"),
+                cast_message: None,
+                hit_message: Some("spell.confuse.cast"),
+                color: LIGHT_GREEN,
+                cause: None,
+                sets_fire: false,
+            },
+            Item::Fireball => Effect {
+                target: TargetKind::ChosenTile(config.fireball_radius),
+                kind: EffectKind::Damage(config.fireball_damage),
+                prompt: Some("spell.fireball.prompt"),
+                cast_message: Some("spell.fireball.cast"),
+                hit_message: Some("spell.fireball.burn"),
+                color: ORANGE,
+                cause: Some("cause.fireball"),
+                sets_fire: true,
+            },
+            Item::Haste => Effect {
+                target: TargetKind::Caster,
+                kind: EffectKind::ApplyStatus(StatusEffect::Hasted {
+                    amount: HASTE_AMOUNT,
+                    turns: HASTE_TURNS,
+                }),
+                prompt: None,
+                cast_message: None,
+                hit_message: None,
+                color: YELLOW,
+                cause: None,
+                sets_fire: false,
+            },
+            Item::Invisibility => Effect {
+                target: TargetKind::Caster,
+                kind: EffectKind::ApplyStatus(StatusEffect::Invisible {
+                    turns: INVISIBLE_TURNS,
+                }),
+                prompt: None,
+                cast_message: None,
+                hit_message: None,
+                color: LIGHTER_GREY,
+                cause: None,
+                sets_fire: false,
+            },
+            _ => return None,
+        };
+        Some(effect)
+    }
+
+    /// Do what the effect does to one target. Returns the experience for
+    /// killing it, if it died.
+    pub fn affect(
+        effect: &Effect,
+        target_id: usize,
+        game: &mut Game,
+        objects: &mut [Object],
+    ) -> Option<i32> {
+        let target = &mut objects[target_id];
+        if let (EffectKind::ApplyAi(_), Some(Ai::Boss { .. })) = (&effect.kind, &target.ai) {
+            // bosses are immune
+            game.messages.add(
+                tr!(game.strings, "spell.confuse.resisted", target.name),
+                ORANGE,
+            );
+            return None;
+        }
+        let amount = match effect.kind {
+            EffectKind::Damage(amount) | EffectKind::Heal(amount) => amount,
+            _ => 0,
+        };
+        if let Some(message) = effect.hit_message {
+            game.messages.add(
+                tr!(game.strings, message, target.name, amount),
+                effect.color,
+            );
+        }
+        match effect.kind {
+            EffectKind::Damage(damage) => {
+                if let Some(cause) = effect.cause {
+                    game.last_damage_source = tr!(game.strings, cause);
+                }
+                return target.take_damage(damage, game);
+            }
+            EffectKind::Heal(amount) => target.heal(amount, game),
+            EffectKind::ApplyAi(ref ai) => {
+                let old_ai = target.ai.take().unwrap_or(Ai::Basic);
+                target.ai = Some(match ai.clone() {
+                    // after some turns it will restore the old AI
+                    Ai::Confused { num_turns, .. } => Ai::Confused {
+                        previous_ai: Box::new(old_ai),
+                        num_turns,
+                    },
+                    ai => ai,
+                });
+            }
+            EffectKind::ApplyStatus(status) => target.add_effect(status, game),
+        }
+        None
+    }
+
+    /// Cast an effect as the player: pick the targets, then affect each
+    /// of them.
+    pub fn cast_effect(
+        effect: &Effect,
+        tcod: &mut Tcod,
+        game: &mut Game,
+        objects: &mut [Object],
+    ) -> UseResult {
+        if let Some(prompt) = effect.prompt {
+            game.messages.add(tr!(game.strings, prompt), LIGHT_CYAN);
+        }
+        let (targets, center): (Vec<ObjectId>, _) = match effect.target {
+            TargetKind::Caster => (vec![objects[PLAYER].id], None),
+            TargetKind::NearestEnemy(range) => {
+                let monster = closest_monster(|x, y| tcod.in_fov(x, y), objects, range);
+                (monster.into_iter().collect(), None)
+            }
+            TargetKind::ChosenMonster(range) => {
+                let monster = target_monster(tcod, game, objects, Some(range as f32));
+                (monster.into_iter().collect(), None)
+            }
+            TargetKind::ChosenTile(radius) => {
+                let center = match target_tile(tcod, game, objects, None, radius) {
+                    Some(tile_pos) => tile_pos,
+                    None => return UseResult::Cancelled,
+                };
+                (caught_in_blast(center, radius, game, objects), Some(center))
+            }
+        };
+        let targets = match check_targets(effect, targets, center, game, objects) {
+            Some(targets) => targets,
+            None => return UseResult::Cancelled,
+        };
+
+        // show the spell on its way
+        match (&effect.target, center) {
+            (TargetKind::NearestEnemy(_), _) => {
+                for &id in &targets {
+                    if let Some(index) = object_index(id, objects) {
+                        let (from, to) = (objects[PLAYER].pos(), objects[index].pos());
+                        animate_bolt(tcod, game, objects, from, to, effect.color);
+                    }
+                }
+            }
+            (_, Some(center)) => {
+                let radius = effect.target.range();
+                animate_blast(tcod, game, objects, center, radius, effect.color);
+            }
+            _ => {}
+        }
+
+        apply_effect(effect, &targets, center, game, objects);
+        UseResult::UsedUp
+    }
+
+    /// Everyone a blast at `center` reaches. It's decided before anyone
+    /// takes damage.
+    pub fn caught_in_blast(
+        center: (i32, i32),
+        radius: i32,
+        game: &Game,
+        objects: &[Object],
+    ) -> Vec<ObjectId> {
+        let (x, y) = center;
+        objects
+            .iter()
+            .filter(|obj| {
+                obj.distance(x, y) <= radius as f32
+                    && obj.fighter.is_some()
+                    && blast_reaches(center, obj.pos(), &game.map)
+            })
+            .map(|obj| obj.id)
+            .collect()
+    }
+
+    /// Make sure the effect lands on someone, and announce it. Returns the
+    /// targets worth affecting, or `None` if there's no point casting it.
+    pub fn check_targets(
+        effect: &Effect,
+        mut targets: Vec<ObjectId>,
+        center: Option<(i32, i32)>,
+        game: &mut Game,
+        objects: &[Object],
+    ) -> Option<Vec<ObjectId>> {
+        if targets.is_empty() && center.is_none() {
+            // no enemy found within maximum range
+            game.messages.add(tr!(game.strings, "spell.no_enemy"), RED);
+            return None;
+        }
+        if let EffectKind::Heal(_) = effect.kind {
+            // no point in healing someone who isn't hurt
+            targets.retain(|&id| {
+                object_index(id, objects).is_some_and(|index| {
+                    let target = &objects[index];
+                    target.fighter.is_some_and(|f| f.hp < target.max_hp(game))
+                })
+            });
+            if targets.is_empty() {
+                game.messages
+                    .add(tr!(game.strings, "spell.heal.full_health"), RED);
+                return None;
+            }
+        }
+        if let Some(message) = effect.cast_message {
+            game.messages.add(
+                tr!(game.strings, message, effect.target.range()),
+                effect.color,
+            );
+        }
+        Some(targets)
+    }
+
+    /// Affect each of the targets, give the player the experience for the
+    /// ones that died and leave the flames behind.
+    pub fn apply_effect(
+        effect: &Effect,
+        targets: &[ObjectId],
+        center: Option<(i32, i32)>,
+        game: &mut Game,
+        objects: &mut [Object],
+    ) {
+        let mut xp_to_gain = 0;
+        for &id in targets {
+            let index = match object_index(id, objects) {
+                Some(index) => index,
+                None => continue,
+            };
+            if let Some(xp) = affect(effect, index, game, objects) {
+                if index != PLAYER {
+                    // Don't reward the player for hurting themself!
+                    xp_to_gain += xp;
+                }
+            }
+        }
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
+
+        if let (true, Some((x, y))) = (effect.sets_fire, center) {
+            // the flames linger for a little while
+            set_fire(x, y, effect.target.range(), game);
+        }
+    }
+}
+
+use effects::{affect, cast_effect, item_effect, Effect, EffectKind};
+
+fn cast_item_effect(
+    inventory_id: usize,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let item = game.inventory[inventory_id].item;
+    match item.and_then(|item| item_effect(item, &tcod.config)) {
+        Some(effect) => cast_effect(&effect, tcod, game, objects),
+        None => UseResult::Cancelled,
+    }
+}
+
+/// Lift the curse off every cursed item in the inventory. Returns
+/// whether there were any.
+fn lift_curses(inventory: &mut [Object]) -> bool {
+    let mut lifted = false;
+    for item in inventory {
+        if let Some(ref mut equipment) = item.equipment {
+            if equipment.cursed {
+                equipment.cursed = false;
+                equipment.curse_known = false;
+                lifted = true;
+            }
+        }
+    }
+    lifted
+}
+
+fn cast_remove_curse(
+    _inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    if lift_curses(&mut game.inventory) {
+        game.messages
+            .add(tr!(game.strings, "spell.remove_curse.lifted"), LIGHT_VIOLET);
+    } else {
+        game.messages.add(
+            tr!(game.strings, "spell.remove_curse.nothing"),
+            LIGHT_VIOLET,
+        );
+    }
+    UseResult::UsedUp
+}
+
+/// Enchant `item`. A cursed one has the curse lifted instead: that takes
+/// all the scroll's magic, so it doesn't get any better.
+fn enchant_item(item: &mut Object, strings: &Strings, messages: &mut Messages) {
+    let equipment = match item.equipment.as_mut() {
+        Some(equipment) => equipment,
+        None => return,
+    };
+    if equipment.cursed {
+        equipment.cursed = false;
+        equipment.curse_known = false;
+        messages.add(
+            tr!(strings, "spell.enchant.uncursed", item.name),
+            LIGHT_BLUE,
+        );
+        return;
+    }
+    if item.enchantment >= MAX_ENCHANTMENT {
+        messages.add(tr!(strings, "spell.enchant.wasted", item.name), ORANGE);
+        return;
+    }
+
+    equipment.enchant();
+    let base_name = item.base_name().to_string();
+    item.enchantment += 1;
+    item.name = tr!(strings, "item.enchanted", base_name, item.enchantment);
+    item.unenchanted_name = Some(base_name.clone());
+    messages.add(tr!(strings, "spell.enchant.done", base_name), LIGHT_BLUE);
+}
+
+fn cast_enchant(
+    _inventory_id: usize,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    if game.inventory.iter().all(|item| item.equipment.is_none()) {
+        game.messages
+            .add(tr!(game.strings, "spell.enchant.nothing"), WHITE);
+        return UseResult::Cancelled;
+    }
+    let header = tr!(game.strings, "spell.enchant.prompt");
+    let target = decide(tcod, game.inventory.len(), |root| {
+        filtered_inventory_menu(
+            &game.inventory,
+            |item| item.equipment.is_some(),
+            &header,
+            &game.strings,
+            root,
+        )
+    });
+    match target {
+        Some(index) => {
+            enchant_item(
+                &mut game.inventory[index],
+                &game.strings,
+                &mut game.messages,
+            );
+            UseResult::UsedUp
+        }
+        None => UseResult::Cancelled,
+    }
+}
+
+fn cast_teleport(
+    _inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    for _ in 0..TELEPORT_TRIES {
+        let x = game.rng.gen_range(0, MAP_WIDTH);
+        let y = game.rng.gen_range(0, MAP_HEIGHT);
+        let stairs = objects
+            .iter()
+            .any(|object| object.pos() == (x, y) && object.is_stairs());
+        if !is_blocked(x, y, &game.map, objects) && !stairs {
+            objects[PLAYER].set_pos(x, y);
+            game.messages
+                .add(tr!(game.strings, "spell.teleport.cast"), LIGHT_VIOLET);
+            return UseResult::UsedUp;
+        }
+    }
+    game.messages
+        .add(tr!(game.strings, "spell.teleport.failed"), WHITE);
+    UseResult::Cancelled
+}
+
+fn cast_slow(
+    _inventory_id: usize,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    game.messages
+        .add(tr!(game.strings, "spell.slow.prompt"), LIGHT_CYAN);
+    let monster_id = target_monster(tcod, game, objects, Some(SLOW_RANGE as f32))
+        .and_then(|id| object_index(id, objects));
+    match monster_id {
+        Some(monster_id) => {
+            let slow = StatusEffect::Slowed {
+                amount: SLOW_AMOUNT,
+                turns: SLOW_TURNS,
+            };
+            objects[monster_id].add_effect(slow, game);
+            UseResult::UsedUp
+        }
+        None => UseResult::Cancelled,
+    }
+}
+
+fn carry_light(
+    inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    game.messages.add(
+        tr!(
+            game.strings,
+            "light.carried",
+            game.inventory[inventory_id].name
+        ),
+        WHITE,
+    );
+    UseResult::UsedAndKept
+}
+
+/// How far the player can see with the lights they're carrying.
+fn light_radius(game: &Game, config: &GameConfig) -> i32 {
+    let has = |item| game.inventory.iter().any(|i| i.item == Some(item));
+    let torch_lit = game
+        .inventory
+        .iter()
+        .any(|i| i.item == Some(Item::Torch) && i.charges != Some(0));
+    if has(Item::Lantern) {
+        config.lantern_radius
+    } else if torch_lit {
+        config.torch_radius
+    } else {
+        config.dark_radius
+    }
+}
+
+/// Burn the player's torch down by a turn, unless they have a lantern.
+fn burn_torch(game: &mut Game) {
+    if game
+        .inventory
+        .iter()
+        .any(|item| item.item == Some(Item::Lantern))
+    {
+        return;
+    }
+    let torch = game
+        .inventory
+        .iter()
+        .position(|item| item.item == Some(Item::Torch));
+    if let Some(torch) = torch {
+        let burnt_out = match game.inventory[torch].charges.as_mut() {
+            Some(fuel) => {
+                *fuel = fuel.saturating_sub(1);
+                *fuel == 0
+            }
+            None => false,
+        };
+        if burnt_out {
+            game.inventory.remove(torch);
+            game.messages
+                .add(tr!(game.strings, "light.torch_died"), ORANGE);
+        }
+    }
+}
+
+fn eat_corpse(
+    inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    if game.hunger <= 0 {
+        game.messages
+            .add(tr!(game.strings, "food.not_hungry"), WHITE);
+        return UseResult::Cancelled;
+    }
+    game.hunger = cmp::max(0, game.hunger - CORPSE_NUTRITION);
+    game.messages.add(
+        tr!(
+            game.strings,
+            "corpse.eat",
+            game.inventory[inventory_id].name
+        ),
+        LIGHT_GREEN,
+    );
+    UseResult::UsedUp
+}
+
+fn eat(
+    _inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    if game.hunger <= 0 {
+        game.messages
+            .add(tr!(game.strings, "food.not_hungry"), WHITE);
+        return UseResult::Cancelled;
+    }
+    game.hunger = cmp::max(0, game.hunger - FOOD_NUTRITION);
+    game.messages
+        .add(tr!(game.strings, "food.eat"), LIGHT_GREEN);
+    UseResult::UsedUp
+}
+
+fn cast_magic_missile(
+    _inventory_id: usize,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    game.messages
+        .add(tr!(game.strings, "spell.magic_missile.prompt"), LIGHT_CYAN);
+    let monster_id = target_monster(tcod, game, objects, Some(MISSILE_RANGE as f32))
+        .and_then(|id| object_index(id, objects));
+    let monster_id = match monster_id {
+        Some(monster_id) => monster_id,
+        None => return UseResult::Cancelled,
+    };
+    game.messages.add(
+        tr!(
+            game.strings,
+            "spell.magic_missile.cast",
+            objects[monster_id].name,
+            MISSILE_DAMAGE
+        ),
+        LIGHT_BLUE,
+    );
+    game.last_damage_source = tr!(game.strings, "cause.magic_missile");
+    if let Some(xp) = objects[monster_id].take_damage(MISSILE_DAMAGE, game) {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+    }
+    UseResult::UsedUp
+}
+
+fn cast_blink(
+    _inventory_id: usize,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    game.messages
+        .add(tr!(game.strings, "spell.blink.prompt"), LIGHT_CYAN);
+    let (x, y) = match target_tile(tcod, game, objects, Some(BLINK_RANGE as f32), 0) {
+        Some(tile) => tile,
+        None => return UseResult::Cancelled,
+    };
+    if is_blocked(x, y, &game.map, objects) {
+        game.messages
+            .add(tr!(game.strings, "spell.blink.blocked"), RED);
+        return UseResult::Cancelled;
+    }
+    objects[PLAYER].set_pos(x, y);
+    game.messages
+        .add(tr!(game.strings, "spell.blink.cast"), LIGHT_BLUE);
+    UseResult::UsedUp
+}
+
+/// The spells the player can learn.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Spell {
+    MagicMissile,
+    Heal,
+    Blink,
+}
+
+impl Spell {
+    const ALL: [Spell; 3] = [Spell::MagicMissile, Spell::Heal, Spell::Blink];
+
+    /// The part of the string keys for this spell.
+    fn key(self) -> &'static str {
+        match self {
+            Spell::MagicMissile => "magic_missile",
+            Spell::Heal => "heal",
+            Spell::Blink => "blink",
+        }
+    }
+
+    fn name(self, strings: &Strings) -> String {
+        tr!(strings, &format!("spellbook.{}", self.key()))
+    }
+
+    /// How much mana casting it takes.
+    fn cost(self) -> i32 {
+        match self {
+            Spell::MagicMissile => 5,
+            Spell::Heal => 10,
+            Spell::Blink => 8,
+        }
+    }
+}
+
+/// What kind of adventurer the player is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum Class {
+    // what the games saved before there were classes get
+    #[default]
+    Warrior,
+    Rogue,
+    Mage,
+}
+
+impl Class {
+    const ALL: [Class; 3] = [Class::Warrior, Class::Rogue, Class::Mage];
+
+    /// The part of the string keys for this class.
+    fn key(self) -> &'static str {
+        match self {
+            Class::Warrior => "warrior",
+            Class::Rogue => "rogue",
+            Class::Mage => "mage",
+        }
+    }
+
+    fn name(self, strings: &Strings) -> String {
+        tr!(strings, &format!("class.{}", self.key()))
+    }
+}
+
+/// How hard the game is. It's picked when starting a new game and kept
+/// for the whole run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum Difficulty {
+    Easy,
+    // what the games saved before there were difficulties get
+    #[default]
+    Normal,
+    Hard,
+}
+
+/// What a difficulty changes.
+struct DifficultyRules {
+    /// The maximum number of monsters in a room, in percent of the usual.
+    monsters_percent: u32,
+    /// The player's starting health, in percent of their class's.
+    starting_hp_percent: i32,
+    /// How many levels early the monsters get tougher.
+    scaling_head_start: u32,
+    /// Whether the player heals when taking the stairs.
+    rest_heal: bool,
+}
+
+/// The rules for each difficulty, in the order of `Difficulty`.
+const DIFFICULTY_RULES: [DifficultyRules; 3] = [
+    // Easy
+    DifficultyRules {
+        monsters_percent: 50,
+        starting_hp_percent: 150,
+        scaling_head_start: 0,
+        rest_heal: true,
+    },
+    // Normal
+    DifficultyRules {
+        monsters_percent: 100,
+        starting_hp_percent: 100,
+        scaling_head_start: 0,
+        rest_heal: true,
+    },
+    // Hard
+    DifficultyRules {
+        monsters_percent: 150,
+        starting_hp_percent: 100,
+        scaling_head_start: 2,
+        rest_heal: false,
+    },
+];
+
+impl Difficulty {
+    const ALL: [Difficulty; 3] = [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard];
+
+    fn rules(self) -> &'static DifficultyRules {
+        &DIFFICULTY_RULES[self as usize]
+    }
+
+    /// The part of the string keys for this difficulty.
+    fn key(self) -> &'static str {
+        match self {
+            Difficulty::Easy => "easy",
+            Difficulty::Normal => "normal",
+            Difficulty::Hard => "hard",
+        }
+    }
+
+    fn name(self, strings: &Strings) -> String {
+        tr!(strings, &format!("difficulty.{}", self.key()))
+    }
+}
+
+/// The order objects are drawn in, from the bottom up.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+enum RenderOrder {
+    Corpse,
+    #[default]
+    Item,
+    Stairs,
+    Actor,
+}
+
+/// How many frames go into one line of the frame time log.
+const FRAME_TIMES_EVERY: u32 = 100;
+
+/// Measures how long drawing the screen takes. Only does anything when
+/// the `TOMBS_FRAME_TIMES` environment variable is set.
+struct FrameTimes {
+    enabled: bool,
+    frames: u32,
+    drawn: u32,
+    drawing: Duration,
+}
+
+impl FrameTimes {
+    fn new() -> Self {
+        FrameTimes {
+            enabled: env::var_os("TOMBS_FRAME_TIMES").is_some(),
+            frames: 0,
+            drawn: 0,
+            drawing: Duration::from_secs(0),
+        }
+    }
+
+    /// Count a frame, with how long drawing it took if it was drawn.
+    fn record(&mut self, drawing: Option<Duration>) {
+        if !self.enabled {
+            return;
+        }
+        self.frames += 1;
+        if let Some(time) = drawing {
+            self.drawn += 1;
+            self.drawing += time;
+        }
+        if self.frames == FRAME_TIMES_EVERY {
+            let average = if self.drawn > 0 {
+                self.drawing / self.drawn
+            } else {
+                Duration::from_secs(0)
+            };
+            eprintln!(
+                "{} of {} frames drawn, {:.2} ms per drawn frame",
+                self.drawn,
+                self.frames,
+                average.as_secs_f64() * 1000.0
+            );
+            self.frames = 0;
+            self.drawn = 0;
+            self.drawing = Duration::from_secs(0);
+        }
+    }
+}
+
+/// Cast one of the player's spells, if they have the mana for it.
+fn cast_spell(
+    spell: Spell,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> PlayerAction {
+    let mana = objects[PLAYER].fighter.map_or(0, |f| f.mana);
+    if mana < spell.cost() {
+        game.messages.add(
+            tr!(game.strings, "spellbook.no_mana", spell.name(&game.strings)),
+            RED,
+        );
+        return PlayerAction::DidntTakeTurn;
+    }
+    // spells don't come from the inventory, so there's no item to pass
+    let result = match spell {
+        Spell::MagicMissile => cast_magic_missile(0, tcod, game, objects),
+        Spell::Heal => {
+            let heal = item_effect(Item::Heal, &tcod.config).unwrap();
+            cast_effect(&heal, tcod, game, objects)
+        }
+        Spell::Blink => cast_blink(0, tcod, game, objects),
+    };
+    match result {
+        UseResult::Cancelled => {
+            game.messages
+                .add(tr!(game.strings, "item.cancelled"), WHITE);
+            PlayerAction::DidntTakeTurn
+        }
+        UseResult::UsedUp | UseResult::UsedAndKept => {
+            objects[PLAYER].fighter.as_mut().unwrap().mana -= spell.cost();
+            PlayerAction::TookTurn
+        }
+    }
+}
+
+/// Show a projectile flying from one tile to another.
+fn animate_projectile(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &[Object],
+    from: (i32, i32),
+    to: (i32, i32),
+    color: Color,
+) {
+    // point it the way it's flying
+    let glyph = match (to.0 - from.0, to.1 - from.1) {
+        (0, _) => '|',
+        (_, 0) => '-',
+        (dx, dy) if (dx > 0) == (dy > 0) => '\\',
+        _ => '/',
+    };
+    for (x, y) in tcod::line::Line::new(from, to) {
+        render_all(tcod, game, objects, false);
+        tcod.root.set_char(x, y, glyph);
+        tcod.root.set_char_foreground(x, y, color);
+        tcod.root.flush();
+    }
+}
+
+/// Play a short animation over the map. The world is rendered before
+/// every frame, and `draw_frame` draws the frame with the given number
+/// on top of it.
+fn animate<F>(frames: u32, tcod: &mut Tcod, game: &mut Game, objects: &[Object], mut draw_frame: F)
+where
+    F: FnMut(u32, &mut Root),
+{
+    if !tcod.settings.animations {
+        return;
+    }
+    for frame in 0..frames {
+        render_all(tcod, game, objects, false);
+        draw_frame(frame, &mut tcod.root);
+        tcod.root.flush();
+        tcod::system::sleep(ANIMATION_FRAME);
+    }
+    // anything pressed while the animation played is thrown away, rather
+    // than all of it happening at once afterwards
+    while input::check_for_event(input::KEY_PRESS | input::MOUSE_PRESS).is_some() {}
+}
+
+/// A bolt of lightning crackling its way from one tile to another.
+fn animate_bolt(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &[Object],
+    from: (i32, i32),
+    to: (i32, i32),
+    color: Color,
+) {
+    let path: Vec<(i32, i32)> = tcod::line::Line::new(from, to).collect();
+    animate(BOLT_FRAMES, tcod, game, objects, |frame, root| {
+        let shown = path.len() * (frame as usize + 1) / BOLT_FRAMES as usize;
+        for (i, &(x, y)) in path.iter().take(shown).enumerate() {
+            // alternate the colors so it flickers
+            let bright = (i as u32 + frame).is_multiple_of(2);
+            root.set_char(x, y, '*');
+            root.set_char_foreground(x, y, if bright { WHITE } else { color });
+        }
+    });
+}
+
+/// Light up every visible tile a blast reaches.
+fn animate_blast(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &[Object],
+    (x, y): (i32, i32),
+    radius: i32,
+    color: Color,
+) {
+    let mut tiles = vec![];
+    for dy in -radius..=radius {
+        for dx in -radius..=radius {
+            let pos = (x + dx, y + dy);
+            let in_radius = ((dx * dx + dy * dy) as f32).sqrt() <= radius as f32;
+            if in_radius && tcod.in_fov(pos.0, pos.1) && blast_reaches((x, y), pos, &game.map) {
+                tiles.push(pos);
+            }
+        }
+    }
+    let colors = [color, YELLOW, color];
+    animate(colors.len() as u32, tcod, game, objects, |frame, root| {
+        for &(x, y) in &tiles {
+            root.set_char_background(x, y, colors[frame as usize], BackgroundFlag::Set);
+        }
+    });
+}
+
+/// Flash the tiles of everyone who got hurt in melee since last time.
+fn animate_hits(tcod: &mut Tcod, game: &mut Game, objects: &[Object]) {
+    let hits: Vec<(i32, i32)> = std::mem::take(&mut game.hits)
+        .into_iter()
+        .filter(|&(x, y)| tcod.in_fov(x, y))
+        .collect();
+    if hits.is_empty() {
+        return;
+    }
+    animate(HIT_FLASH_FRAMES, tcod, game, objects, |_, root| {
+        for &(x, y) in &hits {
+            let foreground = root.get_char_foreground(x, y);
+            let background = root.get_char_background(x, y);
+            root.set_char_foreground(x, y, background);
+            root.set_char_background(x, y, foreground, BackgroundFlag::Set);
+        }
+    });
+}
+
+/// Color the background of the outermost tiles of the screen.
+fn draw_border(root: &mut Root, color: Color) {
+    for x in 0..SCREEN_WIDTH {
+        root.set_char_background(x, 0, color, BackgroundFlag::Set);
+        root.set_char_background(x, SCREEN_HEIGHT - 1, color, BackgroundFlag::Set);
+    }
+    for y in 0..SCREEN_HEIGHT {
+        root.set_char_background(0, y, color, BackgroundFlag::Set);
+        root.set_char_background(SCREEN_WIDTH - 1, y, color, BackgroundFlag::Set);
+    }
+}
+
+/// Show a single red frame around the screen and under the player.
+fn flash_damage(tcod: &mut Tcod, game: &mut Game, objects: &[Object]) {
+    render_all(tcod, game, objects, false);
+    draw_border(&mut tcod.root, RED);
+    let (x, y) = objects[PLAYER].pos();
+    tcod.root
+        .set_char_background(x, y, RED, BackgroundFlag::Set);
+    tcod.root.flush();
+    tcod::system::sleep(ANIMATION_FRAME);
+}
+
+/// Something the player can shoot or throw at a monster.
+#[derive(Clone, Copy, Debug)]
+struct Missile {
+    item: Item,
+    damage: Dice,
+    range: i32,
+    /// The chance to find it again after a miss.
+    recover_chance: f32,
+    /// The first part of its message ids, e.g. "fire" for `fire.hit`.
+    key: &'static str,
+    cause: &'static str,
+}
+
+/// Shoot an arrow with the equipped bow.
+fn shoot(
+    _inventory_id: usize,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let dice = match get_equipped_in_slot(Slot::Back, &game.inventory) {
+        Some(bow) => game.inventory[bow]
+            .equipment
+            .and_then(|bow| bow.damage)
+            .unwrap_or_default(),
+        None => {
+            game.messages.add(tr!(game.strings, "fire.no_bow"), RED);
+            return UseResult::Cancelled;
+        }
+    };
+    let arrow = Missile {
+        item: Item::Arrows,
+        damage: dice,
+        range: BOW_RANGE,
+        recover_chance: ARROW_RECOVER_CHANCE,
+        key: "fire",
+        cause: "cause.arrow",
+    };
+    hurl(arrow, tcod, game, objects)
+}
+
+/// Throw a knife at a monster. It can always be picked up again if it
+/// misses.
+fn throw_knife(
+    _inventory_id: usize,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> UseResult {
+    let knife = Missile {
+        item: Item::ThrowingKnife,
+        damage: Dice::new(1, 4, 0),
+        range: THROW_RANGE,
+        recover_chance: 1.0,
+        key: "throw",
+        cause: "cause.knife",
+    };
+    hurl(knife, tcod, game, objects)
+}
+
+/// Send a missile at a monster the player picks.
+fn hurl(missile: Missile, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> UseResult {
+    let message = |what: &str| format!("{}.{}", missile.key, what);
+    game.messages
+        .add(tr!(game.strings, &message("prompt")), LIGHT_CYAN);
+    let target_id = match target_monster(tcod, game, objects, Some(missile.range as f32))
+        .and_then(|id| object_index(id, objects))
+    {
+        Some(target_id) => target_id,
+        None => return UseResult::Cancelled,
+    };
+    objects[PLAYER].become_visible(game);
+    let (x, y) = objects[target_id].pos();
+    animate_projectile(
+        tcod,
+        game,
+        objects,
+        objects[PLAYER].pos(),
+        (x, y),
+        LIGHT_GREY,
+    );
+
+    let hit_chance = BASE_HIT_CHANCE - DODGE_PER_DEFENSE * objects[target_id].defense(game);
+    if game.rng.gen_range(0, 100) >= hit_chance {
+        game.messages.add(
+            tr!(game.strings, &message("miss"), objects[target_id].name),
+            WHITE,
+        );
+        if game.rng.gen::<f32>() < missile.recover_chance {
+            let mut item = create_item(missile.item, x, y, &tcod.config.spawns, &mut game.rng);
+            item.count = 1;
+            game.pending_spawns.push(item);
+        }
+        return UseResult::UsedUp;
+    }
+
+    let falloff = (objects[PLAYER].distance(x, y) / ARROW_FALLOFF) as i32;
+    let damage = missile.damage.roll(&mut game.rng) + objects[PLAYER].power(game)
+        - objects[target_id].defense(game)
+        - falloff;
+    if damage > 0 {
+        game.messages.add(
+            tr!(
+                game.strings,
+                &message("hit"),
+                objects[target_id].name,
+                damage
+            ),
+            WHITE,
+        );
+        game.last_damage_source = tr!(game.strings, missile.cause);
+        if let Some(xp) = objects[target_id].take_damage(damage, game) {
+            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+        }
+    } else {
+        game.messages.add(
+            tr!(game.strings, &message("no_effect"), objects[target_id].name),
+            WHITE,
+        );
+    }
+    // it knows where that came from
+    if objects[target_id].alive && objects[target_id].ai == Some(Ai::Basic) {
+        objects[target_id].ai = Some(Ai::Chasing {
+            last_seen: objects[PLAYER].pos(),
+            num_turns: CHASE_NUM_TURNS,
+        });
+    }
+    UseResult::UsedUp
+}
+
+/// Shoot the equipped bow, if the player has one and any arrows.
+fn fire(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) -> PlayerAction {
+    if get_equipped_in_slot(Slot::Back, &game.inventory).is_none() {
+        game.messages.add(tr!(game.strings, "fire.no_bow"), RED);
+        return PlayerAction::DidntTakeTurn;
+    }
+    let arrows = game
+        .inventory
+        .iter()
+        .position(|item| item.item == Some(Item::Arrows));
+    let arrows = match arrows {
+        Some(arrows) => arrows,
+        None => {
+            game.messages.add(tr!(game.strings, "fire.no_arrows"), RED);
+            return PlayerAction::DidntTakeTurn;
+        }
+    };
+    match shoot(arrows, tcod, game, objects) {
+        UseResult::Cancelled => {
+            game.messages
+                .add(tr!(game.strings, "item.cancelled"), WHITE);
+            PlayerAction::DidntTakeTurn
+        }
+        UseResult::UsedUp | UseResult::UsedAndKept => {
+            if game.inventory[arrows].count > 1 {
+                game.inventory[arrows].count -= 1;
+            } else {
+                game.inventory.remove(arrows);
+            }
+            PlayerAction::TookTurn
+        }
+    }
+}
+
+fn toggle_equipment(
+    inventory_id: usize,
+    _tcod: &mut Tcod,
+    game: &mut Game,
+    _objects: &mut [Object],
+) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        game.inventory[inventory_id].dequip(&game.strings, &mut game.messages);
+    } else {
+        // use a free slot if there is one. If not, dequip whatever is in
+        // the item's slot first
+        match free_slot_for(equipment.slot, &game.inventory) {
+            Some(free) => game.inventory[inventory_id].set_slot(free),
+            None => {
+                if let Some(current) = get_equipped_in_slot(equipment.slot, &game.inventory) {
+                    if !game.inventory[current].dequip(&game.strings, &mut game.messages) {
+                        // what's there is staying there
+                        return UseResult::UsedAndKept;
+                    }
+                }
+            }
+        }
+        game.inventory[inventory_id].equip(&game.strings, &mut game.messages);
+    }
+    UseResult::UsedAndKept
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// An object that can be equipped, yielding bonuses.
+struct Equipment {
+    slot: Slot,
+    equipped: bool,
+    max_hp_bonus: i32,
+    defense_bonus: i32,
+    power_bonus: i32,
+    /// The damage a weapon does; `None` for everything else.
+    #[serde(default)]
+    damage: Option<Dice>,
+    /// Cursed equipment can't be taken off, and its bonuses turn into
+    /// penalties once it's been worn.
+    #[serde(default)]
+    cursed: bool,
+    /// Whether the player has found out about the curse.
+    #[serde(default)]
+    curse_known: bool,
+}
+
+impl Equipment {
+    /// A curse the wearer has found out about turns the bonuses into
+    /// penalties. The bonuses themselves stay as they are, so lifting
+    /// the curse brings them right back.
+    fn curse_sign(&self) -> i32 {
+        if self.cursed && self.curse_known {
+            -1
+        } else {
+            1
+        }
+    }
+
+    pub fn power(&self) -> i32 {
+        self.curse_sign() * self.power_bonus
+    }
+
+    pub fn defense(&self) -> i32 {
+        self.curse_sign() * self.defense_bonus
+    }
+
+    pub fn max_hp(&self) -> i32 {
+        self.curse_sign() * self.max_hp_bonus
+    }
+
+    /// Make it one better at what the slot it's worn in is for:
+    /// weapons, bows and rings hit harder, shields and armor protect
+    /// better.
+    fn enchant(&mut self) {
+        match self.slot {
+            Slot::LeftHand | Slot::RightHand if self.damage.is_some() => self.power_bonus += 1,
+            Slot::LeftHand | Slot::RightHand | Slot::Head | Slot::Body => self.defense_bonus += 1,
+            Slot::LeftRing | Slot::RightRing | Slot::Back => self.power_bonus += 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Slot {
+    LeftHand,
+    RightHand,
+    Head,
+    Body,
+    LeftRing,
+    RightRing,
+    Back,
+}
+
+impl Slot {
+    /// The part of the string keys for this slot.
+    fn key(self) -> &'static str {
+        match self {
+            Slot::LeftHand => "left_hand",
+            Slot::RightHand => "right_hand",
+            Slot::Head => "head",
+            Slot::Body => "body",
+            Slot::LeftRing => "left_ring",
+            Slot::RightRing => "right_ring",
+            Slot::Back => "back",
+        }
+    }
+
+    fn name(self, strings: &Strings) -> String {
+        tr!(strings, &format!("slot.{}", self.key()))
+    }
+}
+
+impl Slot {
+    const ALL: [Slot; 7] = [
+        Slot::RightHand,
+        Slot::LeftHand,
+        Slot::Head,
+        Slot::Body,
+        Slot::RightRing,
+        Slot::LeftRing,
+        Slot::Back,
+    ];
+}
+
+impl Slot {
+    /// The slots an item made for this slot can be worn in, in order of
+    /// preference.
+    fn interchangeable(self) -> &'static [Slot] {
+        match self {
+            Slot::LeftRing | Slot::RightRing => &[Slot::RightRing, Slot::LeftRing],
+            Slot::LeftHand => &[Slot::LeftHand],
+            Slot::RightHand => &[Slot::RightHand],
+            Slot::Head => &[Slot::Head],
+            Slot::Body => &[Slot::Body],
+            Slot::Back => &[Slot::Back],
+        }
+    }
+}
+
+/// The first unused slot an item made for `slot` could be worn in.
+fn free_slot_for(slot: Slot, inventory: &[Object]) -> Option<Slot> {
+    slot.interchangeable()
+        .iter()
+        .cloned()
+        .find(|&slot| get_equipped_in_slot(slot, inventory).is_none())
+}
+
+impl Object {
+    /// Move a piece of equipment to a different slot. Only call this
+    /// while it's not equipped.
+    fn set_slot(&mut self, slot: Slot) {
+        if let Some(ref mut equipment) = self.equipment {
+            equipment.slot = slot;
+        }
+    }
+}
+
+/// What ended up on a freshly generated level.
+struct LevelSummary {
+    rooms: usize,
+    monsters: usize,
+    items: usize,
+    shop: bool,
+    boss: bool,
+}
+
+impl LevelSummary {
+    /// The id of the message with the player's first impression of the
+    /// level.
+    fn feeling(&self) -> &'static str {
+        let per_room = |count: usize| count as f32 / cmp::max(self.rooms, 1) as f32;
+        if self.boss {
+            "feeling.boss"
+        } else if per_room(self.monsters) >= DANGER_PER_ROOM {
+            "feeling.danger"
+        } else if per_room(self.items) >= TREASURE_PER_ROOM {
+            "feeling.treasure"
+        } else if self.shop {
+            "feeling.shop"
+        } else if per_room(self.monsters) <= QUIET_PER_ROOM {
+            "feeling.quiet"
+        } else {
+            "feeling.ordinary"
+        }
+    }
+}
+
+fn make_map(
+    objects: &mut Vec<Object>,
+    level: u32,
+    difficulty: Difficulty,
+    config: &GameConfig,
+    rng: &mut StdRng,
+) -> (Map, LevelSummary) {
+    // fill map with "blocked" tiles
+    let mut map = Map::new(Tile::wall());
+
+    // Player is the first element, remove everything else.
+    // NOTE: works only when the player is the first object!
+    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
+    objects.truncate(1);
+
+    let rooms = dig_rooms(
+        &mut map,
+        config.max_rooms,
+        config.room_min_size,
+        config.room_max_size,
+        rng,
+        |index, room, map, rng| {
+            if index == 0 {
+                // this is the first room, where the player starts at
+                let (x, y) = room.center();
+                objects[PLAYER].set_pos(x, y);
+            }
+            // add some content to this room, such as monsters. the player is
+            // already in place, so `is_blocked` keeps everything off their tile
+            place_objects(room, map, objects, level, difficulty, &config.spawns, rng);
+        },
+    );
+
+    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+    if level >= FINAL_LEVEL {
+        // the final level has no stairs, just the king in his hall
+        let x = (last_room_x - BOSS_ROOM_WIDTH / 2).clamp(0, MAP_WIDTH - BOSS_ROOM_WIDTH - 1);
+        let y = (last_room_y - BOSS_ROOM_HEIGHT / 2).clamp(0, MAP_HEIGHT - BOSS_ROOM_HEIGHT - 1);
+        create_room(Rect::new(x, y, BOSS_ROOM_WIDTH, BOSS_ROOM_HEIGHT), &mut map);
+        let spawns = &config.spawns;
+        let mut king = create_monster("ancient king", last_room_x, last_room_y, spawns);
+        // he drops it when he dies
+        let crown = create_item(Item::Crown, last_room_x, last_room_y, spawns, rng);
+        king.carried = Some(Box::new(crown));
+        objects.push(king);
+    } else {
+        // create stairs at the center of the last room
+        let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs", WHITE, false);
+        stairs.always_visible = true;
+        stairs.render_order = RenderOrder::Stairs;
+        objects.push(stairs);
+    }
+
+    // some levels have a shop somewhere between the first and the last room
+    if (SHOP_MIN_LEVEL..FINAL_LEVEL).contains(&level)
+        && rooms.len() > 2
+        && rng.gen::<f32>() < SHOP_CHANCE
+    {
+        place_shop(
+            &rooms[1..rooms.len() - 1],
+            level,
+            objects,
+            &config.spawns,
+            rng,
+        );
+    }
+
+    // take stock of what ended up on the level
+    let summary = LevelSummary {
+        rooms: rooms.len(),
+        monsters: objects
+            .iter()
+            .filter(|object| object.fighter.is_some() && object.faction == Faction::Dungeon)
+            .count(),
+        items: objects
+            .iter()
+            .filter(|object| object.item.is_some())
+            .count(),
+        shop: objects
+            .iter()
+            .any(|object| object.ai == Some(Ai::Shopkeeper)),
+        boss: level >= FINAL_LEVEL,
+    };
+
+    (map, summary)
+}
+
+/// A kind of monster, as described in `spawns.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MonsterKind {
+    name: String,
+    glyph: char,
+    color: Color,
+    /// How likely it is to show up, by dungeon level.
+    #[serde(default)]
+    weight: Vec<Transition>,
+    fighter: FighterKind,
+    ai: Ai,
+    #[serde(default)]
+    faction: Faction,
+    #[serde(default)]
+    on_hit: Option<StatusEffect>,
+    /// What it may be carrying when it's created.
+    #[serde(default)]
+    loot: Vec<Loot>,
+}
+
+/// With a `chance` of 0 to 1, a monster carries one of the `items`,
+/// picked at random.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Loot {
+    chance: f32,
+    items: Vec<Item>,
+}
+
+/// The stats a new monster starts out with.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FighterKind {
+    hp: i32,
+    #[serde(default)]
+    defense: i32,
+    #[serde(default)]
+    power: i32,
+    damage: Dice,
+    #[serde(default = "default_crit_chance")]
+    crit_chance: f32,
+    #[serde(default = "default_speed")]
+    speed: i32,
+    xp: i32,
+    #[serde(default = "default_on_death")]
+    on_death: DeathCallback,
+}
+
+/// A kind of item, as described in `spawns.json`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ItemKind {
+    name: String,
+    glyph: char,
+    color: Color,
+    /// How likely it is to show up, by dungeon level.
+    #[serde(default)]
+    weight: Vec<Transition>,
+    /// What it does when used.
+    item: Item,
+    /// What it costs in a shop. Shopkeepers won't buy it if it's 0.
+    #[serde(default)]
+    value: u32,
+    #[serde(default)]
+    equipment: Option<EquipmentKind>,
+    /// The lowest and highest number of charges a new one can have.
+    #[serde(default)]
+    charges: Option<(u32, u32)>,
+    /// How many come together, for things like arrows.
+    #[serde(default)]
+    bundle: Option<(u32, u32)>,
+    #[serde(default)]
+    on_critical: Option<StatusEffect>,
+}
+
+/// The bonuses of a piece of equipment.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EquipmentKind {
+    slot: Slot,
+    #[serde(default)]
+    max_hp_bonus: i32,
+    #[serde(default)]
+    defense_bonus: i32,
+    #[serde(default)]
+    power_bonus: i32,
+    #[serde(default)]
+    damage: Option<Dice>,
+}
+
+fn default_on_death() -> DeathCallback {
+    DeathCallback::Monster
+}
+
+/// Every kind of monster and item the dungeon can create.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct SpawnTables {
+    monsters: Vec<MonsterKind>,
+    items: Vec<ItemKind>,
+}
+
+/// `spawns.json` before we look at the individual entries.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SpawnFile {
+    monsters: Option<Vec<serde_json::Value>>,
+    items: Option<Vec<serde_json::Value>>,
+    /// Names of monsters and items to get rid of.
+    remove: Option<Vec<String>>,
+}
+
+impl SpawnTables {
+    /// Read `data/spawns.json`, then `spawns.json`, then the `mods`'
+    /// changes to it. A list in one of the game's own files replaces the
+    /// one before it, a list that's missing from it keeps the monsters or
+    /// items from before, starting with the built-in ones. A mod's entries
+    /// replace the ones with the same name and add the rest. Any file can
+    /// `remove` monsters and items by name.
+    fn load(mods: &[PathBuf]) -> Result<Self, String> {
+        let mut spawns = SpawnTables::default();
+        let shipped = Path::new("data").join("spawns.json");
+        let mut layers = vec![("game data".to_string(), shipped.clone())];
+        layers.extend(data_layers(Path::new("spawns.json"), mods));
+        for (source, path) in layers {
+            let layer: SpawnFile = match read_layer(&source, &path)? {
+                Some(layer) => layer,
+                None => continue,
+            };
+            let file = format!("{} ({})", source, path.display());
+            let base_game = path == shipped || path == Path::new("spawns.json");
+            for name in layer.remove.unwrap_or_default() {
+                let before = spawns.monsters.len() + spawns.items.len();
+                spawns.monsters.retain(|monster| monster.name != name);
+                spawns.items.retain(|item| item.name != name);
+                if spawns.monsters.len() + spawns.items.len() == before {
+                    return Err(format!(
+                        "{}: there's nothing called `{}` to remove",
+                        file, name
+                    ));
+                }
+            }
+            if let Some(monsters) = layer.monsters {
+                let monsters = parse_spawn_entries(&file, "monster", monsters)?;
+                if base_game {
+                    spawns.monsters = monsters;
+                } else {
+                    merge_by_name(&mut spawns.monsters, monsters, |monster| &monster.name);
+                }
+            }
+            if let Some(items) = layer.items {
+                let items = parse_spawn_entries(&file, "item", items)?;
+                if base_game {
+                    spawns.items = items;
+                } else {
+                    merge_by_name(&mut spawns.items, items, |item| &item.name);
+                }
+            }
+            // every file has to leave the game something it can work
+            // with, so we know whose fault it is when it can't
+            spawns.validate(&file)?;
+        }
+        Ok(spawns)
+    }
+
+    /// Check the game can work with these monsters and items, blaming
+    /// `file` for any problem.
+    fn validate(&self, file: &str) -> Result<(), String> {
+        let mut problems = vec![];
+        for (i, monster) in self.monsters.iter().enumerate() {
+            if self.monsters[..i].iter().any(|m| m.name == monster.name) {
+                problems.push(format!(
+                    "there's more than one monster called `{}`",
+                    monster.name
+                ));
+            }
+            for loot in &monster.loot {
+                if loot.items.is_empty() {
+                    problems.push(format!("`{}` has loot with no items", monster.name));
+                }
+                for item in &loot.items {
+                    if !self.items.iter().any(|kind| kind.item == *item) {
+                        problems.push(format!(
+                            "`{}` carries the {:?} item, which is missing",
+                            monster.name, item
+                        ));
+                    }
+                }
+            }
+        }
+        for (i, item) in self.items.iter().enumerate() {
+            // there can be several kinds of the same item, like two
+            // healing potions, but they need different names
+            if self.items[..i].iter().any(|other| other.name == item.name) {
+                problems.push(format!("there's more than one item called `{}`", item.name));
+            }
+            if let Some((min, max)) = item.charges {
+                if min > max {
+                    problems.push(format!(
+                        "`{}` has more minimum charges than maximum",
+                        item.name
+                    ));
+                }
+            }
+            if let Some((min, max)) = item.bundle {
+                if min < 1 || min > max {
+                    problems.push(format!("`{}` has a broken bundle size", item.name));
+                }
+            }
+        }
+        // the ones the game creates on its own
+        for name in &["orc", "ancient king", "shopkeeper"] {
+            if !self.monsters.iter().any(|m| m.name == *name) {
+                problems.push(format!("the `{}` monster is missing", name));
+            }
+        }
+        let required = &[
+            Item::Crown,
+            Item::Arrows,
+            Item::Torch,
+            // the ones the classes start out with
+            Item::Sword,
+            Item::Shield,
+            Item::ThrowingKnife,
+            Item::Lightning,
+            Item::Confuse,
+        ];
+        for item in required {
+            if !self.items.iter().any(|kind| kind.item == *item) {
+                problems.push(format!("the {:?} item is missing", item));
+            }
+        }
+        // we can't pick from an empty table
+        for level in 1..=FINAL_LEVEL {
+            if self.monster_chances(level).iter().all(|c| c.weight == 0) {
+                problems.push(format!("no monster can show up on level {}", level));
+            }
+            if self.item_chances(level).iter().all(|c| c.weight == 0) {
+                problems.push(format!("no item can show up on level {}", level));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(problems
+                .iter()
+                .map(|problem| format!("{}: {}", file, problem))
+                .collect::<Vec<_>>()
+                .join("\n"))
+        }
+    }
+
+    /// The monster called `name`. It must exist!
+    fn monster(&self, name: &str) -> &MonsterKind {
+        self.monsters
+            .iter()
+            .find(|monster| monster.name == name)
+            .unwrap_or_else(|| panic!("There is no monster called `{}`.", name))
+    }
+
+    /// The chance of each monster showing up on this level.
+    fn monster_chances(&self, level: u32) -> Vec<rand::distributions::Weighted<&str>> {
+        self.monsters
+            .iter()
+            .map(|monster| rand::distributions::Weighted {
+                weight: interpolate_dungeon_level(&monster.weight, level),
+                item: monster.name.as_str(),
+            })
+            .collect()
+    }
+
+    /// The item called `name`. It must exist!
+    fn item(&self, name: &str) -> &ItemKind {
+        self.items
+            .iter()
+            .find(|item| item.name == name)
+            .unwrap_or_else(|| panic!("There is no item called `{}`.", name))
+    }
+
+    /// What a shopkeeper asks for this item.
+    fn value(&self, item: &Object) -> u32 {
+        // a mod's potion may do what a healing potion does, but still
+        // have a price of its own
+        let base_name = item.base_name();
+        self.items
+            .iter()
+            .find(|kind| kind.name == base_name)
+            .or_else(|| self.items.iter().find(|kind| Some(kind.item) == item.item))
+            .map_or(0, |kind| kind.value)
+    }
+
+    /// The chance of each item showing up on this level.
+    fn item_chances(&self, level: u32) -> Vec<rand::distributions::Weighted<&str>> {
+        self.items
+            .iter()
+            .map(|item| rand::distributions::Weighted {
+                weight: interpolate_dungeon_level(&item.weight, level),
+                item: item.name.as_str(),
+            })
+            .collect()
+    }
+}
+
+/// Layer a mod's entries over `entries`: one with the same name replaces
+/// the old one, a new one goes at the end.
+fn merge_by_name<T>(entries: &mut Vec<T>, changes: Vec<T>, name: fn(&T) -> &String) {
+    for change in changes {
+        match entries
+            .iter()
+            .position(|entry| name(entry) == name(&change))
+        {
+            Some(index) => entries[index] = change,
+            None => entries.push(change),
+        }
+    }
+}
+
+/// Read the entries of one of the lists in a `spawns.json`. If one of
+/// them is broken, say which one and in which `file`.
+fn parse_spawn_entries<T: serde::de::DeserializeOwned>(
+    file: &str,
+    what: &str,
+    entries: Vec<serde_json::Value>,
+) -> Result<Vec<T>, String> {
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let name = match entry.get("name").and_then(|name| name.as_str()) {
+                Some(name) => format!("`{}`", name),
+                None => format!("number {}", i + 1),
+            };
+            serde_json::from_value(entry).map_err(|e| format!("{}: {} {}: {}", file, what, name, e))
+        })
+        .collect()
+}
+
+/// The game's own monsters and items, built in from `data/spawns.json`.
+const DEFAULT_SPAWNS: &str = include_str!("../../data/spawns.json");
+
+impl Default for SpawnTables {
+    fn default() -> Self {
+        serde_json::from_str(DEFAULT_SPAWNS).expect("The built-in `data/spawns.json` is broken.")
+    }
+}
+
+/// Create a monster of the given kind at the given position
+fn create_monster(kind: &str, x: i32, y: i32, spawns: &SpawnTables) -> Object {
+    let kind = spawns.monster(kind);
+    let stats = kind.fighter;
+    let mut monster = Object::new(x, y, kind.glyph, &kind.name, kind.color, true);
+    monster.fighter = Some(Fighter {
+        base_max_hp: stats.hp,
+        hp: stats.hp,
+        base_defense: stats.defense,
+        base_power: stats.power,
+        damage: stats.damage,
+        crit_chance: stats.crit_chance,
+        speed: stats.speed,
+        mana: 0,
+        max_mana: 0,
+        xp: stats.xp,
+        on_death: stats.on_death,
+    });
+    monster.ai = Some(kind.ai.clone());
+    monster.faction = kind.faction;
+    monster.on_hit = kind.on_hit;
+    monster.alive = true;
+    monster
+}
+
+/// Create an item of the given kind at the given position. If a mod
+/// added more kinds of it, it's the first one: the base game's.
+fn create_item(item: Item, x: i32, y: i32, spawns: &SpawnTables, rng: &mut StdRng) -> Object {
+    let kind = spawns
+        .items
+        .iter()
+        .find(|kind| kind.item == item)
+        .unwrap_or_else(|| panic!("There is no {:?} item.", item));
+    item_from_kind(kind, x, y, rng)
+}
+
+/// Create the item `kind` describes at the given position.
+fn item_from_kind(kind: &ItemKind, x: i32, y: i32, rng: &mut StdRng) -> Object {
+    let mut object = Object::new(x, y, kind.glyph, &kind.name, kind.color, false);
+    object.equipment = kind.equipment.map(|equipment| Equipment {
+        equipped: false,
+        slot: equipment.slot,
+        max_hp_bonus: equipment.max_hp_bonus,
+        defense_bonus: equipment.defense_bonus,
+        power_bonus: equipment.power_bonus,
+        damage: equipment.damage,
+        cursed: false,
+        curse_known: false,
+    });
+    if let Some((min, max)) = kind.charges {
+        object.charges = Some(rng.gen_range(min, max + 1));
+    }
+    if let Some((min, max)) = kind.bundle {
+        object.count = rng.gen_range(min, max + 1);
+    }
+    object.on_critical = kind.on_critical;
+    object.item = Some(kind.item);
+    object.always_visible = true;
+    object
+}
+
+/// Create a pile of gold at the given position.
+fn create_gold(x: i32, y: i32, amount: u32) -> Object {
+    let mut gold = Object::new(x, y, '$', "gold", GOLD, false);
+    gold.gold = amount;
+    gold.always_visible = true;
+    gold
+}
+
+/// Create a chest at the given position, with the given things in it.
+fn create_chest(x: i32, y: i32, contents: Vec<Object>) -> Object {
+    let mut chest = Object::new(x, y, '&', "chest", SEPIA, false);
+    chest.contents = Some(contents);
+    chest.always_visible = true;
+    chest
+}
+
+/// Pick up all the gold the player is standing on.
+fn pick_up_gold(game: &mut Game, objects: &mut Vec<Object>) {
+    let pos = objects[PLAYER].pos();
+    let mut found = 0;
+    objects.retain(|object| {
+        let here = object.gold > 0 && object.pos() == pos;
+        if here {
+            found += object.gold;
+        }
+        !here
+    });
+    if found > 0 {
+        game.gold += found as i32;
+        game.messages
+            .add(tr!(game.strings, "gold.pick_up", found), GOLD);
+    }
+}
+
+/// Turn the smallest of the given rooms into a shop: clear it out and
+/// put a shopkeeper with some wares in the middle.
+fn place_shop(
+    rooms: &[Rect],
+    level: u32,
+    objects: &mut Vec<Object>,
+    spawns: &SpawnTables,
+    rng: &mut StdRng,
+) {
+    use rand::distributions::{IndependentSample, WeightedChoice};
+
+    let room = match rooms
+        .iter()
+        .min_by_key(|room| (room.x2 - room.x1) * (room.y2 - room.y1))
+    {
+        Some(room) => *room,
+        None => return,
+    };
+    let player_pos = objects[PLAYER].pos();
+    objects.retain(|object| {
+        let (x, y) = object.pos();
+        let inside = x > room.x1 && x < room.x2 && y > room.y1 && y < room.y2;
+        !inside || object.pos() == player_pos
+    });
+
+    let (x, y) = room.center();
+    let mut shopkeeper = create_monster("shopkeeper", x, y, spawns);
+    let item_chances = &mut spawns.item_chances(level);
+    let item_choice = WeightedChoice::new(item_chances);
+    for _ in 0..SHOP_STOCK_SIZE {
+        let kind = spawns.item(item_choice.ind_sample(rng));
+        let item = item_from_kind(kind, x, y, rng);
+        match shopkeeper
+            .stock
+            .iter_mut()
+            .find(|stock| stock.stacks_with(&item))
+        {
+            Some(stock) => stock.count += item.count,
+            None => shopkeeper.stock.push(item),
+        }
+    }
+    objects.push(shopkeeper);
+}
+
+/// Place a pack of monsters of the given kind in the room. All of them
+/// share a `pack_id` no other pack on the level has, and the first one
+/// leads them.
+fn place_pack(
+    kind: &str,
+    room: Rect,
+    map: &Map,
+    objects: &mut Vec<Object>,
+    spawns: &SpawnTables,
+    rng: &mut StdRng,
+) {
+    let pack_id = next_pack_id(objects);
+    let pack_size = rng.gen_range(3, 6);
+    let mut leader = true;
+    for _ in 0..pack_size {
+        // give up on this one if we can't find a free spot after a few tries
+        for _ in 0..10 {
+            let x = rng.gen_range(room.x1 + 1, room.x2);
+            let y = rng.gen_range(room.y1 + 1, room.y2);
+            if !is_blocked(x, y, map, objects) {
+                let mut monster = create_monster(kind, x, y, spawns);
+                monster.ai = Some(Ai::Pack {
+                    pack_id,
+                    leader,
+                    target: None,
+                });
+                objects.push(monster);
+                leader = false;
+                break;
+            }
+        }
+    }
+}
+
+/// A pack ID none of the packs on the level has. Counting the objects
+/// won't do: there are fewer of them after a shop clears out its room.
+fn next_pack_id(objects: &[Object]) -> usize {
+    objects
+        .iter()
+        .filter_map(|object| match object.ai {
+            Some(Ai::Pack { pack_id, .. }) => Some(pack_id + 1),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(1)
+}
+
+/// Make a monster tougher: scale its stats by `percent` and, for a
+/// `tier` above 0, put the tier's title in front of its name.
+fn scale_monster(monster: &mut Object, percent: u32, tier: u32) {
+    let fighter = match monster.fighter.as_mut() {
+        Some(fighter) => fighter,
+        None => return,
+    };
+    let scale = |value: i32| value * percent as i32 / 100;
+    fighter.base_max_hp = scale(fighter.base_max_hp);
+    fighter.hp = fighter.base_max_hp;
+    fighter.base_power = scale(fighter.base_power);
+    fighter.xp = scale(fighter.xp);
+    if tier > 0 {
+        monster.name = format!("{} {}", MONSTER_TIERS[tier as usize], monster.name);
+        monster.tier = tier;
+    }
+}
+
+/// Decide what a newly placed monster is carrying.
+fn roll_loot(monster: &mut Object, spawns: &SpawnTables, rng: &mut StdRng) {
+    let kind = spawns.monster(&monster.name);
+    for loot in &kind.loot {
+        if rng.gen::<f32>() < loot.chance {
+            let item = loot.items[rng.gen_range(0, loot.items.len())];
+            let item = create_item(item, monster.x, monster.y, spawns, rng);
+            monster.loot.push(item);
+        }
+    }
+}
+
+fn place_objects(
+    room: Rect,
+    map: &Map,
+    objects: &mut Vec<Object>,
+    level: u32,
+    difficulty: Difficulty,
+    spawns: &SpawnTables,
+    rng: &mut StdRng,
+) {
+    use rand::distributions::{IndependentSample, WeightedChoice};
+
+    let rules = difficulty.rules();
+
+    // maximum number of monsters per room
+    let max_monsters = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 2 },
+            Transition { level: 4, value: 3 },
+            Transition { level: 6, value: 5 },
+        ],
+        level,
+    ) * rules.monsters_percent
+        / 100;
+
+    // choose random number of monsters
+    let num_monsters = rng.gen_range(0, max_monsters + 1);
+
+    // monster random table
+    let monster_chances = &mut spawns.monster_chances(level);
+    let monster_choice = WeightedChoice::new(monster_chances);
+
+    // the deeper we are, the tougher the monsters (in percent). Harder
+    // games get there sooner
+    let scaling_level = level + rules.scaling_head_start;
+    let monster_scaling = from_dungeon_level(
+        &[
+            Transition {
+                level: 1,
+                value: 100,
+            },
+            Transition {
+                level: 4,
+                value: 125,
+            },
+            Transition {
+                level: 7,
+                value: 150,
+            },
+        ],
+        scaling_level,
+    );
+
+    // and the titles they get for it (an index into `MONSTER_TIERS`)
+    let monster_tier = from_dungeon_level(
+        &[
+            Transition { level: 4, value: 1 },
+            Transition { level: 7, value: 2 },
+        ],
+        scaling_level,
+    );
+
+    // maximum number of items per room
+    let max_items = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 1 },
+            Transition { level: 4, value: 2 },
+        ],
+        level,
+    );
+
+    // item random table
+    let item_chances = &mut spawns.item_chances(level);
+    let item_choice = WeightedChoice::new(item_chances);
+
+    for _ in 0..num_monsters {
+        // choose random spot for this monster
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        // only place it if the tile is not blocked
+        if !is_blocked(x, y, map, objects) {
+            let first_new = objects.len();
+            let kind = monster_choice.ind_sample(rng);
+            match spawns.monster(kind).ai {
+                // pack animals never come alone
+                Ai::Pack { .. } => place_pack(kind, room, map, objects, spawns, rng),
+                _ => {
+                    let monster = create_monster(kind, x, y, spawns);
+                    objects.push(monster);
+                }
+            }
+            for monster in &mut objects[first_new..] {
+                roll_loot(monster, spawns, rng);
+                // the deeper we are, the tougher they get
+                scale_monster(monster, monster_scaling, monster_tier);
+            }
+        }
+    }
+
+    // choose random number of items
+    let num_items = rng.gen_range(0, max_items + 1);
+
+    for _ in 0..num_items {
+        // choose random spot for this item
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+
+        // only place it if the tile is not blocked
+        if !is_blocked(x, y, map, objects) {
+            let kind = spawns.item(item_choice.ind_sample(rng));
+            let mut item = item_from_kind(kind, x, y, rng);
+            if let Some(ref mut equipment) = item.equipment {
+                // there's no telling by looking at it
+                equipment.cursed = rng.gen::<f32>() < CURSE_CHANCE;
+            }
+            objects.push(item);
+        }
+    }
+
+    // maybe a pile of gold
+    let max_gold = from_dungeon_level(
+        &[
+            Transition {
+                level: 1,
+                value: 10,
+            },
+            Transition {
+                level: 4,
+                value: 25,
+            },
+            Transition {
+                level: 7,
+                value: 50,
+            },
+        ],
+        level,
+    );
+    if rng.gen::<f32>() < GOLD_CHANCE {
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        if !is_blocked(x, y, map, objects) {
+            let amount = rng.gen_range(max_gold / 2 + 1, max_gold + 1);
+            objects.push(create_gold(x, y, amount));
+        }
+    }
+
+    // and now and then a chest, with a few more items from the same table
+    if rng.gen::<f32>() < CHEST_CHANCE {
+        let x = rng.gen_range(room.x1 + 1, room.x2);
+        let y = rng.gen_range(room.y1 + 1, room.y2);
+        if !is_blocked(x, y, map, objects) {
+            let count = rng.gen_range(1, CHEST_ITEMS + 1);
+            let contents = (0..count)
+                .map(|_| {
+                    let kind = spawns.item(item_choice.ind_sample(rng));
+                    let mut item = item_from_kind(kind, x, y, rng);
+                    if let Some(ref mut equipment) = item.equipment {
+                        equipment.cursed = rng.gen::<f32>() < CURSE_CHANCE;
+                    }
+                    item
+                })
+                .collect();
+            objects.push(create_chest(x, y, contents));
+        }
+    }
+}
+
+/// Advance to the next level
+fn next_level(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    // any fire stays on the old level
+    game.fires.clear();
+
+    // thieves don't get to keep what they stole just because the player
+    // left: they come along to the new level
+    let thieves = thieves_on_the_run(game, objects);
+
+    if game.difficulty.rules().rest_heal {
+        game.messages.add(tr!(game.strings, "level.rest"), VIOLET);
+        let heal_hp = objects[PLAYER].max_hp(game) / 2;
+        objects[PLAYER].heal(heal_hp, game);
+    }
+
+    game.messages.add(tr!(game.strings, "level.descend"), RED);
+    game.dungeon_level += 1;
+    let (map, summary) = make_map(
+        objects,
+        game.dungeon_level,
+        game.difficulty,
+        &tcod.config,
+        &mut game.rng,
+    );
+    game.map = map;
+    hide_thieves(thieves, game, objects);
+    game.messages
+        .add(tr!(game.strings, summary.feeling()), LIGHT_VIOLET);
+    initialise_fov(tcod, &game.map);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn render_bar(
+    panel: &mut Offscreen,
+    x: i32,
+    y: i32,
+    total_width: i32,
+    name: &str,
+    value: i32,
+    maximum: i32,
+    bar_color: Color,
+    back_color: Color,
+) {
+    // render a bar (HP, experience, etc). First calculate the width of the bar
+    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+
+    // render the background first
+    panel.set_default_background(back_color);
+    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+
+    // now render the bar on top
+    panel.set_default_background(bar_color);
+    if bar_width > 0 {
+        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+    }
+
+    // finally, some centered text with the values
+    panel.set_default_foreground(WHITE);
+    panel.print_ex(
+        x + total_width / 2,
+        y,
+        BackgroundFlag::None,
+        TextAlignment::Center,
+        format!("{}: {}/{}", name, value, maximum),
+    );
+}
+
+/// The color of a message about `attacker` attacking `target`.
+fn combat_color(attacker: &Object, target: &Object, critical: bool) -> Color {
+    let attacking = attacker.faction == Faction::Player;
+    let defending = target.faction == Faction::Player;
+    match (attacking, defending, critical) {
+        (true, _, false) => WHITE,
+        (true, _, true) => ORANGE,
+        (false, true, false) => LIGHT_RED,
+        (false, true, true) => RED,
+        (false, false, _) => LIGHT_GREY,
+    }
+}
+
+/// How hurt a fighter looks (a string id), or None if it isn't.
+fn health_state(hp: i32, max_hp: i32) -> Option<&'static str> {
+    let health = hp as f32 / cmp::max(max_hp, 1) as f32;
+    if health >= 1.0 {
+        None
+    } else if health >= 0.5 {
+        Some("health.wounded")
+    } else if health >= 0.25 {
+        Some("health.badly_wounded")
+    } else {
+        Some("health.almost_dead")
+    }
+}
+
+/// The names of the objects under the mouse, each with its colour.
+fn names_under_mouse(tcod: &Tcod, game: &Game, objects: &[Object]) -> Vec<(String, Color)> {
+    let (x, y) = match screen_to_map(tcod.mouse.cx as i32, tcod.mouse.cy as i32) {
+        Some(pos) => pos,
+        None => return vec![],
+    };
+    let tile = &game.map[(x, y)];
+    let visible = tcod.in_fov(x, y);
+    let strings = &game.strings;
+
+    let mut names: Vec<_> = objects
+        .iter()
+        .enumerate()
+        .filter(|&(_, obj)| obj.pos() == (x, y) && in_view(obj, &game.map, &tcod.fov))
+        .map(|(id, obj)| {
+            if id == PLAYER {
+                (obj.name.clone(), WHITE)
+            } else if let Some(fighter) = obj.fighter {
+                let name = match health_state(fighter.hp, obj.max_hp(game)) {
+                    Some(state) => tr!(strings, "names.detail", obj.name, strings.get(state)),
+                    None => obj.name.clone(),
+                };
+                if obj.faction.is_hostile_to(Faction::Player) {
+                    (name, LIGHT_RED)
+                } else {
+                    (name, LIGHT_BLUE)
+                }
+            } else if obj.item.is_some() {
+                let name = match obj.equipment {
+                    Some(equipment) => tr!(
+                        strings,
+                        "names.detail",
+                        obj.name,
+                        equipment.slot.name(strings)
+                    ),
+                    None => obj.stack_name(strings),
+                };
+                (name, LIGHT_YELLOW)
+            } else if obj.name.starts_with("remains of ") {
+                (obj.name.clone(), DARK_GREY)
+            } else {
+                (obj.name.clone(), LIGHT_GREY)
+            }
+        })
+        .collect();
+
+    // with nothing there, say what the ground is, if we've seen it
+    if names.is_empty() && tile.explored {
+        let terrain = if tile.block_sight {
+            "names.wall"
+        } else {
+            "names.floor"
+        };
+        names.push((tr!(strings, terrain), LIGHT_GREY));
+    }
+    // what we can't see right now is only remembered, so dim it
+    if !visible {
+        for name in &mut names {
+            name.1 = tcod::colors::lerp(name.1, BLACK, 0.5);
+        }
+    }
+    names
+}
+
+impl Object {
+    /// A one-line description for the look command.
+    fn description(&self, strings: &Strings) -> String {
+        let key = format!("describe.{}", self.base_name());
+        let text = strings.get(&key);
+        if text != key {
+            text.to_string()
+        } else if self.ai.is_some() {
+            tr!(strings, "describe.monster")
+        } else if self.item.is_some() {
+            tr!(strings, "describe.item")
+        } else {
+            String::new()
+        }
+    }
+}
+
+impl Object {
+    /// Whether `other` can be added to this item's stack.
+    fn stacks_with(&self, other: &Object) -> bool {
+        self.item.is_some()
+            && self.equipment.is_none()
+            && other.equipment.is_none()
+            && self.charges.is_none()
+            && other.charges.is_none()
+            && self.item == other.item
+            && self.name == other.name
+            && self.rots_at == other.rots_at
+    }
+
+    /// The name with the charges left, or the number of items in the
+    /// stack if there's more than one.
+    fn stack_name(&self, strings: &Strings) -> String {
+        if let Some(charges) = self.charges {
+            tr!(strings, "item.charges", self.name, charges)
+        } else if self.count > 1 {
+            tr!(strings, "item.stack", self.name, self.count)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Take one item off the top of the stack.
+    fn take_one(&mut self) -> Object {
+        self.count -= 1;
+        let mut item = Object::new(
+            self.x,
+            self.y,
+            self.char,
+            &self.name,
+            self.color,
+            self.blocks,
+        );
+        item.item = self.item;
+        item.always_visible = self.always_visible;
+        item.level = self.level;
+        item
+    }
+}
+
+impl Object {
+    /// The name without the enchantment or the tier, e.g. "sword" for
+    /// "sword +2" and "orc" for "veteran orc".
+    fn base_name(&self) -> &str {
+        let name = match (&self.unenchanted_name, self.name.rfind(" +")) {
+            (Some(name), _) => name,
+            // saved before the name was kept
+            (None, Some(end)) if self.enchantment > 0 => &self.name[..end],
+            _ => &self.name,
+        };
+        match name.find(' ') {
+            Some(start) if self.tier > 0 => &name[start + 1..],
+            _ => name,
+        }
+    }
+}
+
+impl Object {
+    fn is_stairs(&self) -> bool {
+        self.render_order == RenderOrder::Stairs
+    }
+}
+
+impl Object {
+    /// Start a status effect, or make it last longer if it's already on.
+    fn add_effect(&mut self, effect: StatusEffect, game: &mut Game) {
+        if !self.alive || self.fighter.is_none() {
+            return;
+        }
+        let key = effect.key();
+        match self.effects.iter_mut().find(|e| e.key() == key) {
+            Some(existing) => {
+                if effect.turns() > existing.turns() {
+                    *existing = effect;
+                }
+            }
+            None => {
+                game.messages.add(
+                    tr!(game.strings, &format!("effect.{}.start", key), self.name),
+                    effect.indicator().1,
+                );
+                self.effects.push(effect);
+            }
+        }
+    }
+
+    /// If this is stunned, use up one of the turns it's stunned for
+    /// and return `true`: it can't act this turn.
+    fn lose_stunned_turn(&mut self, game: &mut Game) -> bool {
+        let index = match self.effects.iter().position(|e| e.key() == "stunned") {
+            Some(index) => index,
+            None => return false,
+        };
+        game.messages.add(
+            tr!(game.strings, "effect.stunned.tick", self.name),
+            LIGHT_BLUE,
+        );
+        *self.effects[index].turns_mut() -= 1;
+        if self.effects[index].turns() <= 0 {
+            self.effects.remove(index);
+            game.messages.add(
+                tr!(game.strings, "effect.stunned.end", self.name),
+                LIGHT_BLUE,
+            );
+        }
+        true
+    }
+
+    /// The status effects a hit from this object inflicts: its own, and
+    /// the ones from the player's equipment.
+    fn hit_effects(&self, game: &Game, critical: bool) -> Vec<StatusEffect> {
+        let mut sources = vec![self];
+        if self.is_player {
+            sources.extend(
+                game.inventory
+                    .iter()
+                    .filter(|item| item.equipment.is_some_and(|e| e.equipped)),
+            );
+        }
+        let mut effects = vec![];
+        for source in sources {
+            effects.extend(source.on_hit);
+            if critical {
+                effects.extend(source.on_critical);
+            }
+        }
+        effects
+    }
+}
+
+impl Object {
+    /// How much energy this gains every tick.
+    fn speed(&self) -> i32 {
+        let bonus: i32 = self.effects.iter().map(|effect| effect.speed_bonus()).sum();
+        self.fighter.map_or(0, |f| cmp::max(f.speed + bonus, 1))
+    }
+}
+
+impl Object {
+    fn is_invisible(&self) -> bool {
+        self.effects.iter().any(|e| e.key() == "invisible")
+    }
+
+    /// End the invisibility early, if there's any.
+    fn become_visible(&mut self, game: &mut Game) {
+        if let Some(index) = self.effects.iter().position(|e| e.key() == "invisible") {
+            self.effects.remove(index);
+            game.messages.add(
+                tr!(game.strings, "effect.invisible.end", self.name),
+                LIGHTER_GREY,
+            );
+        }
+    }
+}
+
+/// Something that affects a fighter for a few turns.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum StatusEffect {
+    Poisoned { damage_per_turn: i32, turns: i32 },
+    Burning { damage_per_turn: i32, turns: i32 },
+    Stunned { turns: i32 },
+    Hasted { amount: i32, turns: i32 },
+    Slowed { amount: i32, turns: i32 },
+    Invisible { turns: i32 },
+}
+
+impl StatusEffect {
+    /// The part of the message ids about this effect, e.g. the
+    /// "poisoned" in `effect.poisoned.start`.
+    fn key(self) -> &'static str {
+        match self {
+            StatusEffect::Poisoned { .. } => "poisoned",
+            StatusEffect::Burning { .. } => "burning",
+            StatusEffect::Stunned { .. } => "stunned",
+            StatusEffect::Hasted { .. } => "hasted",
+            StatusEffect::Slowed { .. } => "slowed",
+            StatusEffect::Invisible { .. } => "invisible",
+        }
+    }
+
+    fn turns(self) -> i32 {
+        match self {
+            StatusEffect::Poisoned { turns, .. } => turns,
+            StatusEffect::Burning { turns, .. } => turns,
+            StatusEffect::Stunned { turns } => turns,
+            StatusEffect::Hasted { turns, .. } => turns,
+            StatusEffect::Slowed { turns, .. } => turns,
+            StatusEffect::Invisible { turns } => turns,
+        }
+    }
+
+    fn turns_mut(&mut self) -> &mut i32 {
+        match self {
+            StatusEffect::Poisoned { turns, .. } => turns,
+            StatusEffect::Burning { turns, .. } => turns,
+            StatusEffect::Stunned { turns } => turns,
+            StatusEffect::Hasted { turns, .. } => turns,
+            StatusEffect::Slowed { turns, .. } => turns,
+            StatusEffect::Invisible { turns } => turns,
+        }
+    }
+
+    /// The letter and color that show the effect next to the HP bar.
+    fn indicator(self) -> (char, Color) {
+        match self {
+            StatusEffect::Poisoned { .. } => ('P', LIGHT_GREEN),
+            StatusEffect::Burning { .. } => ('B', ORANGE),
+            StatusEffect::Stunned { .. } => ('S', LIGHT_BLUE),
+            StatusEffect::Hasted { .. } => ('H', YELLOW),
+            StatusEffect::Slowed { .. } => ('L', LIGHT_GREY),
+            StatusEffect::Invisible { .. } => ('I', LIGHTER_GREY),
+        }
+    }
+
+    /// How much faster (or slower) this makes the fighter.
+    fn speed_bonus(self) -> i32 {
+        match self {
+            StatusEffect::Hasted { amount, .. } => amount,
+            StatusEffect::Slowed { amount, .. } => -amount,
+            _ => 0,
+        }
+    }
+}
+
+/// A tile that's on fire, and for how many more turns.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct Fire {
+    x: i32,
+    y: i32,
+    turns: i32,
+}
+
+/// Let every status effect do its thing for one turn.
+fn tick_effects(game: &mut Game, objects: &mut [Object]) {
+    // standing in fire sets you alight
+    for object in objects.iter_mut() {
+        let pos = object.pos();
+        if !game.fires.iter().any(|fire| (fire.x, fire.y) == pos) {
+            continue;
+        }
+        if object.item == Some(Item::Corpse) {
+            // the dead just burn up
+            if object.rots_at != Some(game.turns) {
+                object.rots_at = Some(game.turns);
+                game.messages
+                    .add(tr!(game.strings, "corpse.burned", object.name), ORANGE);
+            }
+            continue;
+        }
+        let burning = StatusEffect::Burning {
+            damage_per_turn: BURN_DAMAGE,
+            turns: BURN_TURNS,
+        };
+        object.add_effect(burning, game);
+    }
+    for fire in &mut game.fires {
+        fire.turns -= 1;
+    }
+    game.fires.retain(|fire| fire.turns > 0);
+
+    for object in objects.iter_mut() {
+        if !object.alive || object.effects.is_empty() {
+            continue;
+        }
+        let mut effects = std::mem::take(&mut object.effects);
+        for effect in &mut effects {
+            let key = effect.key();
+            let color = effect.indicator().1;
+            match effect {
+                StatusEffect::Poisoned {
+                    damage_per_turn,
+                    turns,
+                }
+                | StatusEffect::Burning {
+                    damage_per_turn,
+                    turns,
+                } => {
+                    *turns -= 1;
+                    let damage = *damage_per_turn;
+                    game.messages.add(
+                        tr!(
+                            game.strings,
+                            &format!("effect.{}.tick", key),
+                            object.name,
+                            damage
+                        ),
+                        color,
+                    );
+                    game.last_damage_source = tr!(game.strings, &format!("effect.{}.source", key));
+                    object.take_damage(damage, game);
+                }
+                // stuns wear off as the turns are lost
+                StatusEffect::Stunned { .. } => {}
+                StatusEffect::Hasted { turns, .. }
+                | StatusEffect::Slowed { turns, .. }
+                | StatusEffect::Invisible { turns } => {
+                    *turns -= 1;
+                }
+            }
+            if effect.turns() <= 0 && object.alive {
+                game.messages.add(
+                    tr!(game.strings, &format!("effect.{}.end", key), object.name),
+                    color,
+                );
+            }
+        }
+        if object.alive {
+            effects.retain(|effect| effect.turns() > 0);
+            object.effects = effects;
+        }
+    }
+}
+
+/// Give the player a hit point back every `regen_interval` turns, as long
+/// as they haven't been hurt in a while, and a point of mana every
+/// `mana_regen_interval` turns.
+fn regenerate(config: &GameConfig, game: &Game, player: &mut Object) {
+    let since_hurt = game.turns - game.last_hurt_turn;
+    if since_hurt >= config.regen_delay as u32
+        && game.turns.is_multiple_of(config.regen_interval as u32)
+    {
+        player.heal(1, game);
+    }
+    if game.turns.is_multiple_of(config.mana_regen_interval as u32) {
+        if let Some(fighter) = player.fighter.as_mut() {
+            fighter.mana = cmp::min(fighter.mana + 1, fighter.max_mana);
+        }
+    }
+}
+
+/// How hungry the player is.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Hunger {
+    #[default]
+    Satiated,
+    Hungry,
+    Weak,
+    Starving,
+}
+
+impl Hunger {
+    fn from_turns(hunger: i32) -> Self {
+        if hunger >= STARVING {
+            Hunger::Starving
+        } else if hunger >= WEAK {
+            Hunger::Weak
+        } else if hunger >= HUNGRY {
+            Hunger::Hungry
+        } else {
+            Hunger::Satiated
+        }
+    }
+
+    /// The part of the string keys for this state.
+    fn key(self) -> &'static str {
+        match self {
+            Hunger::Satiated => "satiated",
+            Hunger::Hungry => "hungry",
+            Hunger::Weak => "weak",
+            Hunger::Starving => "starving",
+        }
+    }
+
+    fn color(self) -> Color {
+        match self {
+            Hunger::Satiated => WHITE,
+            Hunger::Hungry => YELLOW,
+            Hunger::Weak => ORANGE,
+            Hunger::Starving => RED,
+        }
+    }
+
+    /// How much weaker the player hits in this state.
+    fn power_penalty(self) -> i32 {
+        match self {
+            Hunger::Satiated | Hunger::Hungry => 0,
+            Hunger::Weak | Hunger::Starving => WEAK_POWER_PENALTY,
+        }
+    }
+}
+
+/// One more turn without food.
+fn get_hungrier(game: &mut Game, player: &mut Object) {
+    let before = Hunger::from_turns(game.hunger);
+    game.hunger += 1;
+    let now = Hunger::from_turns(game.hunger);
+    if now != before {
+        let key = format!("hunger.{}", now.key());
+        game.messages.add(tr!(game.strings, &key), now.color());
+    }
+    if now == Hunger::Starving && game.turns.is_multiple_of(STARVE_INTERVAL) {
+        game.last_damage_source = tr!(game.strings, "cause.starvation");
+        player.take_damage(1, game);
+    }
+}
+
+/// Run one tick of game time: status effects act, everyone gains energy
+/// and the monsters act as long as they have enough of it.
+fn world_tick(view: &View, game: &mut Game, objects: &mut Vec<Object>) {
+    // poison, fire and the like act first
+    tick_effects(game, objects);
+
+    // the player's turn may have created new monsters (e.g. a new level)
+    game.assign_ids(objects);
+
+    // only the player and the actors ever spend energy
+    let speed = objects[PLAYER].speed();
+    objects[PLAYER].energy += speed;
+    let actors = game.actors.clone();
+    for &id in &actors {
+        if let Some(index) = object_index(id, objects) {
+            let monster = &mut objects[index];
+            if monster.alive {
+                monster.energy += monster.speed();
+            }
+        }
+    }
+
+    for id in actors {
+        // a fast monster may act more than once, and any monster may die
+        // in the middle of it
+        while let Some(index) = object_index(id, objects) {
+            if objects[index].ai.is_none() || objects[index].energy < ACTION_COST {
+                break;
+            }
+            objects[index].energy -= ACTION_COST;
+            if !objects[index].lose_stunned_turn(game) {
+                ai_take_turn(index, view, game, objects);
+            }
+        }
+    }
+
+    // thieves standing on the stairs take them
+    escape_down_stairs(game, objects);
+
+    // forget the ones that are gone from the level or don't act any more
+    game.actors
+        .retain(|&id| object_index(id, objects).is_some_and(|index| objects[index].ai.is_some()));
+}
+
+/// Set the floor within `radius` of (x, y) on fire.
+fn set_fire(x: i32, y: i32, radius: i32, game: &mut Game) {
+    for fire_x in (x - radius)..(x + radius + 1) {
+        for fire_y in (y - radius)..(y + radius + 1) {
+            let distance = (((fire_x - x).pow(2) + (fire_y - y).pow(2)) as f32).sqrt();
+            if in_map(fire_x, fire_y)
+                && distance <= radius as f32
+                && !game.map[(fire_x, fire_y)].blocked
+                && blast_reaches((x, y), (fire_x, fire_y), &game.map)
+            {
+                game.fires.push(Fire {
+                    x: fire_x,
+                    y: fire_y,
+                    turns: FIRE_TURNS,
+                });
+            }
+        }
+    }
+}
+
+/// Is there a clear line from the center of a blast to `pos`, with no
+/// wall in the way?
+fn blast_reaches(center: (i32, i32), pos: (i32, i32), map: &Map) -> bool {
+    tcod::line::Line::new(center, pos).all(|(x, y)| !map[(x, y)].blocked)
+}
+
+/// What the player knows about the tile at (x, y), a line per thing.
+fn describe_tile(x: i32, y: i32, game: &Game, objects: &[Object], fov_map: &FovMap) -> Vec<String> {
+    let strings = &game.strings;
+    if !in_map(x, y) || !game.map[(x, y)].explored {
+        return vec![tr!(strings, "look.unexplored")];
+    }
+    let visible = fov_map.is_in_fov(x, y);
+
+    let mut lines: Vec<String> = objects
+        .iter()
+        .filter(|obj| obj.pos() == (x, y) && (visible || obj.always_visible))
+        .map(|obj| {
+            let description = obj.description(strings);
+            if description.is_empty() {
+                obj.name.clone()
+            } else {
+                format!("{}: {}", obj.name, description)
+            }
+        })
+        .collect();
+    if game.map[(x, y)].block_sight {
+        lines.push(tr!(strings, "look.wall"));
+    } else {
+        lines.push(tr!(strings, "look.floor"));
+    }
+    if !visible {
+        lines.push(tr!(strings, "look.out_of_sight"));
+    }
+    lines
+}
+
+/// Move a cursor around the map and describe what's under it.
+fn look_around(tcod: &mut Tcod, game: &mut Game, objects: &[Object]) {
+    use tcod::input::KeyCode::{Enter, Escape};
+
+    let (mut x, mut y) = objects[PLAYER].pos();
+    loop {
+        tcod.targeting = Some(Targeting { x, y, radius: 0 });
+        render_all(tcod, game, objects, false);
+
+        let mut lines = vec![tr!(game.strings, "look.hint")];
+        lines.extend(describe_tile(x, y, game, objects, &tcod.fov));
+        tcod.root.set_default_foreground(WHITE);
+        tcod.root.set_default_background(BLACK);
+        for (row, line) in lines.iter().enumerate() {
+            tcod.root.print_ex(
+                0,
+                row as i32,
+                BackgroundFlag::Set,
+                TextAlignment::Left,
+                line,
+            );
+        }
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        let command = tcod.keymap.command_for(key);
+        if key.code == Escape || command == Some(Command::Look) {
+            break;
+        }
+        if key.code == Enter {
+            let visible = tcod.in_fov(x, y);
+            let explored = game.map[(x, y)].explored;
+            let texts: Vec<String> = objects
+                .iter()
+                .filter(|obj| obj.pos() == (x, y) && explored && (visible || obj.always_visible))
+                .map(|obj| examine_text(obj, &tcod.config, &game.strings))
+                .collect();
+            if !texts.is_empty() {
+                msgbox(&texts.join("\n\n"), INVENTORY_WIDTH, &mut tcod.root);
+            }
+            continue;
+        }
+        if let Some((dx, dy)) = command.and_then(Command::direction) {
+            if in_map(x + dx, y + dy) {
+                x += dx;
+                y += dy;
+            }
+        }
+    }
+    tcod.targeting = None;
+}
+
+/// The numbers behind what an item does, if it does anything special.
+fn item_details(item: &Object, config: &GameConfig, strings: &Strings) -> Option<String> {
+    let details = match item.item? {
+        Item::Heal => tr!(strings, "details.heal", config.heal_amount),
+        Item::Lightning | Item::LightningWand => tr!(
+            strings,
+            "details.lightning",
+            config.lightning_damage,
+            config.lightning_range
+        ),
+        Item::Confuse | Item::ConfusionWand => tr!(
+            strings,
+            "details.confuse",
+            config.confuse_range,
+            config.confuse_num_turns
+        ),
+        Item::StrengthPotion => tr!(strings, "details.strength"),
+        Item::VitalityPotion => tr!(strings, "details.vitality", VITALITY_BONUS),
+        Item::AgilityPotion => tr!(strings, "details.agility"),
+        Item::Invisibility => tr!(strings, "details.invisibility", INVISIBLE_TURNS),
+        Item::Torch => tr!(strings, "details.light", config.torch_radius),
+        Item::Lantern => tr!(strings, "details.light", config.lantern_radius),
+        Item::Fireball => tr!(
+            strings,
+            "details.fireball",
+            config.fireball_damage,
+            config.fireball_radius
+        ),
+        _ => {
+            let equipment = item.equipment?;
+            match equipment.damage {
+                Some(dice) => tr!(
+                    strings,
+                    "details.weapon",
+                    equipment.slot.name(strings),
+                    dice
+                ),
+                None => tr!(strings, "details.equipment", equipment.slot.name(strings)),
+            }
+        }
+    };
+    Some(details)
+}
+
+/// Everything we know about an object: name, description and numbers.
+fn examine_text(object: &Object, config: &GameConfig, strings: &Strings) -> String {
+    let mut text = format!(
+        "{}\n\n{}",
+        describe_equipment(object, strings),
+        object.description(strings)
+    );
+    if let Some(details) = item_details(object, config, strings) {
+        text.push_str("\n\n");
+        text.push_str(&details);
+    }
+    text
+}
+
+/// Show all the messages so far, newest at the bottom.
+fn show_history(tcod: &mut Tcod, game: &Game) {
+    use tcod::input::KeyCode::*;
+
+    let width = SCREEN_WIDTH - 4;
+    let height = SCREEN_HEIGHT - 4;
+    // the frame takes up a tile on every side
+    let text_width = width - 2;
+    let text_height = (height - 2) as usize;
+    let settings = &tcod.settings;
+    let lines: Vec<(String, Color)> = game
+        .messages
+        .iter()
+        .flat_map(|message| {
+            wrap_text(&message.to_string(), text_width as usize)
+                .into_iter()
+                .map(move |line| (line, settings.message_color(message.color)))
+        })
+        .collect();
+    let bottom = lines.len().saturating_sub(text_height);
+    let mut top = bottom;
+
+    let mut window = Offscreen::new(width, height);
+    loop {
+        window.set_default_foreground(WHITE);
+        window.print_frame(
+            0,
+            0,
+            width,
+            height,
+            true,
+            BackgroundFlag::Set,
+            Some(game.strings.get("history.title")),
+        );
+        for (y, &(ref line, color)) in lines.iter().skip(top).take(text_height).enumerate() {
+            window.set_default_foreground(color);
+            window.print_ex(
+                1,
+                y as i32 + 1,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                line,
+            );
+        }
+        blit(
+            &window,
+            (0, 0),
+            (width, height),
+            &mut tcod.root,
+            (2, 2),
+            1.0,
+            0.9,
+        );
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        match key.code {
+            Up => top = top.saturating_sub(1),
+            Down => top = cmp::min(top + 1, bottom),
+            PageUp => top = top.saturating_sub(text_height),
+            PageDown => top = cmp::min(top + text_height, bottom),
+            Home => top = 0,
+            End => top = bottom,
+            Escape => break,
+            _ if tcod.keymap.command_for(key) == Some(Command::History) => break,
+            _ => {}
+        }
+    }
+}
+
+/// The lines of the help screen, built from the key map so that they
+/// always match what the keys really do.
+fn help_lines(strings: &Strings, keymap: &KeyMap) -> Vec<(String, Color)> {
+    let mut lines = vec![];
+    for &category in &HELP_CATEGORIES {
+        if !lines.is_empty() {
+            lines.push((String::new(), WHITE));
+        }
+        lines.push((tr!(strings, category), LIGHT_YELLOW));
+        let bound = keymap
+            .bindings
+            .iter()
+            .filter(|(command, _)| command.help_category() == category)
+            .map(|(command, keys)| {
+                let keys: Vec<String> = keys.iter().map(|key| key.to_string()).collect();
+                let keys = if keys.is_empty() {
+                    tr!(strings, "help.unbound")
+                } else {
+                    keys.join(", ")
+                };
+                (keys, format!("help.command.{:?}", command))
+            });
+        let fixed = FIXED_KEYS
+            .iter()
+            .filter(|&&(heading, _, _)| heading == category)
+            .map(|&(_, keys, description)| (keys.to_string(), description.to_string()));
+        for (keys, description) in bound.chain(fixed) {
+            let line = format!("  {:<20}{}", keys, strings.get(&description));
+            lines.push((line, WHITE));
+        }
+    }
+    lines
+}
+
+/// Show every command over the whole screen, a page at a time.
+fn show_help(tcod: &mut Tcod, strings: &Strings) {
+    use tcod::input::KeyCode::*;
+
+    let lines = help_lines(strings, &tcod.keymap);
+    // the frame takes up a tile on every side
+    let text_height = (SCREEN_HEIGHT - 2) as usize;
+    let bottom = lines.len().saturating_sub(text_height);
+    let mut top = 0;
+
+    let mut window = Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT);
+    loop {
+        window.set_default_foreground(WHITE);
+        window.print_frame(
+            0,
+            0,
+            SCREEN_WIDTH,
+            SCREEN_HEIGHT,
+            true,
+            BackgroundFlag::Set,
+            Some(strings.get("help.title")),
+        );
+        for (y, &(ref line, color)) in lines.iter().skip(top).take(text_height).enumerate() {
+            window.set_default_foreground(color);
+            window.print_ex(
+                1,
+                y as i32 + 1,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                line,
+            );
+        }
+        if bottom > 0 {
+            window.set_default_foreground(WHITE);
+            window.print_ex(
+                SCREEN_WIDTH - 2,
+                SCREEN_HEIGHT - 1,
+                BackgroundFlag::None,
+                TextAlignment::Right,
+                strings.get("help.scroll"),
+            );
+        }
+        blit(
+            &window,
+            (0, 0),
+            (SCREEN_WIDTH, SCREEN_HEIGHT),
+            &mut tcod.root,
+            (0, 0),
+            1.0,
+            0.9,
+        );
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        match key.code {
+            Up => top = top.saturating_sub(1),
+            Down => top = cmp::min(top + 1, bottom),
+            PageUp => top = top.saturating_sub(text_height),
+            PageDown => top = cmp::min(top + text_height, bottom),
+            Escape => break,
+            _ if tcod.keymap.command_for(key) == Some(Command::Help) => break,
+            _ => {}
+        }
+    }
+}
+
+/// The item's name with the bonuses it gives, like "sword (+3 power)".
+fn describe_equipment(item: &Object, strings: &Strings) -> String {
+    let equipment = match item.equipment {
+        Some(equipment) => equipment,
+        None => return item.name.clone(),
+    };
+    let mut bonuses: Vec<String> = [
+        (equipment.power(), "equipment.power"),
+        (equipment.defense(), "equipment.defense"),
+        (equipment.max_hp(), "equipment.max_hp"),
+    ]
+    .iter()
+    .filter(|&&(bonus, _)| bonus != 0)
+    .map(|&(bonus, key)| tr!(strings, key, format!("{:+}", bonus)))
+    .collect();
+    if equipment.cursed && equipment.curse_known {
+        bonuses.push(tr!(strings, "equipment.cursed"));
+    }
+    if bonuses.is_empty() {
+        item.name.clone()
+    } else {
+        tr!(strings, "names.detail", item.name, bonuses.join(", "))
+    }
+}
+
+/// Show what's equipped in every slot and let the player change it.
+/// Returns whether anything changed.
+fn equipment_screen(tcod: &mut Tcod, game: &mut Game) -> bool {
+    let strings = &game.strings;
+    let slots: Vec<String> = Slot::ALL
+        .iter()
+        .map(|&slot| match get_equipped_in_slot(slot, &game.inventory) {
+            Some(index) => tr!(
+                strings,
+                "equipment.slot",
+                slot.name(strings),
+                describe_equipment(&game.inventory[index], strings)
+            ),
+            None => tr!(
+                strings,
+                "equipment.slot",
+                slot.name(strings),
+                strings.get("equipment.empty")
+            ),
+        })
+        .collect();
+    let header = strings.get("equipment.header");
+    let slot = match decide(tcod, slots.len(), |root| {
+        menu(header, &slots, INVENTORY_WIDTH, root)
+    }) {
+        Some(index) => Slot::ALL[index],
+        None => return false,
+    };
+
+    // what could go there instead
+    let current = get_equipped_in_slot(slot, &game.inventory);
+    let candidates: Vec<usize> = game
+        .inventory
+        .iter()
+        .enumerate()
+        .filter(|&(_, item)| {
+            item.equipment
+                .is_some_and(|e| e.slot.interchangeable().contains(&slot) && !e.equipped)
+        })
+        .map(|(index, _)| index)
+        .collect();
+    let mut options: Vec<String> = candidates
+        .iter()
+        .map(|&index| describe_equipment(&game.inventory[index], strings))
+        .collect();
+    if let Some(current) = current {
+        options.push(tr!(
+            strings,
+            "equipment.take_off",
+            game.inventory[current].name
+        ));
+    }
+    if options.is_empty() {
+        game.messages
+            .add(tr!(strings, "equipment.nothing", slot.name(strings)), WHITE);
+        return false;
+    }
+
+    let header = tr!(strings, "equipment.choose", slot.name(strings));
+    let choice = match decide(tcod, options.len(), |root| {
+        menu(&header, &options, INVENTORY_WIDTH, root)
+    }) {
+        Some(choice) => choice,
+        None => return false,
+    };
+    if let Some(current) = current {
+        if !game.inventory[current].dequip(&game.strings, &mut game.messages) {
+            return false;
+        }
+    }
+    if let Some(&index) = candidates.get(choice) {
+        game.inventory[index].set_slot(slot);
+        game.inventory[index].equip(&game.strings, &mut game.messages);
+    }
+    true
+}
+
+/// Ask what to do with an item from the inventory. `None` means going
+/// back to the inventory.
+fn item_menu(inventory_id: usize, tcod: &mut Tcod, game: &Game) -> Option<Action> {
+    let item = &game.inventory[inventory_id];
+    let strings = &game.strings;
+    let use_label = match item.equipment {
+        Some(equipment) if equipment.equipped => "item_menu.take_off",
+        Some(_) => "item_menu.equip",
+        None => "item_menu.use",
+    };
+    let options = [
+        strings.get(use_label),
+        strings.get("item_menu.drop"),
+        strings.get("item_menu.throw"),
+        strings.get("item_menu.examine"),
+    ];
+    let header = format!("{}\n", describe_equipment(item, strings));
+    match menu(&header, &options, INVENTORY_WIDTH, &mut tcod.root)? {
+        0 => Some(Action::UseItem(inventory_id)),
+        1 => Some(Action::DropItem(inventory_id)),
+        2 => Some(Action::ThrowItem(inventory_id)),
+        _ => {
+            let text = examine_text(item, &tcod.config, strings);
+            msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
+            None
+        }
+    }
+}
+
+fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &[Object], fov_recompute: bool) {
+    if fov_recompute {
+        // recompute FOV if needed (the player moved or something)
+        let player = &objects[PLAYER];
+        let config = &tcod.config;
+        tcod.fov.compute_fov(
+            player.x,
+            player.y,
+            light_radius(game, config),
+            config.fov_light_walls,
+            FOV_ALGO,
+        );
+    }
+
+    // go through all tiles, and repaint the ones whose color changed
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            let visible = tcod.fov.is_in_fov(x, y);
+            let tile = &mut game.map[(x, y)];
+            if visible {
+                // since it's visible, explore it
+                tile.explored = true;
+            }
+            let color = match (tile.explored, visible, tile.block_sight) {
+                // show explored tiles only (any visible tile is explored already)
+                (false, _, _) => BLACK,
+                // outside of field of view:
+                (true, false, true) => COLOR_DARK_WALL,
+                (true, false, false) => COLOR_DARK_GROUND,
+                // inside fov:
+                (true, true, true) => COLOR_LIGHT_WALL,
+                (true, true, false) => COLOR_LIGHT_GROUND,
+            };
+            let shown = &mut tcod.map_colors[(y * MAP_WIDTH + x) as usize];
+            if *shown != Some(color) {
+                *shown = Some(color);
+                tcod.map_layer
+                    .set_char_background(x, y, color, BackgroundFlag::Set);
+            }
+        }
+    }
+    blit(
+        &tcod.map_layer,
+        (0, 0),
+        (MAP_WIDTH, MAP_HEIGHT),
+        &mut tcod.con,
+        (0, 0),
+        1.0,
+        1.0,
+    );
+
+    // fire on the floor
+    for fire in &game.fires {
+        if tcod.in_fov(fire.x, fire.y) {
+            tcod.con
+                .set_char_background(fire.x, fire.y, DARK_FLAME, BackgroundFlag::Set);
+        }
+    }
+
+    // highlight what the player is aiming at
+    if let Some(target) = tcod.targeting {
+        for y in 0..MAP_HEIGHT {
+            for x in 0..MAP_WIDTH {
+                let distance = (((x - target.x).pow(2) + (y - target.y).pow(2)) as f32).sqrt();
+                let cursor = (x, y) == (target.x, target.y);
+                // the cursor can also point at places we remember
+                let shown = tcod.in_fov(x, y) || (cursor && game.map[(x, y)].explored);
+                if !shown || distance > target.radius as f32 {
+                    continue;
+                }
+                if cursor {
+                    tcod.con
+                        .set_char_background(x, y, COLOR_TARGET_CURSOR, BackgroundFlag::Set);
+                } else {
+                    tcod.con
+                        .set_char_background(x, y, COLOR_TARGET_AREA, BackgroundFlag::Multiply);
+                }
+            }
+        }
+    }
+
+    // take the list out of `tcod` while we fill it, so we can still look at
+    // the FOV
+    let mut to_draw = std::mem::take(&mut tcod.draw_order);
+    to_draw.clear();
+    // `in_view` leaves out anything that's off the map. That's a bug, so say
+    // so, once
+    for object in objects.iter().filter(|object| !in_map(object.x, object.y)) {
+        if tcod.warned.insert(object.id) {
+            eprintln!(
+                "Warning: {} is outside the map at ({}, {}).",
+                object.name, object.x, object.y
+            );
+        }
+    }
+    to_draw.extend((0..objects.len()).filter(|&id| in_view(&objects[id], &game.map, &tcod.fov)));
+    to_draw.sort_by_key(|&id| objects[id].render_order);
+    for &id in &to_draw {
+        objects[id].draw(&mut tcod.con);
+    }
+    tcod.draw_order = to_draw;
+
+    // show how healthy the monsters are: green when unhurt, red near death
+    if tcod.settings.show_health_bars {
+        for (id, object) in objects.iter().enumerate() {
+            let fighter = match object.fighter {
+                Some(fighter) if id != PLAYER && tcod.in_fov(object.x, object.y) => fighter,
+                _ => continue,
+            };
+            let max_hp = cmp::max(object.max_hp(game), 1);
+            let health = fighter.hp as f32 / max_hp as f32;
+            let tint = tcod::colors::lerp(RED, GREEN, health);
+            tcod.con
+                .set_char_background(object.x, object.y, tint, BackgroundFlag::Multiply);
+        }
+    }
+
+    // blit the contents of "con" to the root console
+    blit(
+        &tcod.con,
+        (0, 0),
+        (MAP_WIDTH, MAP_HEIGHT),
+        &mut tcod.root,
+        (0, 0),
+        1.0,
+        1.0,
+    );
+
+    // prepare to render the GUI panel
+    tcod.panel.set_default_background(BLACK);
+    tcod.panel.clear();
+
+    // print the game messages from the bottom of the panel up. A message
+    // that doesn't fit entirely still shows its last lines.
+    let mut y = MSG_HEIGHT as i32;
+    'messages: for message in game.messages.iter().rev() {
+        tcod.panel
+            .set_default_foreground(tcod.settings.message_color(message.color));
+        for line in wrap_text(&message.to_string(), MSG_WIDTH as usize)
+            .iter()
+            .rev()
+        {
+            y -= 1;
+            if y < 0 {
+                break 'messages;
+            }
+            tcod.panel
+                .print_ex(MSG_X, y, BackgroundFlag::None, TextAlignment::Left, line);
+        }
+    }
+
+    // show the player's stats
+    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+    let max_hp = objects[PLAYER].max_hp(game);
+    render_bar(
+        &mut tcod.panel,
+        1,
+        1,
+        BAR_WIDTH,
+        game.strings.get("panel.hp"),
+        hp,
+        max_hp,
+        LIGHT_RED,
+        DARKER_RED,
+    );
+    // the player's status effects, at the end of the HP bar
+    for (i, effect) in objects[PLAYER].effects.iter().enumerate() {
+        let (letter, color) = effect.indicator();
+        tcod.panel.set_default_foreground(color);
+        tcod.panel
+            .put_char(BAR_WIDTH - i as i32, 1, letter, BackgroundFlag::None);
+    }
+    // the mana bar, once the player knows some magic
+    if let Some(fighter) = objects[PLAYER].fighter {
+        if fighter.max_mana > 0 {
+            render_bar(
+                &mut tcod.panel,
+                1,
+                2,
+                BAR_WIDTH,
+                game.strings.get("panel.mana"),
+                fighter.mana,
+                fighter.max_mana,
+                LIGHT_BLUE,
+                DARKER_BLUE,
+            );
+        }
+    }
+
+    // the status effects may have left a different color behind
+    tcod.panel.set_default_foreground(WHITE);
+    tcod.panel.print_ex(
+        1,
+        3,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        tr!(game.strings, "panel.dungeon_level", game.dungeon_level),
+    );
+    tcod.panel.set_default_foreground(GOLD);
+    tcod.panel.print_ex(
+        BAR_WIDTH,
+        3,
+        BackgroundFlag::None,
+        TextAlignment::Right,
+        tr!(game.strings, "panel.gold", game.gold),
+    );
+    tcod.panel.set_default_foreground(WHITE);
+    let hunger = Hunger::from_turns(game.hunger);
+    if hunger != Hunger::Satiated {
+        let key = format!("panel.{}", hunger.key());
+        tcod.panel.set_default_foreground(hunger.color());
+        tcod.panel.print_ex(
+            1,
+            4,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            tr!(game.strings, &key),
+        );
+        tcod.panel.set_default_foreground(WHITE);
+    }
+    tcod.panel.print_ex(
+        BAR_WIDTH,
+        4,
+        BackgroundFlag::None,
+        TextAlignment::Right,
+        tr!(game.strings, "panel.turns", game.turns),
+    );
+
+    // display names of objects under the mouse, each in its own colour
+    let names = names_under_mouse(tcod, game, objects);
+    let ellipsis = "...";
+    let mut x = 1;
+    let mut room = (SCREEN_WIDTH - 2) as usize;
+    for (index, &(ref name, color)) in names.iter().enumerate() {
+        let mut text = if index == 0 {
+            name.clone()
+        } else {
+            format!(", {}", name)
+        };
+        let length = text.chars().count();
+        let last = index + 1 == names.len();
+        // unless this is the last one, leave room to say there's more
+        let fits = length + if last { 0 } else { ellipsis.len() } <= room;
+        if !fits {
+            text = text
+                .chars()
+                .take(room.saturating_sub(ellipsis.len()))
+                .collect();
+            text.push_str(ellipsis);
+        }
+        tcod.panel.set_default_foreground(color);
+        tcod.panel
+            .print_ex(x, 0, BackgroundFlag::None, TextAlignment::Left, &text);
+        if !fits {
+            break;
+        }
+        x += length as i32;
+        room -= length;
+    }
+
+    // blit the contents of `panel` to the root console
+    blit(
+        &tcod.panel,
+        (0, 0),
+        (SCREEN_WIDTH, PANEL_HEIGHT),
+        &mut tcod.root,
+        (0, PANEL_Y),
+        1.0,
+        1.0,
+    );
+    // keep warning the player while they're close to death
+    if tcod.settings.low_hp_warning && hp > 0 && hp * 100 < max_hp * LOW_HP_PERCENT {
+        draw_border(&mut tcod.root, DARKER_RED);
+    }
+    if tcod.debug_overlay != DebugOverlay::Off {
+        draw_debug_overlay(tcod, game, objects);
+    }
+}
+
+fn player_move_or_attack(
+    dx: i32,
+    dy: i32,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) {
+    if let Some(target_id) = move_or_attack(dx, dy, game, objects) {
+        talk_or_attack(target_id, tcod, game, objects);
+    }
+}
+
+/// Move the player or attack whoever is in the way. Someone neutral is
+/// left alone: their index is returned instead, so the player can be
+/// asked whether to talk or to fight.
+fn move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut [Object]) -> Option<usize> {
+    // the coordinates the player is moving to/attacking
+    let x = objects[PLAYER].x + dx;
+    let y = objects[PLAYER].y + dy;
+
+    // try to find an attackable object there (not the player, when the
+    // move goes nowhere)
+    let target_id = objects
+        .iter()
+        .position(|object| object.fighter.is_some() && object.pos() == (x, y) && !object.is_player);
+
+    // attack if target found, move otherwise
+    match target_id {
+        Some(target_id) if objects[target_id].faction == Faction::Neutral => {
+            return Some(target_id);
+        }
+        Some(target_id) => {
+            let (player, target) = mut_two(PLAYER, target_id, objects);
+            player.attack(target, game);
+        }
+        None => {
+            move_by(PLAYER, dx, dy, &game.map, objects);
+        }
+    }
+    None
+}
+
+/// Let the player choose between talking to (or trading with) a neutral
+/// character and attacking it
+fn talk_or_attack(target_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    let header = tr!(game.strings, "npc.bump", objects[target_id].name);
+    let shop = objects[target_id].ai == Some(Ai::Shopkeeper);
+    let talk = if shop { "npc.trade" } else { "npc.talk" };
+    let options = &[game.strings.get(talk), game.strings.get("npc.attack")];
+    match decide(tcod, options.len(), |root| {
+        menu(&header, options, INVENTORY_WIDTH, root)
+    }) {
+        Some(0) if shop => trade(target_id, tcod, game, objects),
+        Some(0) => {
+            let text = tr!(game.strings, "npc.lost_adventurer.says");
+            msgbox(&text, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
+        }
+        Some(1) => {
+            let (player, target) = mut_two(PLAYER, target_id, objects);
+            player.attack(target, game);
+        }
+        _ => {}
+    }
+}
+
+/// Buy from and sell to a shopkeeper until the player's had enough.
+fn trade(shopkeeper_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    loop {
+        let header = tr!(game.strings, "shop.header", game.gold);
+        let options = &[game.strings.get("shop.buy"), game.strings.get("shop.sell")];
+        match decide(tcod, options.len(), |root| {
+            menu(&header, options, INVENTORY_WIDTH, root)
+        }) {
+            Some(0) => buy(shopkeeper_id, tcod, game, objects),
+            Some(1) => sell(shopkeeper_id, tcod, game, objects),
+            _ => return,
+        }
+    }
+}
+
+fn buy(shopkeeper_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    let stock = &mut objects[shopkeeper_id].stock;
+    if stock.is_empty() {
+        let text = tr!(game.strings, "shop.empty");
+        msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
+        return;
+    }
+    let options: Vec<_> = stock
+        .iter()
+        .map(|item| {
+            let name = item.stack_name(&game.strings);
+            tr!(
+                game.strings,
+                "shop.price",
+                name,
+                tcod.config.spawns.value(item)
+            )
+        })
+        .collect();
+    let header = tr!(game.strings, "shop.buy_header", game.gold);
+    let index = match decide(tcod, options.len(), |root| {
+        menu(&header, &options, INVENTORY_WIDTH, root)
+    }) {
+        Some(index) => index,
+        None => return,
+    };
+    let spawns = &tcod.config.spawns;
+
+    let price = spawns.value(&stock[index]) as i32;
+    let stack = game
+        .inventory
+        .iter()
+        .position(|item| item.stacks_with(&stock[index]));
+    if price > game.gold {
+        game.messages.add(
+            tr!(game.strings, "shop.too_expensive", stock[index].name),
+            RED,
+        );
+    } else if stack.is_none() && game.inventory.len() >= INVENTORY_SIZE {
+        game.messages.add(
+            tr!(game.strings, "item.inventory_full", stock[index].name),
+            RED,
+        );
+    } else {
+        let item = if stock[index].count > 1 {
+            stock[index].take_one()
+        } else {
+            stock.remove(index)
+        };
+        game.gold -= price;
+        game.messages
+            .add(tr!(game.strings, "shop.bought", item.name, price), GOLD);
+        match stack {
+            Some(stack) => game.inventory[stack].count += item.count,
+            None => game.inventory.push(item),
+        }
+    }
+}
+
+fn sell(shopkeeper_id: usize, tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    if game.inventory.is_empty() {
+        let text = tr!(game.strings, "inventory.empty");
+        msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
+        return;
+    }
+    // the shopkeeper buys for half of what he sells for
+    let options: Vec<_> = game
+        .inventory
+        .iter()
+        .map(|item| {
+            let name = item.stack_name(&game.strings);
+            tr!(
+                game.strings,
+                "shop.price",
+                name,
+                tcod.config.spawns.value(item) / 2
+            )
+        })
+        .collect();
+    let header = tr!(game.strings, "shop.sell_header", game.gold);
+    let index = match decide(tcod, options.len(), |root| {
+        menu(&header, &options, INVENTORY_WIDTH, root)
+    }) {
+        Some(index) => index,
+        None => return,
+    };
+    let spawns = &tcod.config.spawns;
+
+    let price = spawns.value(&game.inventory[index]) / 2;
+    if price == 0 {
+        game.messages.add(
+            tr!(
+                game.strings,
+                "shop.not_interested",
+                game.inventory[index].name
+            ),
+            WHITE,
+        );
+        return;
+    }
+    // cursed equipment can't be sold, it won't come off
+    if let Some(item) = take_from_inventory(index, game) {
+        game.gold += price as i32;
+        game.messages
+            .add(tr!(game.strings, "shop.sold", item.name, price), GOLD);
+        let stock = &mut objects[shopkeeper_id].stock;
+        match stock.iter_mut().find(|stock| stock.stacks_with(&item)) {
+            Some(stack) => stack.count += item.count,
+            None => stock.push(item),
+        }
+    }
+}
+
+/// Where the menu window starts after flipping a page back.
+fn page_up(first: usize) -> usize {
+    first.saturating_sub(MENU_PAGE_SIZE)
+}
+
+/// Where the menu window starts after flipping a page forward. The
+/// last page is always a full one, so it can't go past the end.
+fn page_down(first: usize, len: usize) -> usize {
+    cmp::min(first + MENU_PAGE_SIZE, len.saturating_sub(MENU_PAGE_SIZE))
+}
+
+/// The option a letter picks, as an index into the whole list of `len`
+/// options, when the window starts at `first`.
+fn menu_choice(letter: char, first: usize, len: usize) -> Option<usize> {
+    if !letter.is_ascii_alphabetic() {
+        return None;
+    }
+    let index = letter.to_ascii_lowercase() as usize - 'a' as usize;
+    let shown = cmp::min(MENU_PAGE_SIZE, len.saturating_sub(first));
+    if index < shown {
+        Some(first + index)
+    } else {
+        None
+    }
+}
+
+fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, root: &mut Root) -> Option<usize> {
+    use tcod::input::KeyCode::{Down, Escape, PageDown, PageUp, Up};
+
+    // the index of the first option in the window, and the furthest down
+    // the window can go
+    let mut first = 0;
+    let last_first = options.len().saturating_sub(MENU_PAGE_SIZE);
+    let scrolls = options.len() > MENU_PAGE_SIZE;
+    loop {
+        let shown = &options[first..cmp::min(first + MENU_PAGE_SIZE, options.len())];
+
+        // calculate total height for the header (after auto-wrap), one
+        // line per option and one to say there's more, if it scrolls
+        let header_height = if header.is_empty() {
+            0
+        } else {
+            root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header)
+        };
+        let footer_height = if scrolls { 1 } else { 0 };
+        let height = shown.len() as i32 + header_height + footer_height;
+
+        // create an off-screen console that represents the menu's window
+        let mut window = Offscreen::new(width, height);
+
+        // print the header, with auto-wrap
+        window.set_default_foreground(WHITE);
+        window.print_rect_ex(
+            0,
+            0,
+            width,
+            height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            header,
+        );
+
+        // print the options in the window
+        for (index, option_text) in shown.iter().enumerate() {
+            let menu_letter = (b'a' + index as u8) as char;
+            let text = format!("({}) {}", menu_letter, option_text.as_ref());
+            window.print_ex(
+                0,
+                header_height + index as i32,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                text,
+            );
+        }
+        if scrolls {
+            let more = if first < last_first {
+                "-- more --"
+            } else {
+                "-- end --"
+            };
+            window.print_ex(
+                width / 2,
+                height - 1,
+                BackgroundFlag::None,
+                TextAlignment::Center,
+                more,
+            );
+        }
+
+        // blit the contents of "window" to the root console
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+
+        // present the root console to the player and wait for a key-press
+        root.flush();
+        let key = root.wait_for_keypress(true);
+
+        match key.code {
+            Escape => return None,
+            Up => {
+                first = first.saturating_sub(1);
+                continue;
+            }
+            Down => {
+                first = cmp::min(first + 1, last_first);
+                continue;
+            }
+            _ => {}
+        }
+        if key.code == PageUp || key.printable == '<' {
+            first = page_up(first);
+            continue;
+        }
+        if key.code == PageDown || key.printable == '>' {
+            first = page_down(first, options.len());
+            continue;
+        }
+
+        // a letter picks the option next to it, anything else cancels
+        return menu_choice(key.printable, first, options.len());
+    }
+}
+
+/// The "things here" menu for the player's tile, in a frame. A letter
+/// marks a loose item or a thing in an open container, opens or closes
+/// a container, or butchers a corpse; `*` marks every loose item and
+/// Enter takes whatever is marked. A single loose item is picked up
+/// without asking.
+fn tile_menu(tcod: &mut Tcod, game: &Game, objects: &[Object]) -> Vec<Action> {
+    use tcod::input::KeyCode::{Enter, Escape, NumPadEnter};
+
+    let (x, y) = objects[PLAYER].pos();
+    let mut tile_menu = TileMenu::new(x, y);
+    match tile_menu.entries(objects)[..] {
+        [] => return vec![],
+        [TileInteraction {
+            verb: Verb::PickUp,
+            object_id,
+            ..
+        }] => return vec![Action::PickUp(object_id)],
+        _ => {}
+    }
+
+    let width = INVENTORY_WIDTH;
+    loop {
+        let entries = tile_menu.entries(objects);
+        let shown = &entries[..cmp::min(entries.len(), MENU_PAGE_SIZE)];
+        // a line for each entry and one for the keys, inside the frame
+        let height = shown.len() as i32 + 3;
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(WHITE);
+        window.print_frame(
+            0,
+            0,
+            width,
+            height,
+            true,
+            BackgroundFlag::Set,
+            Some(game.strings.get("tile.title")),
+        );
+        for (index, entry) in shown.iter().enumerate() {
+            let menu_letter = (b'a' + index as u8) as char;
+            let label = tile_menu.label(entry, objects, &game.strings);
+            window.print_ex(
+                1,
+                index as i32 + 1,
+                BackgroundFlag::None,
+                TextAlignment::Left,
+                format!("({}) {}", menu_letter, label),
+            );
+        }
+        window.print_ex(
+            width / 2,
+            height - 2,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            game.strings.get("tile.keys"),
+        );
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        blit(
+            &window,
+            (0, 0),
+            (width, height),
+            &mut tcod.root,
+            (x, y),
+            1.0,
+            0.7,
+        );
+        tcod.root.flush();
+
+        let key = tcod.root.wait_for_keypress(true);
+        match key.code {
+            Escape => return vec![],
+            Enter | NumPadEnter => return tile_menu.actions(),
+            _ if key.printable == '*' => tile_menu.mark_all(objects),
+            _ => {
+                if let Some(index) = menu_choice(key.printable, 0, shown.len()) {
+                    if let Some(actions) = tile_menu.choose(shown[index]) {
+                        return actions;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Where an item goes in the inventory list: equipment first, then
+/// scrolls and wands, then potions.
+fn inventory_category(item: &Object) -> u32 {
+    if item.equipment.is_some() {
+        return 0;
+    }
+    match item.item {
+        Some(Item::Lightning) | Some(Item::Confuse) | Some(Item::Fireball) => 1,
+        Some(Item::LightningWand) | Some(Item::ConfusionWand) => 1,
+        Some(Item::RemoveCurse) | Some(Item::Enchant) | Some(Item::Teleport) => 1,
+        Some(Item::Haste) | Some(Item::Slow) => 1,
+        Some(Item::Heal) => 2,
+        Some(Item::StrengthPotion) | Some(Item::VitalityPotion) | Some(Item::AgilityPotion) => 2,
+        Some(Item::Invisibility) => 2,
+        _ => 3,
+    }
+}
+
+/// The order the inventory is listed in: each entry is an index into
+/// `inventory`.
+fn inventory_order(inventory: &[Object]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..inventory.len()).collect();
+    order.sort_by_key(|&index| inventory_category(&inventory[index]));
+    order
+}
+
+fn inventory_menu(
+    inventory: &[Object],
+    header: &str,
+    strings: &Strings,
+    root: &mut Root,
+) -> Option<usize> {
+    // sort by category for the menu, but remember where each item really is
+    let order = inventory_order(inventory);
+
+    // how a menu with each item of the inventory as an option
+    let options = if inventory.is_empty() {
+        vec![tr!(strings, "inventory.empty")]
+    } else {
+        order
+            .iter()
+            .map(|&index| &inventory[index])
+            .map(|item| {
+                // show additional information, in case it's equipped
+                match item.equipment {
+                    Some(equipment) if equipment.equipped => {
+                        tr!(
+                            strings,
+                            "inventory.equipped_on",
+                            item.name,
+                            equipment.slot.name(strings)
+                        )
+                    }
+                    _ => item.stack_name(strings),
+                }
+            })
+            .collect()
+    };
+
+    let inventory_index = menu(header, &options, INVENTORY_WIDTH, root);
+
+    // if an item was chosen, return where it is in the inventory
+    if !inventory.is_empty() {
+        inventory_index.map(|choice| order[choice])
+    } else {
+        None
+    }
+}
+
+/// Like `inventory_menu`, but only offering the items `filter` accepts.
+fn filtered_inventory_menu<F: Fn(&Object) -> bool>(
+    inventory: &[Object],
+    filter: F,
+    header: &str,
+    strings: &Strings,
+    root: &mut Root,
+) -> Option<usize> {
+    let candidates: Vec<usize> = (0..inventory.len())
+        .filter(|&index| filter(&inventory[index]))
+        .collect();
+    let options: Vec<String> = candidates
+        .iter()
+        .map(|&index| describe_equipment(&inventory[index], strings))
+        .collect();
+    menu(header, &options, INVENTORY_WIDTH, root).map(|choice| candidates[choice])
+}
+
+fn msgbox(text: &str, width: i32, root: &mut Root) {
+    let options: &[&str] = &[];
+    menu(text, options, width, root);
+}
+
+fn show_high_scores(strings: &Strings, paths: &Paths, root: &mut Root) {
+    let scores = load_high_scores(paths);
+    let mut text = strings.get("hall.header").to_string();
+    if scores.is_empty() {
+        text.push_str(strings.get("hall.empty"));
+    }
+    for (rank, entry) in scores.iter().enumerate() {
+        let outcome = match entry.killed_by {
+            Some(ref killer) => tr!(strings, "hall.killed_by", killer),
+            None => tr!(strings, "hall.victory"),
+        };
+        let line = tr!(
+            strings,
+            "hall.entry",
+            rank + 1,
+            score(entry),
+            entry.level,
+            entry.dungeon_level,
+            entry.turns,
+            outcome,
+            entry.name,
+            entry.difficulty.name(strings)
+        );
+        text.push_str(&line);
+        text.push('\n');
+    }
+    msgbox(&text, SCREEN_WIDTH - 10, root);
+}
+
+/// Split `text` into lines no wider than `width` characters. Words
+/// longer than a whole line are broken up. An empty text is still one
+/// (empty) line so it takes up a row like any other message.
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let width = cmp::max(width, 1);
+    if text.is_empty() {
+        return vec![String::new()];
+    }
+    let mut lines = vec![];
+    for paragraph in text.lines() {
+        let mut line = String::new();
+        let mut line_width = 0; // in characters, not bytes
+        for word in paragraph.split(' ') {
+            let mut word: Vec<char> = word.chars().collect();
+            // a word that doesn't fit on a line of its own gets broken up
+            while word.len() > width {
+                if line_width > 0 {
+                    lines.push(line);
+                    line = String::new();
+                    line_width = 0;
+                }
+                let rest = word.split_off(width);
+                lines.push(word.into_iter().collect());
+                word = rest;
+            }
+            if line_width > 0 && line_width + 1 + word.len() > width {
+                lines.push(line);
+                line = String::new();
+                line_width = 0;
+            }
+            if line_width > 0 {
+                line.push(' ');
+                line_width += 1;
+            }
+            line.extend(word.iter());
+            line_width += word.len();
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Show a long text in a window the player can scroll with the arrow
+/// keys. Any other key closes it.
+fn scroll_box(text: &str, root: &mut Root) {
+    use tcod::input::KeyCode::*;
+
+    let width = SCREEN_WIDTH - 4;
+    let height = SCREEN_HEIGHT - 4;
+    let lines = wrap_text(text, width as usize);
+    let mut window = Offscreen::new(width, height);
+    let mut top = 0;
+    loop {
+        window.set_default_foreground(WHITE);
+        window.clear();
+        for (y, line) in lines.iter().skip(top).take(height as usize).enumerate() {
+            window.print_ex(0, y as i32, BackgroundFlag::None, TextAlignment::Left, line);
+        }
+        blit(&window, (0, 0), (width, height), root, (2, 2), 1.0, 0.9);
+        root.flush();
+
+        match root.wait_for_keypress(true).code {
+            Up => top = top.saturating_sub(1),
+            Down if top + (height as usize) < lines.len() => top += 1,
+            Down => {}
+            _ => break,
+        }
+    }
+}
+
+/// Something the player wants to do, no matter how they asked for it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Action {
+    /// Move in the given direction, attacking whoever is in the way.
+    Move(i32, i32),
+    /// Keep moving in the given direction until something interesting happens.
+    Run(i32, i32),
+    /// Walk to the given map position, or attack the monster there if it's next to us.
+    Travel(i32, i32),
+    /// Skip a turn.
+    Wait,
+    /// Pick up the item with the given ID.
+    PickUp(ObjectId),
+    /// Pick up everything under the player. Only older replays have
+    /// this: the "things here" menu marks the items one by one.
+    PickUpAll,
+    /// Take the item at this position in the container with the given ID.
+    TakeOut(ObjectId, usize),
+    /// Cut up the corpse with the given ID.
+    Butcher(ObjectId),
+    /// Use the item at this position in the inventory.
+    UseItem(usize),
+    /// Drop the item at this position in the inventory.
+    DropItem(usize),
+    /// Throw the item at this position in the inventory somewhere.
+    ThrowItem(usize),
+    /// Cast one of the spells the player knows.
+    CastSpell(Spell),
+    /// Shoot the equipped bow.
+    Fire,
+    /// Go down the stairs.
+    Descend,
+    /// Look around the map with a cursor.
+    Look,
+    /// Read all the messages so far.
+    ShowHistory,
+    /// List every command and the keys for it.
+    ShowHelp,
+    /// Write what's on the screen to a text file.
+    Screenshot,
+    /// Show the next page of the debug overlay, or hide it.
+    ToggleDebugOverlay,
+    /// Show or hide how healthy the monsters are.
+    ToggleHealthBars,
+    /// See and change what the player is wearing.
+    ShowEquipment,
+    ShowCharacter,
+    ToggleFullscreen,
+    Exit,
+}
+
+/// Work out what the player wants to do from the key they pressed.
+fn key_to_action(tcod: &mut Tcod, game: &Game, objects: &[Object]) -> Option<Action> {
+    use tcod::input::KeyCode::*;
+    use Action::*;
+
+    match tcod.key {
+        Key {
+            code: Enter,
+            alt: true,
+            ..
+        } => return Some(ToggleFullscreen),
+        Key { code: Escape, .. } => return Some(Exit),
+        _ => {}
+    }
+    // the history is worth reading after death, too
+    let ctrl_p = tcod.key.ctrl && tcod.key.printable == 'p';
+    if ctrl_p || tcod.keymap.command_for(tcod.key) == Some(Command::History) {
+        return Some(ShowHistory);
+    }
+    if tcod.keymap.command_for(tcod.key) == Some(Command::Help) {
+        return Some(ShowHelp);
+    }
+    if tcod.keymap.command_for(tcod.key) == Some(Command::Screenshot) {
+        return Some(Screenshot);
+    }
+    if tcod.keymap.command_for(tcod.key) == Some(Command::DebugOverlay) {
+        return Some(ToggleDebugOverlay);
+    }
+
+    if !objects[PLAYER].alive {
+        return None;
+    }
+
+    // clicking on the map walks there
+    if tcod.mouse.lbutton_pressed {
+        if let Some((x, y)) = screen_to_map(tcod.mouse.cx as i32, tcod.mouse.cy as i32) {
+            return Some(Travel(x, y));
+        }
+    }
+
+    // with Shift held, keep going in that direction
+    let shift = tcod.key.shift;
+    let step = |dx, dy| if shift { Run(dx, dy) } else { Move(dx, dy) };
+
+    match tcod.keymap.command_for(tcod.key)? {
+        Command::MoveN => Some(step(0, -1)),
+        Command::MoveNE => Some(step(1, -1)),
+        Command::MoveE => Some(step(1, 0)),
+        Command::MoveSE => Some(step(1, 1)),
+        Command::MoveS => Some(step(0, 1)),
+        Command::MoveSW => Some(step(-1, 1)),
+        Command::MoveW => Some(step(-1, 0)),
+        Command::MoveNW => Some(step(-1, -1)),
+        Command::Wait => Some(Wait),
+
+        // it can ask for several things at once, so `handle_keys` opens
+        // the "things here" menu itself
+        Command::PickUp => None,
+
+        Command::Inventory => loop {
+            // show the inventory: pick an item, then what to do with it.
+            // Cancelling the second menu goes back to the list.
+            let inventory_id = inventory_menu(
+                &game.inventory,
+                game.strings.get("inventory.use_header"),
+                &game.strings,
+                &mut tcod.root,
+            )?;
+            if let Some(action) = item_menu(inventory_id, tcod, game) {
+                break Some(action);
+            }
+        },
+
+        Command::Drop => {
+            // show the inventory; if an item is selected, drop it
+            inventory_menu(
+                &game.inventory,
+                game.strings.get("inventory.drop_header"),
+                &game.strings,
+                &mut tcod.root,
+            )
+            .map(DropItem)
+        }
+
+        Command::Descend => Some(Descend),
+        Command::Character => Some(ShowCharacter),
+        Command::Look => Some(Look),
+        Command::History => Some(ShowHistory),
+        Command::Help => Some(ShowHelp),
+        Command::Screenshot => Some(Screenshot),
+        Command::DebugOverlay => Some(ToggleDebugOverlay),
+        Command::HealthBars => Some(ToggleHealthBars),
+        Command::Equipment => Some(ShowEquipment),
+        Command::Fire => Some(Fire),
+
+        Command::Cast => {
+            // pick one of the spells the player knows
+            if game.spells.is_empty() {
+                let text = tr!(game.strings, "spellbook.empty");
+                msgbox(&text, INVENTORY_WIDTH, &mut tcod.root);
+                return None;
+            }
+            let options: Vec<String> = game
+                .spells
+                .iter()
+                .map(|spell| {
+                    tr!(
+                        game.strings,
+                        "spellbook.option",
+                        spell.name(&game.strings),
+                        spell.cost()
+                    )
+                })
+                .collect();
+            let mana = objects[PLAYER].fighter.map_or(0, |f| f.mana);
+            let header = tr!(game.strings, "spellbook.header", mana);
+            menu(&header, &options, INVENTORY_WIDTH, &mut tcod.root)
+                .map(|index| CastSpell(game.spells[index]))
+        }
+    }
+}
+
+/// Do what the player asked for, if it can be done without the window:
+/// moving, fighting, waiting, picking things up and dropping them.
+/// `None` means it needs the window, and `apply_action` has to do it.
+fn apply_world_action(
+    action: Action,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+) -> Option<PlayerAction> {
+    use PlayerAction::*;
+
+    let result = match action {
+        Action::Move(dx, dy) => match move_or_attack(dx, dy, game, objects) {
+            None => TookTurn,
+            // asking whether to talk or fight needs the window
+            Some(_) => return None,
+        },
+        Action::Wait => TookTurn,
+        Action::PickUp(id) => {
+            // only what's lying under the player, whatever a replay says
+            let (player_x, player_y) = objects[PLAYER].pos();
+            match object_index(id, objects) {
+                Some(index) if items_at(player_x, player_y, objects).contains(&index) => {
+                    pick_item_up(index, game, objects)
+                }
+                _ => {}
+            }
+            DidntTakeTurn
+        }
+        Action::PickUpAll => {
+            let (player_x, player_y) = objects[PLAYER].pos();
+            // start with the highest index: picking an item up moves the
+            // last object into its place
+            for &item_id in items_at(player_x, player_y, objects).iter().rev() {
+                pick_item_up(item_id, game, objects);
+            }
+            DidntTakeTurn
+        }
+        Action::TakeOut(container_id, slot) => {
+            take_out(container_id, slot, game, objects);
+            DidntTakeTurn
+        }
+        Action::Butcher(corpse_id) => {
+            if butcher(corpse_id, game, objects) {
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        Action::DropItem(inventory_index) => {
+            if inventory_index < game.inventory.len() {
+                drop_item(inventory_index, game, objects);
+            }
+            DidntTakeTurn
+        }
+        _ => return None,
+    };
+    Some(result)
+}
+
+/// Carry out the player's action.
+fn apply_action(
+    action: Action,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+) -> PlayerAction {
+    use PlayerAction::*;
+
+    if let Some(result) = apply_world_action(action, game, objects) {
+        return result;
+    }
+    match action {
+        Action::ToggleFullscreen => {
+            let fullscreen = tcod.root.is_fullscreen();
+            tcod.root.set_fullscreen(!fullscreen);
+            tcod.settings.fullscreen = !fullscreen;
+            tcod.settings.save(&tcod.paths);
+            DidntTakeTurn
+        }
+        Action::Exit => pause_menu(tcod, game),
+        Action::Move(dx, dy) => {
+            player_move_or_attack(dx, dy, tcod, game, objects);
+            TookTurn
+        }
+        Action::Run(dx, dy) => {
+            let start = objects[PLAYER].pos();
+            player_move_or_attack(dx, dy, tcod, game, objects);
+            // only keep going if that was a step and not an attack
+            if objects[PLAYER].pos() != start {
+                game.pending_run = Some((dx, dy));
+                game.run_snapshot = RunSnapshot::take(tcod, game, objects);
+            }
+            TookTurn
+        }
+        Action::Travel(x, y) => {
+            let (player_x, player_y) = objects[PLAYER].pos();
+            let (dx, dy) = (x - player_x, y - player_y);
+            let monster_there = objects
+                .iter()
+                .any(|o| o.pos() == (x, y) && o.alive && o.ai.is_some());
+            if monster_there && dx.abs() <= 1 && dy.abs() <= 1 {
+                player_move_or_attack(dx, dy, tcod, game, objects);
+                return TookTurn;
+            }
+            if let Some(mut path) = find_path((player_x, player_y), (x, y), &game.map) {
+                path.reverse();
+                game.pending_path = path;
+                game.run_snapshot = RunSnapshot::take(tcod, game, objects);
+            }
+            DidntTakeTurn
+        }
+        Action::ToggleHealthBars => {
+            let settings = &mut tcod.settings;
+            settings.show_health_bars = !settings.show_health_bars;
+            settings.save(&tcod.paths);
+            let message = if settings.show_health_bars {
+                "health_bars.on"
+            } else {
+                "health_bars.off"
+            };
+            game.messages.add(tr!(game.strings, message), WHITE);
+            DidntTakeTurn
+        }
+        Action::ShowEquipment => {
+            if equipment_screen(tcod, game) {
+                TookTurn
+            } else {
+                DidntTakeTurn
+            }
+        }
+        Action::ShowHistory => {
+            show_history(tcod, game);
+            DidntTakeTurn
+        }
+        Action::ShowHelp => {
+            show_help(tcod, &game.strings);
+            DidntTakeTurn
+        }
+        Action::Screenshot => {
+            take_screenshot(tcod, game, objects);
+            DidntTakeTurn
+        }
+        Action::ToggleDebugOverlay => {
+            tcod.debug_overlay = tcod.debug_overlay.next();
+            DidntTakeTurn
+        }
+        Action::Look => {
+            look_around(tcod, game, objects);
+            DidntTakeTurn
+        }
+        Action::UseItem(inventory_index) => {
+            if inventory_index < game.inventory.len() {
+                use_item(inventory_index, tcod, game, objects);
+            }
+            DidntTakeTurn
+        }
+        Action::ThrowItem(inventory_index) => {
+            if inventory_index < game.inventory.len() {
+                throw_item(inventory_index, tcod, game, objects);
+            }
+            DidntTakeTurn
+        }
+        Action::CastSpell(spell) => cast_spell(spell, tcod, game, objects),
+        Action::Fire => fire(tcod, game, objects),
+        Action::Descend => {
+            // go down stairs, if the player is on them
+            let player_on_stairs = objects
+                .iter()
+                .any(|object| object.pos() == objects[PLAYER].pos() && object.is_stairs());
+            if player_on_stairs {
+                next_level(tcod, game, objects);
+            }
+            DidntTakeTurn
+        }
+        Action::ShowCharacter => {
+            let player = &objects[PLAYER];
+            let level = player.level;
+            let level_up_xp =
+                tcod.config.level_up_base + player.level * tcod.config.level_up_factor;
+            if let Some(fighter) = player.fighter.as_ref() {
+                let msg = tr!(
+                    game.strings,
+                    "character.info",
+                    level,
+                    fighter.xp,
+                    level_up_xp,
+                    player.max_hp(game),
+                    player.power(game),
+                    player.defense(game),
+                    player.damage_range(game),
+                    game.class.name(&game.strings),
+                    player.name,
+                    game.turns
+                );
+                msgbox(&msg, CHARACTER_SCREEN_WIDTH, &mut tcod.root);
+            }
+            DidntTakeTurn
+        }
+        // done by `apply_world_action` already
+        Action::Wait
+        | Action::PickUp(_)
+        | Action::PickUpAll
+        | Action::TakeOut(..)
+        | Action::Butcher(_)
+        | Action::DropItem(_) => DidntTakeTurn,
+    }
+}
+
+/// What the player knew after the last step of a run.
+#[derive(Default)]
+struct RunSnapshot {
+    hp: i32,
+    hunger: Hunger,
+    last_message: Option<Message>,
+    visible: Vec<ObjectId>,
+}
+
+impl RunSnapshot {
+    fn take(tcod: &Tcod, game: &Game, objects: &[Object]) -> Self {
+        RunSnapshot {
+            hp: objects[PLAYER].fighter.map_or(0, |f| f.hp),
+            hunger: Hunger::from_turns(game.hunger),
+            last_message: game.messages.last().cloned(),
+            visible: objects
+                .iter()
+                .filter(|o| tcod.in_fov(o.x, o.y))
+                .map(|o| o.id)
+                .collect(),
+        }
+    }
+}
+
+/// Take the next step of a run, unless there's a reason to stop.
+/// `stopped` says whether the player asked to.
+fn continue_run(
+    stopped: bool,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> PlayerAction {
+    let (dx, dy) = match game.pending_run {
+        Some(direction) => direction,
+        None => return PlayerAction::DidntTakeTurn,
+    };
+    let (x, y) = objects[PLAYER].pos();
+    let now = RunSnapshot::take(tcod, game, objects);
+    let before = &game.run_snapshot;
+    let stop = stopped
+        || !objects[PLAYER].alive
+        || is_blocked(x + dx, y + dy, &game.map, objects)
+        || !items_at(x, y, objects).is_empty()
+        || now.hp != before.hp
+        || now.last_message != before.last_message
+        || now.visible.iter().any(|id| !before.visible.contains(id));
+    if stop {
+        game.pending_run = None;
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    player_move_or_attack(dx, dy, tcod, game, objects);
+    game.run_snapshot = RunSnapshot::take(tcod, game, objects);
+    PlayerAction::TookTurn
+}
+
+/// The shortest way between two tiles over explored floor, without the
+/// starting tile. `None` if there's no way there.
+fn find_path(from: (i32, i32), to: (i32, i32), map: &Map) -> Option<Vec<(i32, i32)>> {
+    shortest_path(from, to, |x, y| {
+        in_map(x, y) && map[(x, y)].explored && !map[(x, y)].blocked
+    })
+}
+
+/// The shortest way between two tiles over the `walkable` ones, without
+/// the starting tile. `None` if there's no way there.
+fn shortest_path<F>(from: (i32, i32), to: (i32, i32), walkable: F) -> Option<Vec<(i32, i32)>>
+where
+    F: Fn(i32, i32) -> bool,
+{
+    if !walkable(to.0, to.1) {
+        return None;
+    }
+
+    // where we stepped onto each tile from
+    let mut came_from = vec![vec![None; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    came_from[from.0 as usize][from.1 as usize] = Some(from);
+    let mut queue = std::collections::VecDeque::new();
+    queue.push_back(from);
+    while let Some((x, y)) = queue.pop_front() {
+        if (x, y) == to {
+            // follow the trail back to the start
+            let mut path = vec![];
+            let mut current = to;
+            while current != from {
+                path.push(current);
+                current = came_from[current.0 as usize][current.1 as usize].unwrap();
+            }
+            path.reverse();
+            return Some(path);
+        }
+        for dx in -1..=1 {
+            for dy in -1..=1 {
+                let (next_x, next_y) = (x + dx, y + dy);
+                if walkable(next_x, next_y) && came_from[next_x as usize][next_y as usize].is_none()
+                {
+                    came_from[next_x as usize][next_y as usize] = Some((x, y));
+                    queue.push_back((next_x, next_y));
+                }
+            }
+        }
+    }
+    None
+}
+
+/// Take the next step towards the travel target, unless there's a reason to stop.
+/// `stopped` says whether the player asked to.
+fn continue_travel(
+    stopped: bool,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut [Object],
+) -> PlayerAction {
+    let (x, y) = match game.pending_path.last() {
+        Some(&next) => next,
+        None => return PlayerAction::DidntTakeTurn,
+    };
+    let now = RunSnapshot::take(tcod, game, objects);
+    let before = &game.run_snapshot;
+    let monster_appeared = now
+        .visible
+        .iter()
+        .filter(|id| !before.visible.contains(id))
+        .any(|&id| object_index(id, objects).is_some_and(|index| objects[index].ai.is_some()));
+    let stop = stopped
+        || !objects[PLAYER].alive
+        || is_blocked(x, y, &game.map, objects)
+        || now.hunger != before.hunger
+        || monster_appeared;
+    if stop {
+        game.pending_path.clear();
+        return PlayerAction::DidntTakeTurn;
+    }
+
+    game.pending_path.pop();
+    let (player_x, player_y) = objects[PLAYER].pos();
+    player_move_or_attack(x - player_x, y - player_y, tcod, game, objects);
+    game.run_snapshot = RunSnapshot::take(tcod, game, objects);
+    PlayerAction::TookTurn
+}
+
+fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
+    if game.pending_run.is_some() || !game.pending_path.is_empty() {
+        let stopped = stop_requested(tcod);
+        return if game.pending_run.is_some() {
+            continue_run(stopped, tcod, game, objects)
+        } else {
+            continue_travel(stopped, tcod, game, objects)
+        };
+    }
+
+    // a replay doesn't listen to the keys, except for Escape to stop it
+    if tcod.replay.is_playing() {
+        if tcod.key.code == tcod::input::KeyCode::Escape {
+            return PlayerAction::ExitWithoutSaving;
+        }
+        return replay_action(tcod, game, objects);
+    }
+
+    if game.wizard {
+        if let Some(action) = wizard_keys(tcod, game, objects) {
+            return action;
+        }
+    }
+
+    let pick_up = tcod.keymap.command_for(tcod.key) == Some(Command::PickUp);
+    let actions = if pick_up && objects[PLAYER].alive {
+        tile_menu(tcod, game, objects)
+    } else {
+        key_to_action(tcod, game, objects).into_iter().collect()
+    };
+    // it's all one turn, if any of it takes one
+    let mut result = PlayerAction::DidntTakeTurn;
+    for action in actions {
+        if action.changes_game() {
+            tcod.replay.write(&Decision::Act(action));
+        }
+        let outcome = apply_action(action, tcod, game, objects);
+        if outcome != PlayerAction::DidntTakeTurn {
+            result = outcome;
+        }
+    }
+    result
+}
+
+/// The cheat keys, only there in wizard mode. `None` if the key isn't
+/// one of them.
+fn wizard_keys(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut Vec<Object>,
+) -> Option<PlayerAction> {
+    use tcod::input::KeyCode::*;
+
+    if !objects[PLAYER].alive {
+        return None;
+    }
+    match tcod.key.code {
+        F1 => {
+            for tile in game.map.tiles_mut() {
+                tile.explored = true;
+            }
+        }
+        F2 => {
+            let max_hp = objects[PLAYER].max_hp(game);
+            if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+                fighter.hp = max_hp;
+                fighter.mana = fighter.max_mana;
+            }
+        }
+        F3 => wizard_spawn(tcod, game, objects),
+        F4 => match objects.iter().find(|object| object.is_stairs()) {
+            Some(stairs) => {
+                let (x, y) = stairs.pos();
+                objects[PLAYER].set_pos(x, y);
+            }
+            None => game
+                .messages
+                .add(tr!(game.strings, "wizard.no_stairs"), RED),
+        },
+        F5 => {
+            let options: Vec<String> = (1..=FINAL_LEVEL).map(|level| level.to_string()).collect();
+            let header = game.strings.get("wizard.which_level");
+            if let Some(index) = menu(header, &options, INVENTORY_WIDTH, &mut tcod.root) {
+                // `next_level` goes one further down
+                game.dungeon_level = index as u32;
+                next_level(tcod, game, objects);
+            }
+        }
+        F6 => {
+            game.god_mode = !game.god_mode;
+            let key = if game.god_mode {
+                "wizard.god_mode_on"
+            } else {
+                "wizard.god_mode_off"
+            };
+            game.messages.add(tr!(game.strings, key), LIGHT_CYAN);
+        }
+        _ => return None,
+    }
+    Some(PlayerAction::DidntTakeTurn)
+}
+
+/// Put any monster or item the game knows about next to the player.
+fn wizard_spawn(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    let spawns = &tcod.config.spawns;
+    let options: Vec<&str> = spawns
+        .monsters
+        .iter()
+        .map(|kind| kind.name.as_str())
+        .chain(spawns.items.iter().map(|kind| kind.name.as_str()))
+        .collect();
+    let header = game.strings.get("wizard.spawn");
+    let choice = match menu(header, &options, INVENTORY_WIDTH, &mut tcod.root) {
+        Some(choice) => choice,
+        None => return,
+    };
+
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let free_tile = (-1..=1)
+        .flat_map(|dy| (-1..=1).map(move |dx| (player_x + dx, player_y + dy)))
+        .find(|&(x, y)| in_map(x, y) && !is_blocked(x, y, &game.map, objects));
+    let (x, y) = match free_tile {
+        Some(tile) => tile,
+        None => {
+            game.messages.add(tr!(game.strings, "wizard.no_room"), RED);
+            return;
+        }
+    };
+    let object = match spawns.monsters.get(choice) {
+        Some(kind) => create_monster(&kind.name, x, y, spawns),
+        None => {
+            let kind = &spawns.items[choice - spawns.monsters.len()];
+            item_from_kind(kind, x, y, &mut game.rng)
+        }
+    };
+    // it gets an ID (and its turns, if it's a monster) with everything else
+    objects.push(object);
+}
+
+fn level_up(tcod: &mut Tcod, game: &mut Game, objects: &mut [Object]) {
+    let player = &mut objects[PLAYER];
+    let level_up_xp = tcod.config.level_up_base + player.level * tcod.config.level_up_factor;
+    // see if the player's experience is enough to level-up
+    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
+        // it is! level up
+        player.level += 1;
+        game.messages.add(
+            tr!(game.strings, "level_up.message", player.level, player.name),
+            YELLOW,
+        );
+        // show the totals, equipment included, as they'll end up
+        let max_hp = player.max_hp(game);
+        let power = player.power(game);
+        let defense = player.defense(game);
+        let fighter = player.fighter.as_mut().unwrap();
+        let mut options = vec![
+            tr!(game.strings, "level_up.constitution", max_hp, max_hp + 20),
+            tr!(game.strings, "level_up.strength", power, power + 1),
+            tr!(game.strings, "level_up.agility", defense, defense + 1),
+        ];
+        // mages can also just grow their mana
+        let mana_choice = if game.class == Class::Mage {
+            options.push(tr!(
+                game.strings,
+                "level_up.mana",
+                fighter.max_mana,
+                fighter.max_mana + SPELL_MANA
+            ));
+            Some(options.len() - 1)
+        } else {
+            None
+        };
+        // or learn a spell the player doesn't know yet
+        let first_spell = options.len();
+        let new_spells: Vec<Spell> = Spell::ALL
+            .iter()
+            .cloned()
+            .filter(|spell| !game.spells.contains(spell))
+            .collect();
+        options.extend(new_spells.iter().map(|spell| {
+            tr!(
+                game.strings,
+                "level_up.learn",
+                spell.name(&game.strings),
+                spell.cost()
+            )
+        }));
+        let mut header = game.strings.get("level_up.header");
+        let mut choice = None;
+        while choice.is_none() {
+            // keep asking until a choice is made, and say so if the
+            // player tries to get out of it
+            choice = decide(tcod, options.len(), |root| {
+                menu(header, &options, LEVEL_SCREEN_WIDTH, root)
+            });
+            header = game.strings.get("level_up.must_choose");
+        }
+        fighter.xp -= level_up_xp;
+        match choice.unwrap() {
+            0 => {
+                fighter.base_max_hp += 20;
+                fighter.hp += 20;
+            }
+            1 => {
+                fighter.base_power += 1;
+            }
+            2 => {
+                fighter.base_defense += 1;
+            }
+            choice if Some(choice) == mana_choice => {
+                fighter.max_mana += SPELL_MANA;
+                fighter.mana += SPELL_MANA;
+            }
+            choice => {
+                let spell = new_spells[choice - first_spell];
+                game.spells.push(spell);
+                // every spell comes with a bit more mana to cast it with
+                fighter.max_mana += SPELL_MANA;
+                fighter.mana += SPELL_MANA;
+                game.messages.add(
+                    tr!(game.strings, "spellbook.learned", spell.name(&game.strings)),
+                    LIGHT_BLUE,
+                );
+            }
+        }
+    }
+}
+
+/// Ask the player which class to play. `None` if they changed their
+/// mind.
+fn choose_class(strings: &Strings, root: &mut Root) -> Option<Class> {
+    let options: Vec<String> = Class::ALL
+        .iter()
+        .map(|class| tr!(strings, &format!("class.{}.choice", class.key())))
+        .collect();
+    menu(strings.get("menu.choose_class"), &options, 50, root).map(|index| Class::ALL[index])
+}
+
+/// Ask the player how hard the game should be. `None` if they changed
+/// their mind.
+fn choose_difficulty(strings: &Strings, root: &mut Root) -> Option<Difficulty> {
+    let options: Vec<String> = Difficulty::ALL
+        .iter()
+        .map(|difficulty| tr!(strings, &format!("difficulty.{}.choice", difficulty.key())))
+        .collect();
+    menu(strings.get("menu.choose_difficulty"), &options, 50, root)
+        .map(|index| Difficulty::ALL[index])
+}
+
+/// Let the player type in a name for their character. `None` if they
+/// pressed Escape.
+fn enter_name(strings: &Strings, root: &mut Root) -> Option<String> {
+    use tcod::input::KeyCode::{Backspace, Enter, Escape};
+
+    let header = strings.get("menu.enter_name");
+    let width = NAME_ENTRY_WIDTH;
+    let header_height = root.get_height_rect(0, 0, width, SCREEN_HEIGHT, header);
+    let height = header_height + 1;
+    let mut name = String::new();
+    loop {
+        let mut window = Offscreen::new(width, height);
+        window.set_default_foreground(WHITE);
+        window.print_rect_ex(
+            0,
+            0,
+            width,
+            height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            header,
+        );
+        // the name so far, with a cursor after it
+        window.print_ex(
+            0,
+            header_height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("{}_", name),
+        );
+
+        let x = SCREEN_WIDTH / 2 - width / 2;
+        let y = SCREEN_HEIGHT / 2 - height / 2;
+        blit(&window, (0, 0), (width, height), root, (x, y), 1.0, 0.7);
+        root.flush();
+
+        let key = root.wait_for_keypress(true);
+        match key.code {
+            Enter if !name.trim().is_empty() => return Some(name.trim().to_string()),
+            Escape => return None,
+            Backspace => {
+                name.pop();
+            }
+            _ => {
+                let letter = key.printable;
+                let allowed = letter.is_alphanumeric() || " -'".contains(letter);
+                if allowed && name.chars().count() < MAX_NAME_LENGTH {
+                    name.push(letter);
+                }
+            }
+        }
+    }
+}
+
+/// What the player can do after pressing Escape.
+fn pause_menu(tcod: &mut Tcod, game: &Game) -> PlayerAction {
+    let strings = &game.strings;
+    let mut options = vec![
+        strings.get("pause.resume"),
+        strings.get("pause.options"),
+        strings.get("pause.save_and_quit"),
+    ];
+    // with permadeath, quitting without saving would undo a bad turn
+    if !tcod.settings.permadeath {
+        options.push(strings.get("pause.quit_without_saving"));
+    }
+    match menu(
+        strings.get("pause.header"),
+        &options,
+        PAUSE_MENU_WIDTH,
+        &mut tcod.root,
+    ) {
+        Some(1) => {
+            options_menu(tcod, strings);
+            PlayerAction::DidntTakeTurn
+        }
+        Some(2) => PlayerAction::Exit,
+        Some(3) => {
+            let options = &[strings.get("menu.yes"), strings.get("menu.no")];
+            let header = strings.get("pause.confirm_quit");
+            if menu(header, options, PAUSE_MENU_WIDTH, &mut tcod.root) == Some(0) {
+                PlayerAction::ExitWithoutSaving
+            } else {
+                PlayerAction::DidntTakeTurn
+            }
+        }
+        // resume, or Escape again
+        _ => PlayerAction::DidntTakeTurn,
+    }
+}
+
+/// Let the player change the settings. Everything except the font
+/// applies straight away, and every change is saved as it's made.
+fn options_menu(tcod: &mut Tcod, strings: &Strings) {
+    let font_before = tcod.settings.font.clone();
+    loop {
+        let on_off = |on: bool| strings.get(if on { "options.on" } else { "options.off" });
+        let settings = &tcod.settings;
+        let fps = settings.limit_fps.unwrap_or(tcod.config.limit_fps);
+        let options = &[
+            tr!(strings, "options.fullscreen", on_off(settings.fullscreen)),
+            tr!(strings, "options.fps", fps),
+            tr!(strings, "options.font", settings.font),
+            tr!(
+                strings,
+                "options.colored_messages",
+                on_off(settings.colored_messages)
+            ),
+            tr!(strings, "options.animations", on_off(settings.animations)),
+            tr!(
+                strings,
+                "options.damage_flash",
+                on_off(settings.damage_flash)
+            ),
+            tr!(
+                strings,
+                "options.low_hp_warning",
+                on_off(settings.low_hp_warning)
+            ),
+        ];
+        let mut header = tr!(strings, "options.header");
+        if settings.font != font_before {
+            header.push_str(strings.get("options.restart_note"));
+        }
+        match menu(&header, options, OPTIONS_MENU_WIDTH, &mut tcod.root) {
+            Some(0) => {
+                let fullscreen = !tcod.settings.fullscreen;
+                tcod.root.set_fullscreen(fullscreen);
+                tcod.settings.fullscreen = fullscreen;
+            }
+            Some(1) => {
+                // go to the next limit up, wrapping around to the lowest
+                let next = FPS_LIMITS
+                    .iter()
+                    .cloned()
+                    .find(|&limit| limit > fps)
+                    .unwrap_or(FPS_LIMITS[0]);
+                tcod::system::set_fps(next);
+                tcod.settings.limit_fps = Some(next);
+            }
+            Some(2) => {
+                let current = FONTS.iter().position(|&font| font == tcod.settings.font);
+                let next = current.map_or(0, |index| (index + 1) % FONTS.len());
+                tcod.settings.font = FONTS[next].into();
+            }
+            Some(3) => tcod.settings.colored_messages = !tcod.settings.colored_messages,
+            Some(4) => tcod.settings.animations = !tcod.settings.animations,
+            Some(5) => tcod.settings.damage_flash = !tcod.settings.damage_flash,
+            Some(6) => tcod.settings.low_hp_warning = !tcod.settings.low_hp_warning,
+            _ => break,
+        }
+        tcod.settings.save(&tcod.paths);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlayerAction {
+    TookTurn,
+    DidntTakeTurn,
+    /// Save the game and go back to the main menu.
+    Exit,
+    /// Go back to the main menu without saving.
+    ExitWithoutSaving,
+}
+
+fn player_death(player: &mut Object, game: &mut Game) {
+    // the game ended!
+    game.messages
+        .add(tr!(game.strings, "combat.player_died", player.name), RED);
+
+    // for added effect, transform the player into a corpse!
+    player.char = '%';
+    player.color = DARK_RED;
+}
+
+fn monster_death(monster: &mut Object, game: &mut Game) {
+    // transform it into a nasty corpse! it doesn't block, can't be
+    // attacked and doesn't move
+    game.messages.add(
+        tr!(
+            game.strings,
+            "combat.monster_died",
+            monster.name,
+            monster.fighter.unwrap().xp
+        ),
+        ORANGE,
+    );
+    // drop anything it was carrying
+    if let Some(mut item) = monster.carried.take() {
+        game.messages.add(
+            tr!(game.strings, "thief.drop", monster.name, item.name),
+            LIGHT_GREEN,
+        );
+        item.set_pos(monster.x, monster.y);
+        game.pending_spawns.push(*item);
+    }
+    // monsters carry some gold, the tougher ones more of it
+    let gold = (monster.fighter.map_or(0, |f| f.xp) as f32 * GOLD_PER_XP) as u32;
+    if gold > 0 && monster.faction != Faction::Wildlife {
+        game.pending_spawns
+            .push(create_gold(monster.x, monster.y, gold));
+    }
+    // a shopkeeper's wares are up for grabs
+    for mut item in monster.stock.drain(..) {
+        item.set_pos(monster.x, monster.y);
+        game.pending_spawns.push(item);
+    }
+    // and whatever else it had on it
+    for mut item in monster.loot.drain(..) {
+        item.set_pos(monster.x, monster.y);
+        game.pending_spawns.push(item);
+    }
+    monster.char = '%';
+    monster.color = DARK_RED;
+    monster.blocks = false;
+    monster.render_order = RenderOrder::Corpse;
+    monster.fighter = None;
+    monster.ai = None;
+    game.actors.retain(|&id| id != monster.id);
+    monster.name = format!("remains of {}", monster.name);
+    // what's left can be carried off, until it rots away
+    monster.item = Some(Item::Corpse);
+    monster.rots_at = Some(game.turns + CORPSE_ROT_TURNS);
+}
+
+/// Remove everything that has rotted away.
+fn rot_corpses(game: &mut Game, objects: &mut Vec<Object>) {
+    let turns = game.turns;
+    let rotten = |object: &Object| object.rots_at.is_some_and(|turn| turn <= turns);
+    objects.retain(|object| !rotten(object));
+    let carried = game.inventory.len();
+    game.inventory.retain(|item| !rotten(item));
+    if game.inventory.len() < carried {
+        game.messages
+            .add(tr!(game.strings, "corpse.rotted"), DARK_RED);
+    }
+}
+
+impl Game {
+    /// Everything that happens once for each turn the player takes.
+    fn on_turn_passed(&mut self, config: &GameConfig, objects: &mut Vec<Object>) {
+        self.turns += 1;
+        if objects[PLAYER].alive {
+            // heal slowly over time
+            regenerate(config, self, &mut objects[PLAYER]);
+            // and get hungry
+            if config.hunger {
+                get_hungrier(self, &mut objects[PLAYER]);
+            }
+            // and the torch burns down
+            burn_torch(self);
+        }
+        // the dead don't last forever
+        rot_corpses(self, objects);
+        // and the dungeon makes its noises
+        if self.turns >= self.next_ambient {
+            play_ambient(self);
+        }
+    }
+}
+
+/// Show one of the ambient messages for this depth, and decide when the
+/// next one comes.
+fn play_ambient(game: &mut Game) {
+    let (_, messages) = AMBIENT_MESSAGES
+        .iter()
+        .rev()
+        .find(|&&(level, _)| game.dungeon_level >= level)
+        .unwrap_or(&AMBIENT_MESSAGES[0]);
+    let key = messages[game.rng.gen_range(0, messages.len())];
+    game.messages.add(tr!(game.strings, key), GREY);
+    game.next_ambient = game.turns + game.rng.gen_range(AMBIENT_MIN_TURNS, AMBIENT_MAX_TURNS + 1);
+}
+
+fn boss_death(boss: &mut Object, game: &mut Game) {
+    game.messages
+        .add(tr!(game.strings, "boss.defeated", boss.name), LIGHT_GREEN);
+    if let Some(mut crown) = boss.carried.take() {
+        crown.set_pos(boss.x, boss.y);
+        game.pending_spawns.push(*crown);
+    }
+    game.boss_defeated = true;
+    monster_death(boss, game);
+}
+
+fn new_game(
+    tcod: &mut Tcod,
+    class: Class,
+    difficulty: Difficulty,
+    name: String,
+    seed: usize,
+) -> (Game, Vec<Object>) {
+    // create object representing the player
+    let mut player = Object::new(0, 0, '@', &name, WHITE, true);
+    player.alive = true;
+    player.is_player = true;
+    player.fighter = Some(Fighter {
+        base_max_hp: 100,
+        hp: 100,
+        base_defense: 1,
+        base_power: 1,
+        damage: Dice::new(1, 3, 0),
+        crit_chance: CRIT_CHANCE,
+        speed: NORMAL_SPEED,
+        mana: 0,
+        max_mana: 0,
+        xp: 0,
+        on_death: DeathCallback::Player,
+    });
+    player.faction = Faction::Player;
+    player.energy = ACTION_COST;
+
+    // the list of objects with just the player
+    let mut objects = vec![player];
+
+    // generate map (at this point it's not drawn to the screen)
+    let mut rng = seeded_rng(seed);
+    let (map, first_level) = make_map(&mut objects, 1, difficulty, &tcod.config, &mut rng);
+
+    let mut game = Game {
+        map,
+        messages: Messages::new(),
+        inventory: vec![],
+        dungeon_level: 1,
+        strings: Strings::load(&tcod.settings.language, &active_mods()),
+        pending_spawns: vec![],
+        mods: active_mods().iter().map(|dir| mod_name(dir)).collect(),
+        boss_defeated: false,
+        next_object_id: 1,
+        save_slot: 0,
+        last_damage_source: String::new(),
+        turns: 0,
+        pending_run: None,
+        run_snapshot: Default::default(),
+        fires: vec![],
+        last_hurt_turn: 0,
+        gold: 0,
+        hunger: 0,
+        spells: vec![],
+        class,
+        player_name: name,
+        difficulty,
+        actors: vec![],
+        hits: vec![],
+        player_hurt: false,
+        next_ambient: AMBIENT_MIN_TURNS,
+        wizard: tcod.wizard,
+        god_mode: false,
+        pending_path: vec![],
+        rng,
+        fled: vec![],
+    };
+
+    setup_class(class, &mut objects[PLAYER], &mut game, &tcod.config.spawns);
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        let percent = difficulty.rules().starting_hp_percent;
+        fighter.base_max_hp = fighter.base_max_hp * percent / 100;
+        fighter.hp = fighter.base_max_hp;
+    }
+
+    // and a torch to see by
+    let mut torch = create_item(Item::Torch, 0, 0, &tcod.config.spawns, &mut game.rng);
+    torch.charges = Some(TORCH_FUEL);
+    game.inventory.push(torch);
+
+    initialise_fov(tcod, &game.map);
+
+    // a warm welcoming message!
+    game.messages.add(tr!(game.strings, "game.welcome"), RED);
+    game.messages
+        .add(tr!(game.strings, first_level.feeling()), LIGHT_VIOLET);
+    if game.wizard {
+        game.messages
+            .add(tr!(game.strings, "wizard.keys"), LIGHT_CYAN);
+    }
+
+    (game, objects)
+}
+
+/// Give a new player the stats, gear and spells of their class.
+fn setup_class(class: Class, player: &mut Object, game: &mut Game, spawns: &SpawnTables) {
+    match class {
+        Class::Warrior => setup_warrior(player, game, spawns),
+        Class::Rogue => setup_rogue(player, game, spawns),
+        Class::Mage => setup_mage(player, game, spawns),
+    }
+}
+
+/// Tough and strong, with a sword and a shield.
+fn setup_warrior(player: &mut Object, game: &mut Game, spawns: &SpawnTables) {
+    if let Some(fighter) = player.fighter.as_mut() {
+        fighter.base_max_hp = 120;
+        fighter.hp = 120;
+        fighter.base_power = 2;
+    }
+    for item in &[Item::Sword, Item::Shield] {
+        let mut item = create_item(*item, 0, 0, spawns, &mut game.rng);
+        if let Some(ref mut equipment) = item.equipment {
+            equipment.equipped = true;
+        }
+        game.inventory.push(item);
+    }
+}
+
+/// Frail, but hard to hit and good at finding the weak spots. Comes
+/// with a dagger and a few knives to throw.
+fn setup_rogue(player: &mut Object, game: &mut Game, spawns: &SpawnTables) {
+    if let Some(fighter) = player.fighter.as_mut() {
+        fighter.base_max_hp = 80;
+        fighter.hp = 80;
+        fighter.base_defense = 2;
+        fighter.crit_chance = CRIT_CHANCE * 3.0;
+    }
+    game.inventory.push(starting_dagger());
+    let mut knives = create_item(Item::ThrowingKnife, 0, 0, spawns, &mut game.rng);
+    knives.count = ROGUE_KNIVES;
+    game.inventory.push(knives);
+}
+
+/// The weakest in a fight, but knows magic missile from the start and
+/// has the mana for it, and a couple of scrolls.
+fn setup_mage(player: &mut Object, game: &mut Game, spawns: &SpawnTables) {
+    if let Some(fighter) = player.fighter.as_mut() {
+        fighter.base_max_hp = 70;
+        fighter.hp = 70;
+        fighter.max_mana = 2 * SPELL_MANA;
+        fighter.mana = 2 * SPELL_MANA;
+    }
+    game.spells.push(Spell::MagicMissile);
+    game.inventory.push(starting_dagger());
+    for item in &[Item::Lightning, Item::Confuse] {
+        game.inventory
+            .push(create_item(*item, 0, 0, spawns, &mut game.rng));
+    }
+}
+
+/// The dagger the rogue and the mage start out with.
+fn starting_dagger() -> Object {
+    let mut dagger = Object::new(0, 0, '-', "dagger", SKY, false);
+    dagger.item = Some(Item::Sword);
+    dagger.equipment = Some(Equipment {
+        equipped: true,
+        slot: Slot::LeftHand,
+        max_hp_bonus: 0,
+        defense_bonus: 0,
+        power_bonus: 1,
+        damage: Some(Dice::new(1, 4, 0)),
+        cursed: false,
+        curse_known: false,
+    });
+    dagger
+}
+
+/// Copy the tile at (x, y) into the FOV map. Call this whenever a tile
+/// changes whether it can be seen or walked through, and make sure the
+/// FOV gets recomputed afterwards.
+fn update_fov_tile(fov: &mut FovMap, map: &Map, x: i32, y: i32) {
+    let tile = &map[(x, y)];
+    fov.set(x, y, !tile.block_sight, !tile.blocked);
+}
+
+/// Build the FOV map from scratch, e.g. for a new level.
+fn initialise_fov(tcod: &mut Tcod, map: &Map) {
+    for y in 0..MAP_HEIGHT {
+        for x in 0..MAP_WIDTH {
+            update_fov_tile(&mut tcod.fov, map, x, y);
+        }
+    }
+}
+
+fn play_game(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) {
+    // force FOV "recompute" first time through the game loop
+    let mut previous_player_position = (-1, -1);
+    let mut victory_shown = game.boss_defeated;
+    // the first frame always has to be drawn
+    let mut redraw = true;
+    let mut previous_mouse_cell = (-1, -1);
+    let mut frame_times = FrameTimes::new();
+    game.find_actors(objects);
+
+    while !tcod.root.window_closed() {
+        game.assign_ids(objects);
+
+        // a key press or a click only counts once
+        tcod.key = Default::default();
+        tcod.mouse.lbutton_pressed = false;
+        tcod.mouse.rbutton_pressed = false;
+        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+            Some((_, Event::Mouse(m))) => tcod.mouse = m,
+            Some((_, Event::Key(k))) => tcod.key = k,
+            _ => {}
+        }
+
+        // only draw the screen when something on it could have changed
+        let fov_recompute = previous_player_position != (objects[PLAYER].pos());
+        let mouse_cell = (tcod.mouse.cx, tcod.mouse.cy);
+        let had_input = tcod.key.code != input::KeyCode::NoKey
+            || tcod.mouse.lbutton_pressed
+            || tcod.mouse.rbutton_pressed
+            || mouse_cell != previous_mouse_cell;
+        previous_mouse_cell = mouse_cell;
+        // the debug overlay has to keep its numbers up to date
+        let overlay = tcod.debug_overlay != DebugOverlay::Off;
+        let drawing = if redraw || had_input || fov_recompute || overlay {
+            let started = Instant::now();
+            tcod.con.clear();
+            render_all(tcod, game, objects, fov_recompute);
+            Some(started.elapsed())
+        } else {
+            None
+        };
+        frame_times.record(drawing);
+        tcod.frame_clock.tick();
+
+        tcod.root.flush();
+
+        // level up if needed
+        let level_before = objects[PLAYER].level;
+        level_up(tcod, game, objects);
+
+        // handle keys and exit game if needed
+        previous_player_position = objects[PLAYER].pos();
+        let player_was_alive = objects[PLAYER].alive;
+        let light_before = light_radius(game, &tcod.config);
+        let player_action = if objects[PLAYER].alive && objects[PLAYER].lose_stunned_turn(game) {
+            PlayerAction::TookTurn
+        } else {
+            handle_keys(tcod, game, objects)
+        };
+        if player_action == PlayerAction::Exit {
+            if let Err(e) = save_on_exit(&tcod.paths, &tcod.settings, game, objects) {
+                eprintln!("Could not save the game: {}", e);
+                let text = tr!(game.strings, "game.save_failed", e);
+                msgbox(&text, 50, &mut tcod.root);
+            }
+            break;
+        }
+        if player_action == PlayerAction::ExitWithoutSaving {
+            break;
+        }
+        redraw = had_input
+            || player_action != PlayerAction::DidntTakeTurn
+            || objects[PLAYER].level != level_before;
+
+        // gold is picked up just by walking over it
+        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
+            pick_up_gold(game, objects);
+        }
+
+        // the player has used up their energy: run the world until they have
+        // enough to act again
+        if objects[PLAYER].alive && player_action != PlayerAction::DidntTakeTurn {
+            objects[PLAYER].energy -= ACTION_COST;
+            let view = View::of(tcod);
+            while objects[PLAYER].alive && objects[PLAYER].energy < ACTION_COST {
+                world_tick(&view, game, objects);
+            }
+        }
+        // show everyone who got hit this turn, by the player or the monsters
+        animate_hits(tcod, game, objects);
+        // make sure the player notices getting hurt
+        if std::mem::take(&mut game.player_hurt) && tcod.settings.damage_flash {
+            flash_damage(tcod, game, objects);
+        }
+
+        if player_action != PlayerAction::DidntTakeTurn {
+            game.on_turn_passed(&tcod.config, objects);
+        }
+        if light_radius(game, &tcod.config) != light_before {
+            previous_player_position = (-1, -1);
+        }
+
+        // put everything created during this turn on the map
+        add_pending_spawns(game, objects);
+
+        // there's no coming back from the dead. A replay leaves the saves alone
+        if !objects[PLAYER].alive && tcod.settings.permadeath && !tcod.replay.is_playing() {
+            delete_save(&tcod.paths.save(game.save_slot));
+        }
+
+        // the run is over: show how it went
+        if player_was_alive && !objects[PLAYER].alive {
+            show_morgue(tcod, game, objects);
+        }
+
+        // the king is dead!
+        if game.boss_defeated && !victory_shown {
+            render_all(tcod, game, objects, false);
+            msgbox(&tr!(game.strings, "victory"), 50, &mut tcod.root);
+            victory_shown = true;
+            show_morgue(tcod, game, objects);
+        }
+
+        // catch broken objects while we still know which turn broke them
+        if cfg!(debug_assertions) {
+            warn_once(game.validate(objects), &mut tcod.warned);
+        }
+    }
+
+    // closing the window leaves the game too. There's nothing to save when
+    // watching a replay
+    if tcod.root.window_closed() && !tcod.replay.is_playing() {
+        // there's no window left to show an error in
+        if let Err(e) = save_on_exit(&tcod.paths, &tcod.settings, game, objects) {
+            eprintln!("Could not save the game: {}", e);
+        }
+    }
+}
+
+/// Where the game keeps the files it writes.
+struct Paths {
+    data_dir: PathBuf,
+}
+
+impl Paths {
+    /// Find the data directory and create it if it doesn't exist yet.
+    fn new() -> Self {
+        let data_dir = env::var_os("TOMBS_DATA_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(default_data_dir);
+        if let Err(e) = fs::create_dir_all(&data_dir) {
+            eprintln!("Could not create {}: {}", data_dir.display(), e);
+        }
+        Paths { data_dir }
+    }
+
+    /// The file the game in the given save slot is stored in.
+    fn save(&self, slot: usize) -> PathBuf {
+        if slot == 0 {
+            self.data_dir.join("savegame")
+        } else {
+            self.data_dir.join(format!("savegame{}", slot + 1))
+        }
+    }
+
+    fn high_scores(&self) -> PathBuf {
+        self.data_dir.join("highscores.json")
+    }
+
+    /// The morgue file for a run that ended at `time`.
+    fn morgue(&self, time: u64) -> PathBuf {
+        self.data_dir.join(format!("morgue-{}.txt", time))
+    }
+
+    /// The screenshot taken at `time`.
+    fn screenshot(&self, time: u64) -> PathBuf {
+        self.data_dir.join(format!("screenshot-{}.txt", time))
+    }
+
+    /// The recording of the last run started in the given save slot.
+    fn replay(&self, slot: usize) -> PathBuf {
+        self.data_dir.join(format!("replay{}.jsonl", slot + 1))
+    }
+}
+
+/// The usual place for per-user game data on this operating system.
+fn default_data_dir() -> PathBuf {
+    let home = env::var_os("HOME").map(PathBuf::from);
+    let base = if cfg!(windows) {
+        env::var_os("APPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        home.map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env::var_os("XDG_DATA_HOME")
+            .map(PathBuf::from)
+            .or_else(|| home.map(|home| home.join(".local").join("share")))
+    };
+    match base {
+        Some(base) => base.join("tombs-of-the-ancient-kings"),
+        // no idea where home is, so stay where we are
+        None => PathBuf::from("."),
+    }
+}
+
+impl Paths {
+    fn settings(&self) -> PathBuf {
+        self.data_dir.join("settings.json")
+    }
+}
+
+/// How a recorded run started: everything `new_game` needs to start it
+/// the same way again.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct ReplayHeader {
+    seed: usize,
+    class: Class,
+    difficulty: Difficulty,
+    name: String,
+    mods: Vec<String>,
+}
+
+/// Something the player decided, in the order the game asked.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Decision {
+    /// What a key press or a click turned into.
+    Act(Action),
+    /// A choice in a menu the game showed in the middle of a turn.
+    Choose(Option<usize>),
+    /// Where the player aimed, or `None` if they changed their mind.
+    Aim(Option<(i32, i32)>),
+    /// The player stopped a run or a walk.
+    Stop,
+}
+
+impl Action {
+    /// Does doing this change the game? The rest (looking around, the
+    /// help screen and such) is left out of replays.
+    fn changes_game(self) -> bool {
+        use Action::*;
+
+        match self {
+            Move(..) | Run(..) | Travel(..) | Wait | PickUp(_) | PickUpAll | TakeOut(..)
+            | Butcher(_) | UseItem(_) | DropItem(_) | ThrowItem(_) | CastSpell(_) | Fire
+            | Descend | ShowEquipment => true,
+            Look | ShowHistory | ShowHelp | Screenshot | ToggleDebugOverlay | ToggleHealthBars
+            | ShowCharacter | ToggleFullscreen | Exit => false,
+        }
+    }
+}
+
+/// Writes the player's decisions down as they play, or plays them back.
+enum Replay {
+    /// Loaded games aren't recorded: the random number generator isn't
+    /// saved with them, so they couldn't be played back.
+    Off,
+    /// One line of JSON with the `ReplayHeader`, then one per `Decision`.
+    Recording(File),
+    Playing {
+        decisions: VecDeque<Decision>,
+        /// How many decisions have been played back so far.
+        step: usize,
+    },
+}
+
+impl Replay {
+    /// Start recording a new run into `path`.
+    fn record(path: &Path, header: &ReplayHeader) -> Self {
+        let started = File::create(path).and_then(|mut file| {
+            writeln!(file, "{}", serde_json::to_string(header)?)?;
+            Ok(file)
+        });
+        match started {
+            Ok(file) => Replay::Recording(file),
+            Err(e) => {
+                eprintln!("Could not record the run in {}: {}", path.display(), e);
+                Replay::Off
+            }
+        }
+    }
+
+    /// Read a recorded run: how it started and everything the player
+    /// decided.
+    fn load(path: &Path) -> Result<(ReplayHeader, Replay), Box<dyn Error>> {
+        let text = fs::read_to_string(path)?;
+        let mut lines = text.lines();
+        let header = serde_json::from_str(lines.next().ok_or("the file is empty")?)?;
+        let mut decisions = VecDeque::new();
+        for (number, line) in lines.enumerate() {
+            let decision =
+                serde_json::from_str(line).map_err(|e| format!("line {}: {}", number + 2, e))?;
+            decisions.push_back(decision);
+        }
+        Ok((header, Replay::Playing { decisions, step: 0 }))
+    }
+
+    fn is_playing(&self) -> bool {
+        matches!(self, Replay::Playing { .. })
+    }
+
+    /// Write a decision down, if we're recording. A run that can't be
+    /// written down stops being recorded, it doesn't stop the game.
+    fn write(&mut self, decision: &Decision) {
+        if let Replay::Recording(file) = self {
+            let written = serde_json::to_string(decision)
+                .map_err(std::io::Error::from)
+                .and_then(|line| writeln!(file, "{}", line));
+            if let Err(e) = written {
+                eprintln!("Could not record the run: {}", e);
+                *self = Replay::Off;
+            }
+        }
+    }
+
+    /// The next decision of the replay, `None` at the end of it.
+    fn next(&mut self) -> Option<Decision> {
+        match self {
+            Replay::Playing { decisions, step } => {
+                let decision = decisions.pop_front()?;
+                *step += 1;
+                Some(decision)
+            }
+            _ => None,
+        }
+    }
+
+    /// Take a recorded `Stop`, if that's what comes next.
+    fn take_stop(&mut self) -> bool {
+        match self {
+            Replay::Playing { decisions, .. } if decisions.front() == Some(&Decision::Stop) => {
+                self.next();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Give up on a replay that no longer matches the game.
+    fn out_of_sync(&self, problem: &str) -> ! {
+        let step = match self {
+            Replay::Playing { step, .. } => *step,
+            _ => 0,
+        };
+        eprintln!(
+            "The replay went out of sync at decision {}: {}",
+            step, problem
+        );
+        std::process::exit(1);
+    }
+}
+
+/// Ask the player to choose one of `count` options in the middle of
+/// their turn. A replay has the answer written down already.
+fn decide<F>(tcod: &mut Tcod, count: usize, ask: F) -> Option<usize>
+where
+    F: FnOnce(&mut Root) -> Option<usize>,
+{
+    if tcod.replay.is_playing() {
+        return match tcod.replay.next() {
+            Some(Decision::Choose(choice)) if choice.is_none_or(|index| index < count) => choice,
+            other => tcod.replay.out_of_sync(&format!(
+                "expected a choice of {} options, found {:?}",
+                count, other
+            )),
+        };
+    }
+    let choice = ask(&mut tcod.root);
+    tcod.replay.write(&Decision::Choose(choice));
+    choice
+}
+
+/// Did the player ask to stop running or walking? A replay has that
+/// written down; otherwise any key press or click does it.
+fn stop_requested(tcod: &mut Tcod) -> bool {
+    if tcod.replay.is_playing() {
+        return tcod.replay.take_stop();
+    }
+    let stop = tcod.key.code != tcod::input::KeyCode::NoKey
+        || tcod.mouse.lbutton_pressed
+        || tcod.mouse.rbutton_pressed;
+    if stop {
+        tcod.replay.write(&Decision::Stop);
+    }
+    stop
+}
+
+/// Could the player do this right now? A recorded action they couldn't
+/// means the replay and the game have gone their separate ways.
+fn check_action(action: Action, game: &Game, objects: &[Object]) -> Result<(), String> {
+    let player = &objects[PLAYER];
+    let in_inventory = |index: usize| {
+        if index < game.inventory.len() {
+            Ok(())
+        } else {
+            Err(format!(
+                "there's no item {} in an inventory of {}",
+                index,
+                game.inventory.len()
+            ))
+        }
+    };
+    match action {
+        _ if !player.alive => Err(format!(
+            "the player is dead, but the replay goes on with {:?}",
+            action
+        )),
+        Action::UseItem(index) | Action::DropItem(index) | Action::ThrowItem(index) => {
+            in_inventory(index)
+        }
+        Action::PickUp(id) => {
+            let under_player = objects.iter().any(|object| {
+                object.id == id && object.item.is_some() && object.pos() == player.pos()
+            });
+            if under_player {
+                Ok(())
+            } else {
+                Err(format!(
+                    "there's no item with the ID {} under the player",
+                    id
+                ))
+            }
+        }
+        Action::TakeOut(id, slot) => {
+            let in_reach = objects.iter().any(|object| {
+                object.id == id
+                    && object.pos() == player.pos()
+                    && object
+                        .contents
+                        .as_ref()
+                        .is_some_and(|contents| slot < contents.len())
+            });
+            if in_reach {
+                Ok(())
+            } else {
+                Err(format!(
+                    "there's no container with the ID {} and a thing at {} in it under the player",
+                    id, slot
+                ))
+            }
+        }
+        Action::Butcher(id) => {
+            let under_player = objects.iter().any(|object| {
+                object.id == id && object.item == Some(Item::Corpse) && object.pos() == player.pos()
+            });
+            if under_player {
+                Ok(())
+            } else {
+                Err(format!(
+                    "there's no corpse with the ID {} under the player",
+                    id
+                ))
+            }
+        }
+        Action::CastSpell(spell) if !game.spells.contains(&spell) => {
+            Err(format!("the player doesn't know {:?}", spell))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Play the next recorded action.
+fn replay_action(tcod: &mut Tcod, game: &mut Game, objects: &mut Vec<Object>) -> PlayerAction {
+    match tcod.replay.next() {
+        Some(Decision::Act(action)) => {
+            if let Err(problem) = check_action(action, game, objects) {
+                tcod.replay.out_of_sync(&problem);
+            }
+            apply_action(action, tcod, game, objects)
+        }
+        Some(other) => tcod
+            .replay
+            .out_of_sync(&format!("expected an action, found {:?}", other)),
+        None => {
+            msgbox(&tr!(game.strings, "replay.over"), 30, &mut tcod.root);
+            PlayerAction::ExitWithoutSaving
+        }
+    }
+}
+
+/// Play back a recorded run, as fast as the computer can with `fast`.
+fn watch_replay(tcod: &mut Tcod, path: &Path, fast: bool) {
+    let (header, replay) = match Replay::load(path) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("Could not read the replay {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    };
+    // different monsters or items make for a different game
+    let mods: Vec<String> = active_mods().iter().map(|dir| mod_name(dir)).collect();
+    if header.mods != mods {
+        eprintln!(
+            "The replay was recorded with the mods {:?}, but these are active: {:?}",
+            header.mods, mods
+        );
+        std::process::exit(1);
+    }
+    if fast {
+        tcod::system::set_fps(0);
+        tcod.settings.animations = false;
+    }
+    // the cheat keys aren't recorded
+    tcod.wizard = false;
+    let (mut game, mut objects) = new_game(
+        tcod,
+        header.class,
+        header.difficulty,
+        header.name,
+        header.seed,
+    );
+    tcod.replay = replay;
+    play_game(tcod, &mut game, &mut objects);
+}
+
+fn save_game(path: &Path, game: &Game, objects: &[Object]) -> Result<(), Box<dyn Error>> {
+    // cosmetic objects don't belong in the save
+    let objects: Vec<&Object> = objects.iter().filter(|o| !o.transient).collect();
+    let save_data = serde_json::to_string(&(SAVE_VERSION, game, &objects))?;
+    let mut file = File::create(path)?;
+    file.write_all(save_data.as_bytes())?;
+
+    let summary = SaveSummary {
+        name: game.player_name.clone(),
+        level: objects[PLAYER].level,
+        dungeon_level: game.dungeon_level,
+        saved_at: now(),
+    };
+    fs::write(summary_path(path), serde_json::to_string(&summary)?)?;
+    Ok(())
+}
+
+/// Save the game the player is leaving, unless the run is over for good.
+fn save_on_exit(
+    paths: &Paths,
+    settings: &Settings,
+    game: &Game,
+    objects: &[Object],
+) -> Result<(), Box<dyn Error>> {
+    if objects[PLAYER].alive || !settings.permadeath {
+        save_game(&paths.save(game.save_slot), game, objects)?;
+    }
+    Ok(())
+}
+
+/// Remove the saved game at `path`, if there is one.
+fn delete_save(path: &Path) {
+    let _ = fs::remove_file(path);
+    let _ = fs::remove_file(summary_path(path));
+}
+
+/// The reasons a saved game can't be loaded.
+#[derive(Debug)]
+enum LoadError {
+    /// There's no saved game in the slot.
+    NoFile,
+    /// The file exists, but we can't make sense of it.
+    Corrupt(Box<dyn Error>),
+    /// The game was saved by a newer version of the game.
+    Incompatible(u64),
+}
+
+fn load_game(path: &Path) -> Result<(Game, Vec<Object>), LoadError> {
+    let mut json_save_state = String::new();
+    let mut file = File::open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => LoadError::NoFile,
+        _ => LoadError::Corrupt(e.into()),
+    })?;
+    file.read_to_string(&mut json_save_state)
+        .map_err(|e| LoadError::Corrupt(e.into()))?;
+
+    let mut save: serde_json::Value =
+        serde_json::from_str(&json_save_state).map_err(|e| LoadError::Corrupt(e.into()))?;
+    let version = match save.as_array().map(|items| items.as_slice()) {
+        // before we had versions, the save was just `(game, objects)`
+        Some([_game, _objects]) => 0,
+        Some([version, _game, _objects]) => match version.as_u64() {
+            Some(version) => version,
+            None => return Err(LoadError::Corrupt("invalid save version".into())),
+        },
+        _ => return Err(LoadError::Corrupt("unknown save format".into())),
+    };
+    if version > SAVE_VERSION {
+        return Err(LoadError::Incompatible(version));
+    }
+    migrate_save(&mut save, version);
+
+    let (_version, game, mut objects): (u64, Game, Vec<Object>) =
+        serde_json::from_value(save).map_err(|e| LoadError::Corrupt(e.into()))?;
+    // older saves only told the player apart by its name
+    if let Some(player) = objects.get_mut(PLAYER) {
+        player.is_player = true;
+    }
+    Ok((game, objects))
+}
+
+/// Upgrade a save from an older `version` to the current format.
+fn migrate_save(save: &mut serde_json::Value, version: u64) {
+    if version < 1 {
+        // version 0 didn't store the version number
+        if let Some(items) = save.as_array_mut() {
+            items.insert(0, serde_json::Value::from(1));
+        }
+    }
+    if version < 2 {
+        // version 1 didn't know the order to draw the objects in
+        let objects = save.get_mut(2).and_then(|objects| objects.as_array_mut());
+        for object in objects.into_iter().flatten() {
+            let order = old_render_order(object);
+            if let Some(fields) = object.as_object_mut() {
+                fields.insert("render_order".into(), serde_json::Value::from(order));
+            }
+        }
+    }
+    // future migrations go here: `if version < 3 { ... }`
+}
+
+/// The drawing order of an object from a version 1 save.
+fn old_render_order(object: &serde_json::Value) -> &'static str {
+    let name = object["name"].as_str().unwrap_or("");
+    if object["blocks"] == true {
+        "Actor"
+    } else if name == "stairs" {
+        "Stairs"
+    } else if object["item"] == "Corpse" || name.starts_with("remains of ") {
+        "Corpse"
+    } else {
+        "Item"
+    }
+}
+
+/// How many of the last messages go into the morgue file.
+const MORGUE_MESSAGES: usize = 20;
+
+/// Describe the run so far: the character, their gear and what
+/// happened last.
+fn morgue_text(game: &Game, objects: &[Object]) -> String {
+    let player = &objects[PLAYER];
+    let strings = &game.strings;
+    let mut lines = vec![];
+
+    let outcome = if !player.alive {
+        tr!(
+            strings,
+            "morgue.killed_by",
+            game.last_damage_source,
+            game.dungeon_level
+        )
+    } else if game.boss_defeated {
+        tr!(strings, "morgue.victory")
+    } else {
+        tr!(strings, "morgue.quit", game.dungeon_level)
+    };
+    lines.push(outcome);
+    lines.push(tr!(
+        strings,
+        "morgue.character",
+        player.level,
+        player.fighter.map_or(0, |f| f.xp),
+        game.dungeon_level,
+        game.turns,
+        player.name,
+        game.class.name(strings),
+        game.difficulty.name(strings)
+    ));
+    // a run with mods may not be the game the reader knows
+    if !game.mods.is_empty() {
+        lines.push(tr!(strings, "morgue.mods", game.mods.join(", ")));
+    }
+
+    lines.push(String::new());
+    lines.push(tr!(strings, "morgue.inventory"));
+    for item in &game.inventory {
+        let name = match item.equipment {
+            Some(equipment) if equipment.equipped => {
+                tr!(
+                    strings,
+                    "inventory.equipped_on",
+                    item.name,
+                    equipment.slot.name(strings)
+                )
+            }
+            _ => item.stack_name(strings),
+        };
+        lines.push(format!("- {}", name));
+    }
+
+    lines.push(String::new());
+    lines.push(tr!(strings, "morgue.messages"));
+    let messages: Vec<_> = game.messages.iter().collect();
+    let first = messages.len().saturating_sub(MORGUE_MESSAGES);
+    for message in &messages[first..] {
+        lines.push(format!("- {}", message));
+    }
+
+    lines.join("\n")
+}
+
+/// Write the morgue file for this run and return its name.
+fn write_morgue(paths: &Paths, text: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = paths.morgue(now());
+    fs::write(&path, text)?;
+    Ok(path)
+}
+
+/// Whether the player can see this object on the map.
+fn in_view(object: &Object, map: &Map, fov: &FovMap) -> bool {
+    in_map(object.x, object.y)
+        && (fov.is_in_fov(object.x, object.y)
+            || (object.always_visible && map[(object.x, object.y)].explored))
+}
+
+/// The map as text, the way the player sees it: only the explored
+/// tiles, with the objects in view on top.
+fn map_text(map: &Map, objects: &[Object], fov: &FovMap) -> Vec<String> {
+    let mut grid: Vec<Vec<char>> = (0..MAP_HEIGHT)
+        .map(|y| {
+            (0..MAP_WIDTH)
+                .map(|x| {
+                    let tile = &map[(x, y)];
+                    if !tile.explored {
+                        ' '
+                    } else if tile.block_sight {
+                        '#'
+                    } else {
+                        '.'
+                    }
+                })
+                .collect()
+        })
+        .collect();
+    let mut shown: Vec<&Object> = objects
+        .iter()
+        .filter(|object| in_view(object, map, fov))
+        .collect();
+    shown.sort_by_key(|object| object.render_order);
+    for object in shown {
+        grid[object.y as usize][object.x as usize] = object.char;
+    }
+    grid.into_iter()
+        .map(|row| row.into_iter().collect::<String>().trim_end().to_string())
+        .collect()
+}
+
+/// Everything on the screen as text: the map, the panel and the latest
+/// messages.
+fn screenshot_text(tcod: &Tcod, game: &Game, objects: &[Object]) -> String {
+    let mut lines = map_text(&game.map, objects, &tcod.fov);
+
+    // the panel is already drawn, so it can be read back as it is
+    lines.push(String::new());
+    for y in 0..PANEL_HEIGHT {
+        let row: String = (0..SCREEN_WIDTH)
+            .map(|x| tcod.panel.get_char(x, y))
+            .collect();
+        lines.push(row.trim_end().to_string());
+    }
+
+    lines.push(String::new());
+    lines.push(tr!(game.strings, "morgue.messages"));
+    let messages: Vec<_> = game.messages.iter().collect();
+    let first = messages.len().saturating_sub(MORGUE_MESSAGES);
+    for message in &messages[first..] {
+        lines.push(format!("- {}", message));
+    }
+    lines.join("\n")
+}
+
+fn take_screenshot(tcod: &mut Tcod, game: &mut Game, objects: &[Object]) {
+    let text = screenshot_text(tcod, game, objects);
+    let path = tcod.paths.screenshot(now());
+    match fs::write(&path, text) {
+        Ok(()) => game.messages.add(
+            tr!(game.strings, "screenshot.saved", path.display()),
+            LIGHT_GREY,
+        ),
+        Err(e) => game
+            .messages
+            .add(tr!(game.strings, "screenshot.failed", e), RED),
+    }
+}
+
+/// Numbers that help with finding bugs, on top of everything else.
+fn draw_debug_overlay(tcod: &mut Tcod, game: &Game, objects: &[Object]) {
+    if tcod.debug_overlay == DebugOverlay::Fov {
+        for y in 0..MAP_HEIGHT {
+            for x in 0..MAP_WIDTH {
+                if tcod.fov.is_transparent(x, y) {
+                    tcod.root
+                        .set_char_background(x, y, DARKEST_BLUE, BackgroundFlag::Add);
+                }
+                if tcod.fov.is_walkable(x, y) {
+                    tcod.root
+                        .set_char_background(x, y, DARKEST_GREEN, BackgroundFlag::Add);
+                }
+            }
+        }
+    }
+
+    let player = &objects[PLAYER];
+    let lines = [
+        tr!(
+            game.strings,
+            "debug.fps",
+            format!("{:.1}", tcod.frame_clock.fps())
+        ),
+        tr!(
+            game.strings,
+            "debug.objects",
+            objects.len(),
+            game.actors.len()
+        ),
+        tr!(game.strings, "debug.position", player.x, player.y),
+        tr!(game.strings, "debug.dungeon_level", game.dungeon_level),
+    ];
+    tcod.root.set_default_foreground(WHITE);
+    tcod.root.set_default_background(BLACK);
+    for (y, line) in lines.iter().enumerate() {
+        tcod.root.print_ex(
+            SCREEN_WIDTH - 1,
+            y as i32,
+            BackgroundFlag::Set,
+            TextAlignment::Right,
+            line,
+        );
+    }
+}
+
+fn show_morgue(tcod: &mut Tcod, game: &mut Game, objects: &[Object]) {
+    // a replayed run was counted when it was played
+    if !tcod.replay.is_playing() {
+        record_high_score(&tcod.paths, game, objects);
+    }
+    let mut text = morgue_text(game, objects);
+    if !tcod.replay.is_playing() {
+        match write_morgue(&tcod.paths, &text) {
+            Ok(path) => {
+                let note = tr!(game.strings, "morgue.saved", path.display());
+                text = format!("{}\n\n{}", note, text);
+            }
+            Err(e) => eprintln!("Could not write the morgue file: {}", e),
+        }
+    }
+    render_all(tcod, game, objects, false);
+    scroll_box(&text, &mut tcod.root);
+}
+
+/// A finished run in the hall of heroes.
+#[derive(Serialize, Deserialize)]
+struct HighScore {
+    name: String,
+    level: i32,
+    dungeon_level: u32,
+    xp: i32,
+    turns: u32,
+    #[serde(default)]
+    gold: i32,
+    #[serde(default)]
+    difficulty: Difficulty,
+    /// What killed the character, or `None` if they won.
+    killed_by: Option<String>,
+}
+
+/// Points for each dungeon level reached.
+const DEPTH_BONUS: i32 = 50;
+
+fn score(entry: &HighScore) -> i32 {
+    entry.xp + DEPTH_BONUS * entry.dungeon_level as i32 + entry.gold
+}
+
+/// How many runs the hall of heroes remembers.
+const HALL_SIZE: usize = 10;
+
+fn load_high_scores(paths: &Paths) -> Vec<HighScore> {
+    fs::read_to_string(paths.high_scores())
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Add the run that just ended to the hall of heroes.
+fn record_high_score(paths: &Paths, game: &Game, objects: &[Object]) {
+    // a run with the cheat keys doesn't count
+    if game.wizard {
+        return;
+    }
+    let player = &objects[PLAYER];
+    let mut scores = load_high_scores(paths);
+    scores.push(HighScore {
+        name: player.name.clone(),
+        level: player.level,
+        dungeon_level: game.dungeon_level,
+        xp: player.fighter.map_or(0, |f| f.xp),
+        turns: game.turns,
+        gold: game.gold,
+        difficulty: game.difficulty,
+        killed_by: if player.alive {
+            None
+        } else {
+            Some(game.last_damage_source.clone())
+        },
+    });
+    rank_high_scores(&mut scores);
+
+    if let Err(e) = save_high_scores(paths, &scores) {
+        eprintln!("Could not save the high scores: {}", e);
+    }
+}
+
+/// Put the best runs first and forget the ones that didn't make it.
+fn rank_high_scores(scores: &mut Vec<HighScore>) {
+    // if two runs have the same score, the quicker one wins
+    scores.sort_by_key(|entry| (-score(entry), entry.turns));
+    scores.truncate(HALL_SIZE);
+}
+
+fn save_high_scores(paths: &Paths, scores: &[HighScore]) -> Result<(), Box<dyn Error>> {
+    fs::write(paths.high_scores(), serde_json::to_string(scores)?)?;
+    Ok(())
+}
+
+/// A short description of a saved game, stored next to it so the
+/// main menu can show it without loading the whole game.
+#[derive(Serialize, Deserialize)]
+struct SaveSummary {
+    #[serde(default = "default_player_name")]
+    name: String,
+    level: i32,
+    dungeon_level: u32,
+    /// When the game was saved, in seconds since the Unix epoch.
+    saved_at: u64,
+}
+
+fn summary_path(save: &Path) -> PathBuf {
+    save.with_extension("summary")
+}
+
+/// Seconds since the Unix epoch.
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+fn load_summary(save: &Path) -> Option<SaveSummary> {
+    let data = fs::read_to_string(summary_path(save)).ok()?;
+    serde_json::from_str(&data).ok()
+}
+
+fn default_player_name() -> String {
+    "player".into()
+}
+
+fn describe_age(strings: &Strings, saved_at: u64) -> String {
+    let minutes = now().saturating_sub(saved_at) / 60;
+    if minutes < 60 {
+        tr!(strings, "time.minutes_ago", minutes)
+    } else if minutes < 60 * 24 {
+        tr!(strings, "time.hours_ago", minutes / 60)
+    } else {
+        tr!(strings, "time.days_ago", minutes / (60 * 24))
+    }
+}
+
+/// Show all the save slots and let the player pick one.
+fn choose_slot(strings: &Strings, paths: &Paths, root: &mut Root) -> Option<usize> {
+    let options: Vec<String> = (0..SAVE_SLOTS)
+        .map(|slot| {
+            let path = paths.save(slot);
+            match load_summary(&path) {
+                Some(summary) => tr!(
+                    strings,
+                    "menu.slot",
+                    slot + 1,
+                    summary.level,
+                    summary.dungeon_level,
+                    describe_age(strings, summary.saved_at),
+                    summary.name
+                ),
+                None if path.exists() => tr!(strings, "menu.slot_unknown", slot + 1),
+                None => tr!(strings, "menu.slot_empty", slot + 1),
+            }
+        })
+        .collect();
+    menu(
+        strings.get("menu.choose_slot"),
+        &options,
+        SLOT_MENU_WIDTH,
+        root,
+    )
+}
+
+/// The most recently saved game across all the slots.
+fn latest_summary(paths: &Paths) -> Option<SaveSummary> {
+    (0..SAVE_SLOTS)
+        .filter_map(|slot| load_summary(&paths.save(slot)))
+        .max_by_key(|summary| summary.saved_at)
+}
+
+fn main_menu(tcod: &mut Tcod) {
+    let img =
+        tcod::image::Image::from_file("menu_background.png").expect("Background image not found");
+
+    while !tcod.root.window_closed() {
+        // the language may have changed since the last time around
+        let strings = Strings::load(&tcod.settings.language, &active_mods());
+
+        // show the background image, at twice the regular console resolution
+        tcod::image::blit_2x(&img, (0, 0), (-1, -1), &mut tcod.root, (0, 0));
+
+        tcod.root.set_default_foreground(LIGHT_YELLOW);
+        tcod.root.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT / 2 - 4,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            strings.get("title.name"),
+        );
+        tcod.root.print_ex(
+            SCREEN_WIDTH / 2,
+            SCREEN_HEIGHT - 2,
+            BackgroundFlag::None,
+            TextAlignment::Center,
+            strings.get("title.author"),
+        );
+
+        // what "Continue" would most likely pick up
+        if let Some(summary) = latest_summary(&tcod.paths) {
+            let text = tr!(
+                strings,
+                "menu.last_save",
+                summary.name,
+                summary.level,
+                summary.dungeon_level,
+                describe_age(&strings, summary.saved_at)
+            );
+            tcod.root.set_default_foreground(WHITE);
+            tcod.root.print_ex(
+                SCREEN_WIDTH / 2,
+                SCREEN_HEIGHT / 2 + 5,
+                BackgroundFlag::None,
+                TextAlignment::Center,
+                text,
+            );
+        }
+
+        // show options and wait for the player's choice
+        let choices = &[
+            strings.get("menu.new_game"),
+            strings.get("menu.continue"),
+            strings.get("menu.hall_of_heroes"),
+            strings.get("menu.options"),
+            strings.get("menu.delete_save"),
+            strings.get("menu.quit"),
+        ];
+        let choice = menu("", choices, 24, &mut tcod.root);
+
+        match choice {
+            Some(0) => {
+                // new game
+                let slot = match choose_slot(&strings, &tcod.paths, &mut tcod.root) {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                if tcod.paths.save(slot).exists() {
+                    let header = tr!(strings, "menu.overwrite", slot + 1);
+                    let options = &[strings.get("menu.yes"), strings.get("menu.no")];
+                    if menu(&header, options, 30, &mut tcod.root) != Some(0) {
+                        continue;
+                    }
+                }
+                let class = match choose_class(&strings, &mut tcod.root) {
+                    Some(class) => class,
+                    None => continue,
+                };
+                let difficulty = match choose_difficulty(&strings, &mut tcod.root) {
+                    Some(difficulty) => difficulty,
+                    None => continue,
+                };
+                let name = match enter_name(&strings, &mut tcod.root) {
+                    Some(name) => name,
+                    None => continue,
+                };
+                // write the run down as it's played, to watch it again later.
+                // The cheat keys aren't written down, so wizards aren't recorded
+                let seed = rand::random();
+                if !tcod.wizard {
+                    let header = ReplayHeader {
+                        seed,
+                        class,
+                        difficulty,
+                        name: name.clone(),
+                        mods: active_mods().iter().map(|dir| mod_name(dir)).collect(),
+                    };
+                    tcod.replay = Replay::record(&tcod.paths.replay(slot), &header);
+                }
+                let (mut game, mut objects) = new_game(tcod, class, difficulty, name, seed);
+                game.save_slot = slot;
+                play_game(tcod, &mut game, &mut objects);
+                tcod.replay = Replay::Off;
+            }
+            Some(1) => {
+                // load game
+                let slot = match choose_slot(&strings, &tcod.paths, &mut tcod.root) {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                match load_game(&tcod.paths.save(slot)) {
+                    Ok((mut game, mut objects)) => {
+                        game.strings = Strings::load(&tcod.settings.language, &active_mods());
+                        game.save_slot = slot;
+                        // playing it with the cheat keys taints it for good
+                        game.wizard |= tcod.wizard;
+                        warn_once(game.validate(&objects), &mut tcod.warned);
+                        initialise_fov(tcod, &game.map);
+                        play_game(tcod, &mut game, &mut objects);
+                    }
+                    Err(e) => {
+                        let text = match e {
+                            LoadError::NoFile => tr!(strings, "menu.no_saved_game"),
+                            LoadError::Corrupt(e) => {
+                                eprintln!("Could not load the saved game: {}", e);
+                                tr!(strings, "menu.corrupt_save")
+                            }
+                            LoadError::Incompatible(version) => {
+                                tr!(strings, "menu.incompatible_save", version)
+                            }
+                        };
+                        msgbox(&text, 30, &mut tcod.root);
+                        continue;
+                    }
+                }
+            }
+            Some(2) => {
+                // hall of heroes
+                show_high_scores(&strings, &tcod.paths, &mut tcod.root);
+            }
+            Some(3) => {
+                options_menu(tcod, &strings);
+            }
+            Some(4) => {
+                // delete a saved game
+                let slot = match choose_slot(&strings, &tcod.paths, &mut tcod.root) {
+                    Some(slot) => slot,
+                    None => continue,
+                };
+                let path = tcod.paths.save(slot);
+                if !path.exists() {
+                    msgbox(&tr!(strings, "menu.no_saved_game"), 30, &mut tcod.root);
+                    continue;
+                }
+                let header = tr!(strings, "menu.confirm_delete", slot + 1);
+                let options = &[strings.get("menu.yes"), strings.get("menu.no")];
+                if menu(&header, options, 30, &mut tcod.root) == Some(0) {
+                    delete_save(&path);
+                }
+            }
+            Some(5) => {
+                // quit
+                break;
+            }
+            _ => {}
+        }
+    }
+}
+
+fn main() {
+    if std::env::args().any(|arg| arg == "--default-config") {
+        let defaults = serde_json::to_string_pretty(&GameConfig::default()).unwrap();
+        println!("{}", defaults);
+        return;
+    }
+    if std::env::args().any(|arg| arg == "--default-spawns") {
+        let defaults = serde_json::to_string_pretty(&SpawnTables::default()).unwrap();
+        println!("{}", defaults);
+        return;
+    }
+    let config = match GameConfig::load(&active_mods()) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("There's a problem with the game configuration:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    let keymap = match KeyMap::load(&active_mods()) {
+        Ok(keymap) => keymap,
+        Err(e) => {
+            eprintln!("There's a problem with the key bindings:\n{}", e);
+            std::process::exit(1);
+        }
+    };
+    let paths = Paths::new();
+    let mut settings = Settings::load(&paths);
+    // an older version of the game may have shipped a different font
+    if !FONTS.contains(&settings.font.as_str()) {
+        settings.font = FONTS[0].into();
+    }
+    tcod::system::set_fps(settings.limit_fps.unwrap_or(config.limit_fps));
+
+    let root = Root::initializer()
+        .font(&settings.font, FontLayout::Tcod)
+        .font_type(FontType::Greyscale)
+        .size(SCREEN_WIDTH, SCREEN_HEIGHT)
+        .title("Rust/libtcod tutorial")
+        .fullscreen(settings.fullscreen)
+        .init();
+
+    let mut tcod = Tcod {
+        root,
+        con: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
+        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
+        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+        key: Default::default(),
+        mouse: Default::default(),
+        settings,
+        paths,
+        config,
+        keymap,
+        targeting: None,
+        draw_order: vec![],
+        map_layer: Offscreen::new(MAP_WIDTH, MAP_HEIGHT),
+        map_colors: vec![None; (MAP_WIDTH * MAP_HEIGHT) as usize],
+        wizard: std::env::args().any(|arg| arg == "--wizard"),
+        debug_overlay: DebugOverlay::Off,
+        frame_clock: FrameClock::new(),
+        warned: HashSet::new(),
+        replay: Replay::Off,
+    };
+
+    // `--replay <file>` watches a recorded run instead
+    let args: Vec<String> = env::args().collect();
+    if let Some(index) = args.iter().position(|arg| arg == "--replay") {
+        match args.get(index + 1) {
+            Some(path) => {
+                let fast = args.iter().any(|arg| arg == "--fast");
+                watch_replay(&mut tcod, Path::new(path), fast);
+            }
+            None => eprintln!("--replay needs the file to play back"),
+        }
+        return;
+    }
+
+    main_menu(&mut tcod);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn menu_fits_on_one_page() {
+        for &len in &[25, 26] {
+            assert_eq!(page_down(0, len), 0);
+            assert_eq!(menu_choice('a', 0, len), Some(0));
+            assert_eq!(menu_choice('y', 0, len), Some(24));
+        }
+        assert_eq!(menu_choice('z', 0, 25), None);
+        assert_eq!(menu_choice('z', 0, 26), Some(25));
+        assert_eq!(menu_choice('Z', 0, 26), Some(25));
+        assert_eq!(menu_choice('1', 0, 26), None);
+    }
+
+    #[test]
+    fn menu_pages_map_back_to_the_whole_list() {
+        // one option too many: the second page is the first one moved by one
+        assert_eq!(page_down(0, 27), 1);
+        assert_eq!(menu_choice('a', 1, 27), Some(1));
+        assert_eq!(menu_choice('z', 1, 27), Some(26));
+        assert_eq!(page_up(1), 0);
+
+        // two full pages
+        assert_eq!(page_down(0, 52), 26);
+        assert_eq!(page_down(26, 52), 26);
+        assert_eq!(menu_choice('a', 26, 52), Some(26));
+        assert_eq!(menu_choice('z', 26, 52), Some(51));
+        assert_eq!(page_up(26), 0);
+    }
+
+    #[test]
+    fn inventory_menu_returns_the_real_index() {
+        let mut potion = Object::new(0, 0, '!', "healing potion", WHITE, false);
+        potion.item = Some(Item::Heal);
+        let mut scroll = Object::new(0, 0, '#', "scroll of fireball", WHITE, false);
+        scroll.item = Some(Item::Fireball);
+        let mut sword = Object::new(0, 0, '/', "sword", WHITE, false);
+        sword.item = Some(Item::Sword);
+        sword.equipment = Some(Equipment {
+            slot: Slot::RightHand,
+            equipped: false,
+            max_hp_bonus: 0,
+            defense_bonus: 0,
+            power_bonus: 3,
+            damage: None,
+            cursed: false,
+            curse_known: false,
+        });
+        let inventory = vec![potion, scroll, sword];
+
+        // listed as sword, scroll, potion
+        let order = inventory_order(&inventory);
+        assert_eq!(order, vec![2, 1, 0]);
+        let pick = |letter| menu_choice(letter, 0, order.len()).map(|choice| order[choice]);
+        assert_eq!(pick('a'), Some(2));
+        assert_eq!(pick('c'), Some(0));
+        assert_eq!(pick('d'), None);
+    }
+
+    fn high_score(name: &str, xp: i32, dungeon_level: u32, gold: i32, turns: u32) -> HighScore {
+        HighScore {
+            name: name.into(),
+            level: 1,
+            dungeon_level,
+            xp,
+            turns,
+            gold,
+            difficulty: Difficulty::Normal,
+            killed_by: None,
+        }
+    }
+
+    #[test]
+    fn score_adds_xp_depth_and_gold() {
+        assert_eq!(score(&high_score("a", 0, 0, 0, 1)), 0);
+        assert_eq!(score(&high_score("a", 120, 0, 0, 1)), 120);
+        assert_eq!(score(&high_score("a", 0, 3, 0, 1)), 3 * DEPTH_BONUS);
+        assert_eq!(score(&high_score("a", 0, 0, 75, 1)), 75);
+        assert_eq!(
+            score(&high_score("a", 120, 3, 75, 1)),
+            120 + 3 * DEPTH_BONUS + 75
+        );
+    }
+
+    #[test]
+    fn quicker_run_wins_a_tie() {
+        let mut scores = vec![
+            high_score("slow", 100, 2, 0, 900),
+            high_score("best", 500, 2, 0, 2000),
+            high_score("quick", 100, 2, 0, 300),
+        ];
+        rank_high_scores(&mut scores);
+        let names: Vec<&str> = scores.iter().map(|entry| entry.name.as_str()).collect();
+        assert_eq!(names, vec!["best", "quick", "slow"]);
+    }
+
+    #[test]
+    fn hall_of_heroes_keeps_the_best_runs() {
+        let mut scores: Vec<HighScore> = (0..HALL_SIZE as i32 + 5)
+            .map(|xp| high_score("hero", xp, 1, 0, 100))
+            .collect();
+        rank_high_scores(&mut scores);
+        assert_eq!(scores.len(), HALL_SIZE);
+        assert_eq!(scores[0].xp, HALL_SIZE as i32 + 4);
+        assert_eq!(scores[HALL_SIZE - 1].xp, 5);
+    }
+
+    #[test]
+    fn wrap_text_breaks_between_words() {
+        assert_eq!(
+            wrap_text("the orc hits you", 8),
+            vec!["the orc", "hits you"]
+        );
+    }
+
+    #[test]
+    fn wrap_text_message_exactly_as_wide_as_the_log() {
+        let message: String = "x".repeat(MSG_WIDTH as usize);
+        assert_eq!(
+            wrap_text(&message, MSG_WIDTH as usize),
+            vec![message.clone()]
+        );
+
+        let message = format!("{} y", "x".repeat(MSG_WIDTH as usize - 2));
+        assert_eq!(
+            wrap_text(&message, MSG_WIDTH as usize),
+            vec![message.clone()]
+        );
+    }
+
+    #[test]
+    fn wrap_text_breaks_up_long_words() {
+        assert_eq!(
+            wrap_text("a abcdefghij b", 4),
+            vec!["a", "abcd", "efgh", "ij b"]
+        );
+        // characters, not bytes
+        assert_eq!(wrap_text("ééééé", 2), vec!["éé", "éé", "é"]);
+    }
+
+    #[test]
+    fn wrap_text_keeps_empty_messages() {
+        assert_eq!(wrap_text("", MSG_WIDTH as usize), vec![""]);
+        assert_eq!(wrap_text("one\n\ntwo", 10), vec!["one", "", "two"]);
+    }
+
+    /// Every literal key in `source` that follows one of the `needles`.
+    fn literal_keys<'a>(source: &'a str, needles: &[&str]) -> Vec<&'a str> {
+        let mut keys = vec![];
+        for needle in needles {
+            for (start, _) in source.match_indices(needle) {
+                let rest = &source[start + needle.len()..];
+                // `tr!` takes the strings first, then the key
+                let rest = if needle.ends_with('(') {
+                    match rest.find(',') {
+                        Some(comma) => rest[comma + 1..].trim_start(),
+                        None => continue,
+                    }
+                } else {
+                    rest
+                };
+                if let Some(key) = rest.strip_prefix('"') {
+                    if let Some(end) = key.find('"') {
+                        keys.push(&key[..end]);
+                    }
+                }
+            }
+        }
+        keys
+    }
+
+    #[test]
+    fn every_literal_string_key_exists() {
+        // built with `concat!` so the needles don't find themselves
+        let needles = [concat!("tr", "!("), concat!("strings.get", "(")];
+        let source = include_str!("part-14-extras.rs");
+        let keys = literal_keys(source, &needles);
+        assert!(keys.len() > 100, "only found {} keys", keys.len());
+        let missing: Vec<&str> = keys
+            .into_iter()
+            .filter(|key| DEFAULT_STRINGS.iter().all(|&(id, _)| id != *key))
+            .collect();
+        assert!(missing.is_empty(), "missing strings: {:?}", missing);
+    }
+
+    /// A game on an open, empty map, without any of the files or the
+    /// window a real one needs.
+    fn test_game() -> Game {
+        Game {
+            map: Map::new(Tile::empty()),
+            messages: Messages::new(),
+            inventory: vec![],
+            dungeon_level: 1,
+            strings: Strings::default(),
+            pending_spawns: vec![],
+            mods: vec![],
+            boss_defeated: false,
+            next_object_id: 1,
+            save_slot: 0,
+            last_damage_source: String::new(),
+            turns: 0,
+            pending_run: None,
+            run_snapshot: Default::default(),
+            fires: vec![],
+            last_hurt_turn: 0,
+            gold: 0,
+            hunger: 0,
+            spells: vec![],
+            class: Class::default(),
+            player_name: default_player_name(),
+            difficulty: Difficulty::default(),
+            actors: vec![],
+            hits: vec![],
+            player_hurt: false,
+            next_ambient: AMBIENT_MIN_TURNS,
+            wizard: false,
+            god_mode: false,
+            pending_path: vec![],
+            rng: seeded_rng(0),
+            fled: vec![],
+        }
+    }
+
+    fn test_fighter(hp: i32, on_death: DeathCallback) -> Fighter {
+        Fighter {
+            hp,
+            base_max_hp: hp,
+            base_defense: 0,
+            base_power: 2,
+            damage: Dice::default(),
+            crit_chance: 0.0,
+            speed: NORMAL_SPEED,
+            mana: 0,
+            max_mana: 0,
+            xp: 10,
+            on_death,
+        }
+    }
+
+    fn test_player(x: i32, y: i32) -> Object {
+        let mut player = Object::new(x, y, '@', "player", WHITE, true);
+        player.alive = true;
+        player.is_player = true;
+        player.faction = Faction::Player;
+        player.fighter = Some(test_fighter(30, DeathCallback::Player));
+        player
+    }
+
+    fn test_monster(name: &str, x: i32, y: i32, hp: i32, ai: Ai) -> Object {
+        let mut monster = Object::new(x, y, 'm', name, WHITE, true);
+        monster.alive = true;
+        monster.fighter = Some(test_fighter(hp, DeathCallback::Monster));
+        monster.ai = Some(ai);
+        monster
+    }
+
+    /// The actors are exactly the objects with an AI, each listed once.
+    fn assert_actors_match(game: &Game, objects: &[Object]) {
+        let mut actors = game.actors.clone();
+        actors.sort();
+        let before = actors.len();
+        actors.dedup();
+        assert_eq!(actors.len(), before, "an actor is listed twice");
+        let mut expected: Vec<ObjectId> = objects
+            .iter()
+            .filter(|object| object.ai.is_some())
+            .map(|object| object.id)
+            .collect();
+        expected.sort();
+        assert_eq!(actors, expected);
+    }
+
+    #[test]
+    fn actors_follow_spawns_splits_and_deaths() {
+        let mut game = test_game();
+        let mut objects = vec![
+            test_player(10, 10),
+            test_monster("orc", 12, 10, 10, Ai::Basic),
+            test_monster("slime", 20, 20, 1000, Ai::Slime),
+        ];
+        game.find_actors(&mut objects);
+        assert_actors_match(&game, &objects);
+
+        // a monster showing up in the middle of the game
+        game.pending_spawns
+            .push(test_monster("troll", 30, 30, 10, Ai::Basic));
+        add_pending_spawns(&mut game, &mut objects);
+        game.assign_ids(&mut objects);
+        assert_eq!(objects.len(), 4);
+        assert_actors_match(&game, &objects);
+
+        // splitting is random, so keep hitting it until it does
+        for _ in 0..100 {
+            if !game.pending_spawns.is_empty() {
+                break;
+            }
+            split_slime(&mut objects[2], &mut game);
+        }
+        assert_eq!(game.pending_spawns.len(), 1, "the slime never split");
+        add_pending_spawns(&mut game, &mut objects);
+        game.assign_ids(&mut objects);
+        assert_eq!(objects.len(), 5);
+        assert_actors_match(&game, &objects);
+
+        // and the orc dying
+        objects[1].take_damage(100, &mut game);
+        assert!(!objects[1].alive);
+        assert!(!game.actors.contains(&objects[1].id));
+        add_pending_spawns(&mut game, &mut objects);
+        game.assign_ids(&mut objects);
+        assert_actors_match(&game, &objects);
+    }
+
+    /// A piece of equipment without any bonuses, not worn yet.
+    fn test_gear(name: &str, item: Item, slot: Slot) -> Object {
+        let mut gear = Object::new(0, 0, '[', name, WHITE, false);
+        gear.item = Some(item);
+        gear.equipment = Some(Equipment {
+            slot,
+            equipped: false,
+            max_hp_bonus: 0,
+            defense_bonus: 0,
+            power_bonus: 0,
+            damage: None,
+            cursed: false,
+            curse_known: false,
+        });
+        gear
+    }
+
+    #[test]
+    fn healing_counts_the_max_hp_bonus() {
+        let mut game = test_game();
+        let mut player = test_player(10, 10);
+        let mut shield = test_gear("shield", Item::Shield, Slot::LeftHand);
+        shield.equipment.as_mut().unwrap().max_hp_bonus = 20;
+        game.inventory.push(shield);
+
+        // not worn, so it doesn't count
+        player.fighter.as_mut().unwrap().hp = 10;
+        player.heal(100, &game);
+        assert_eq!(player.fighter.unwrap().hp, 30);
+
+        game.inventory[0].equipment.as_mut().unwrap().equipped = true;
+        assert_eq!(player.max_hp(&game), 50);
+        player.heal(15, &game);
+        assert_eq!(player.fighter.unwrap().hp, 45);
+        player.heal(100, &game);
+        assert_eq!(player.fighter.unwrap().hp, 50);
+    }
+
+    #[test]
+    fn lifting_a_curse_restores_the_bonuses() {
+        let mut messages = Messages::new();
+        let strings = Strings::default();
+        let mut sword = test_gear("sword", Item::Sword, Slot::RightHand);
+        {
+            let equipment = sword.equipment.as_mut().unwrap();
+            equipment.power_bonus = 3;
+            equipment.defense_bonus = -1;
+            equipment.cursed = true;
+        }
+        // nobody knows yet
+        assert_eq!(sword.equipment.unwrap().power(), 3);
+
+        sword.equip(&strings, &mut messages);
+        let equipment = sword.equipment.unwrap();
+        assert!(equipment.curse_known);
+        assert_eq!(equipment.power(), -3);
+        assert_eq!(equipment.defense(), 1);
+        assert!(!sword.dequip(&strings, &mut messages));
+
+        let mut inventory = vec![sword];
+        assert!(lift_curses(&mut inventory));
+        let equipment = inventory[0].equipment.unwrap();
+        assert_eq!((equipment.power(), equipment.defense()), (3, -1));
+        assert_eq!((equipment.power_bonus, equipment.defense_bonus), (3, -1));
+        assert!(!lift_curses(&mut inventory));
+    }
+
+    #[test]
+    fn enchanting_follows_the_slot() {
+        let mut messages = Messages::new();
+        let strings = Strings::default();
+        let mut dagger = starting_dagger();
+        let mut shield = test_gear("shield", Item::Shield, Slot::LeftHand);
+        let mut armor = test_gear("plate armor", Item::PlateArmor, Slot::Body);
+        let mut ring = test_gear("ring of vitality", Item::RingOfVitality, Slot::RightRing);
+        for item in [&mut dagger, &mut shield, &mut armor, &mut ring] {
+            enchant_item(item, &strings, &mut messages);
+        }
+        let bonuses = |item: &Object| {
+            let equipment = item.equipment.unwrap();
+            (equipment.power_bonus, equipment.defense_bonus)
+        };
+        // the dagger is a weapon even in the left hand, the shield isn't
+        assert_eq!(bonuses(&dagger), (2, 0));
+        assert_eq!(bonuses(&shield), (0, 1));
+        assert_eq!(bonuses(&armor), (0, 1));
+        assert_eq!(bonuses(&ring), (1, 0));
+        assert_eq!(dagger.name, "dagger +1");
+    }
+
+    #[test]
+    fn enchanting_a_cursed_item_lifts_the_curse() {
+        let mut messages = Messages::new();
+        let strings = Strings::default();
+        let mut sword = test_gear("sword", Item::Sword, Slot::RightHand);
+        {
+            let equipment = sword.equipment.as_mut().unwrap();
+            equipment.power_bonus = 3;
+            equipment.damage = Some(Dice::new(1, 8, 0));
+            equipment.cursed = true;
+        }
+        sword.equip(&strings, &mut messages);
+        assert_eq!(sword.equipment.unwrap().power(), -3);
+
+        enchant_item(&mut sword, &strings, &mut messages);
+        let equipment = sword.equipment.unwrap();
+        assert!(!equipment.cursed && !equipment.curse_known);
+        assert_eq!(equipment.power(), 3);
+        assert_eq!((sword.enchantment, sword.name.as_str()), (0, "sword"));
+
+        // the next scroll enchants it as usual
+        enchant_item(&mut sword, &strings, &mut messages);
+        assert_eq!(sword.equipment.unwrap().power(), 4);
+    }
+
+    /// The player at (10, 10) and hostile monsters at the given positions.
+    fn targets(positions: &[(i32, i32)]) -> Vec<Object> {
+        let mut objects = vec![test_player(10, 10)];
+        for (id, &(x, y)) in positions.iter().enumerate() {
+            let mut monster = test_monster("orc", x, y, 10, Ai::Basic);
+            monster.id = id as ObjectId + 1;
+            objects.push(monster);
+        }
+        objects
+    }
+
+    fn see_everything(_x: i32, _y: i32) -> bool {
+        true
+    }
+
+    #[test]
+    fn nothing_to_target_but_the_player() {
+        let objects = targets(&[]);
+        assert_eq!(closest_monster(see_everything, &objects, 100), None);
+    }
+
+    #[test]
+    fn closest_monster_picks_the_nearest_one() {
+        let objects = targets(&[(15, 10), (12, 10), (10, 14)]);
+        assert_eq!(closest_monster(see_everything, &objects, 10), Some(2));
+    }
+
+    #[test]
+    fn closest_monster_breaks_ties_in_list_order() {
+        let objects = targets(&[(13, 10), (10, 7), (7, 10)]);
+        assert_eq!(closest_monster(see_everything, &objects, 10), Some(1));
+    }
+
+    #[test]
+    fn closest_monster_skips_the_unreachable() {
+        let mut objects = targets(&[(20, 10), (10, 13), (12, 10), (11, 11)]);
+        // the closest one is a friend, the next one is around a corner
+        objects[4].faction = Faction::Player;
+        let in_fov = |x: i32, _y: i32| x != 12;
+        assert_eq!(closest_monster(in_fov, &objects, 5), Some(2));
+        // and out of range
+        assert_eq!(closest_monster(in_fov, &objects, 2), None);
+        assert_eq!(closest_monster(in_fov, &objects, 3), Some(2));
+    }
+
+    /// A fresh, empty data directory just for this test.
+    fn test_paths(name: &str) -> Paths {
+        let data_dir = env::temp_dir().join(format!("tombs-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&data_dir);
+        fs::create_dir_all(&data_dir).unwrap();
+        Paths { data_dir }
+    }
+
+    #[test]
+    fn leaving_the_game_saves_it() {
+        let paths = test_paths("save");
+        let settings = Settings::default();
+        let mut game = test_game();
+        game.dungeon_level = 3;
+        let objects = vec![test_player(10, 10)];
+        save_on_exit(&paths, &settings, &game, &objects).unwrap();
+        let (loaded, loaded_objects) = load_game(&paths.save(0)).unwrap();
+        assert_eq!(loaded.dungeon_level, 3);
+        assert_eq!(loaded_objects[PLAYER].pos(), (10, 10));
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn a_dead_player_is_only_saved_without_permadeath() {
+        let paths = test_paths("dead");
+        let mut settings = Settings::default();
+        let game = test_game();
+        let mut objects = vec![test_player(10, 10)];
+        objects[PLAYER].alive = false;
+
+        settings.permadeath = true;
+        save_on_exit(&paths, &settings, &game, &objects).unwrap();
+        assert!(!paths.save(0).exists());
+
+        settings.permadeath = false;
+        save_on_exit(&paths, &settings, &game, &objects).unwrap();
+        assert!(paths.save(0).exists());
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn failing_to_save_is_reported() {
+        let paths = test_paths("unwritable");
+        let missing = Paths {
+            data_dir: paths.data_dir.join("missing"),
+        };
+        let objects = vec![test_player(10, 10)];
+        let result = save_on_exit(&missing, &Settings::default(), &test_game(), &objects);
+        assert!(result.is_err());
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn validate_reports_without_fixing() {
+        let mut game = test_game();
+        game.map[(5, 5)] = Tile::wall();
+        let mut objects = vec![
+            test_player(10, 10),
+            test_monster("orc", 5, 5, 10, Ai::Basic),
+            test_monster("troll", MAP_WIDTH, -1, 10, Ai::Basic),
+        ];
+        game.find_actors(&mut objects);
+        let problems = game.validate(&objects);
+        let ids: Vec<ObjectId> = problems.iter().map(|&(id, _)| id).collect();
+        assert_eq!(ids, vec![objects[1].id, objects[2].id]);
+        assert_eq!(objects[2].pos(), (MAP_WIDTH, -1));
+
+        objects[PLAYER].fighter = None;
+        assert_eq!(game.validate(&objects)[0].0, 0);
+    }
+
+    #[test]
+    fn warn_once_per_object() {
+        let mut warned = HashSet::new();
+        warn_once(vec![(3, "one".into()), (3, "two".into())], &mut warned);
+        assert_eq!(warned.len(), 1);
+        warn_once(vec![(3, "three".into()), (4, "four".into())], &mut warned);
+        assert_eq!(warned.len(), 2);
+    }
+
+    /// A walled room with the player in the middle and monsters of most
+    /// kinds around them. The player can't get hurt, so the fighting goes on
+    /// for as long as we like.
+    fn test_arena(seed: usize) -> (Game, Vec<Object>) {
+        let mut game = test_game();
+        game.rng = seeded_rng(seed);
+        game.god_mode = true;
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                if !(10..30).contains(&x) || !(10..25).contains(&y) {
+                    game.map[(x, y)] = Tile::wall();
+                }
+            }
+        }
+        // something for the thief to steal
+        game.inventory
+            .push(test_gear("sword", Item::Sword, Slot::RightHand));
+
+        let mut objects = vec![test_player(20, 17)];
+        let monsters = vec![
+            ("orc", 12, 12, Ai::Basic, Faction::Dungeon),
+            ("thief", 28, 12, Ai::Thief, Faction::Dungeon),
+            (
+                "apprentice",
+                12,
+                23,
+                Ai::Caster { cooldown: 0 },
+                Faction::Dungeon,
+            ),
+            ("wolf", 27, 22, pack_wolf(true), Faction::Dungeon),
+            ("wolf", 28, 23, pack_wolf(false), Faction::Dungeon),
+            (
+                "orc king",
+                20,
+                11,
+                Ai::Boss {
+                    phase: 1,
+                    summon_cooldown: 0,
+                },
+                Faction::Dungeon,
+            ),
+            ("slime", 15, 20, Ai::Slime, Faction::Wildlife),
+            ("rat", 25, 15, Ai::Basic, Faction::Wildlife),
+            ("rat", 14, 16, Ai::Basic, Faction::Wildlife),
+            ("bat", 22, 21, Ai::Wander, Faction::Wildlife),
+            ("bat", 11, 18, Ai::Wander, Faction::Wildlife),
+            (
+                "orc",
+                18,
+                23,
+                Ai::Confused {
+                    previous_ai: Box::new(Ai::Basic),
+                    num_turns: 50,
+                },
+                Faction::Dungeon,
+            ),
+        ];
+        for (name, x, y, ai, faction) in monsters {
+            let mut monster = test_monster(name, x, y, 20, ai);
+            monster.faction = faction;
+            objects.push(monster);
+        }
+        game.find_actors(&mut objects);
+        (game, objects)
+    }
+
+    /// Play `turns` turns the way the game loop does, with the player
+    /// standing still, and check the invariants after every one of them.
+    fn run_turns(game: &mut Game, objects: &mut Vec<Object>, turns: u32) {
+        let config = GameConfig::default();
+        let view = View {
+            in_fov: Box::new(in_map),
+            config: &config,
+        };
+        for _ in 0..turns {
+            world_tick(&view, game, objects);
+            game.on_turn_passed(&config, objects);
+            add_pending_spawns(game, objects);
+            game.assign_ids(objects);
+            let problems = game.validate(objects);
+            assert!(problems.is_empty(), "turn {}: {:?}", game.turns, problems);
+        }
+    }
+
+    #[test]
+    fn monsters_keep_the_world_consistent() {
+        for seed in 0..10 {
+            let (mut game, mut objects) = test_arena(seed);
+            run_turns(&mut game, &mut objects, 300);
+            assert_actors_match(&game, &objects);
+        }
+    }
+
+    #[test]
+    fn the_same_seed_plays_the_same_game() {
+        let play = |seed| {
+            let (mut game, mut objects) = test_arena(seed);
+            run_turns(&mut game, &mut objects, 200);
+            objects
+                .iter()
+                .map(|object| {
+                    (
+                        object.name.clone(),
+                        object.pos(),
+                        object.fighter.map(|f| f.hp),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(play(7), play(7));
+    }
+
+    /// A map that's all wall except for a corridor along y = 10 from x = 10
+    /// to 19.
+    fn corridor_game() -> Game {
+        let mut game = test_game();
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                if y != 10 || !(10..20).contains(&x) {
+                    game.map[(x, y)] = Tile::wall();
+                }
+            }
+        }
+        game
+    }
+
+    #[test]
+    fn stumbling_around_never_walks_into_a_wall() {
+        let mut game = corridor_game();
+        let mut objects = vec![
+            test_player(0, 0),
+            test_monster("orc", 15, 10, 10, Ai::Basic),
+        ];
+        for _ in 0..100 {
+            assert!(step_at_random(1, &game.map, &mut objects, &mut game.rng));
+            assert_eq!(objects[1].y, 10);
+            assert!((10..20).contains(&objects[1].x));
+        }
+    }
+
+    #[test]
+    fn stumbling_around_with_nowhere_to_go() {
+        let mut game = corridor_game();
+        let mut objects = vec![
+            test_player(0, 0),
+            test_monster("orc", 10, 10, 10, Ai::Basic),
+            test_monster("orc", 11, 10, 10, Ai::Basic),
+        ];
+        assert!(!step_at_random(1, &game.map, &mut objects, &mut game.rng));
+        assert_eq!(objects[1].pos(), (10, 10));
+    }
+
+    /// Generate a level the way a new game or the stairs would.
+    fn generate_level(seed: usize, level: u32) -> (Map, Vec<Object>) {
+        let config = GameConfig::default();
+        let mut objects = vec![test_player(0, 0)];
+        let mut rng = seeded_rng(seed);
+        let (map, _) = make_map(&mut objects, level, Difficulty::Hard, &config, &mut rng);
+        (map, objects)
+    }
+
+    #[test]
+    fn nothing_starts_on_the_players_tile() {
+        for seed in 0..300 {
+            let level = 1 + seed as u32 % FINAL_LEVEL;
+            let (_, objects) = generate_level(seed, level);
+            let start = objects[PLAYER].pos();
+            for object in &objects[1..] {
+                assert_ne!(
+                    object.pos(),
+                    start,
+                    "seed {}: the {} is in the way",
+                    seed,
+                    object.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn the_same_seed_makes_the_same_level() {
+        let describe = |seed| {
+            let (map, objects) = generate_level(seed, 4);
+            let walls: Vec<bool> = map.tiles().map(|tile| tile.blocked).collect();
+            let objects: Vec<_> = objects
+                .iter()
+                .map(|object| (object.name.clone(), object.pos()))
+                .collect();
+            (walls, objects)
+        };
+        assert_eq!(describe(3), describe(3));
+        assert_ne!(describe(3), describe(4));
+    }
+
+    #[test]
+    fn levels_stay_inside_their_walls() {
+        for seed in 0..2000 {
+            let level = 1 + seed as u32 % FINAL_LEVEL;
+            // `carve` stops any test build that goes outside the map
+            let (map, objects) = generate_level(seed, level);
+            for x in 0..MAP_WIDTH {
+                for y in 0..MAP_HEIGHT {
+                    let edge = x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1;
+                    if edge {
+                        assert!(
+                            map[(x, y)].blocked,
+                            "seed {}: ({}, {}) was carved",
+                            seed,
+                            x,
+                            y
+                        );
+                    }
+                }
+            }
+            assert!(objects.iter().all(|object| in_map(object.x, object.y)));
+        }
+    }
+
+    #[test]
+    fn a_recording_plays_back_the_same() {
+        let paths = test_paths("replay");
+        let header = ReplayHeader {
+            seed: 42,
+            class: Class::Rogue,
+            difficulty: Difficulty::Hard,
+            name: "Anna".into(),
+            mods: vec![],
+        };
+        let decisions = vec![
+            Decision::Act(Action::Move(1, 0)),
+            Decision::Act(Action::ThrowItem(2)),
+            Decision::Aim(Some((10, 12))),
+            Decision::Act(Action::Run(0, -1)),
+            Decision::Stop,
+            Decision::Choose(None),
+            Decision::Act(Action::CastSpell(Spell::MagicMissile)),
+        ];
+        let mut replay = Replay::record(&paths.replay(0), &header);
+        for decision in &decisions {
+            replay.write(decision);
+        }
+        drop(replay);
+
+        let (loaded, mut replay) = Replay::load(&paths.replay(0)).unwrap();
+        assert_eq!(loaded, header);
+        assert!(replay.is_playing());
+        let played: Vec<Decision> = std::iter::from_fn(|| replay.next()).collect();
+        assert_eq!(played, decisions);
+    }
+
+    #[test]
+    fn a_stop_is_only_taken_when_it_comes_next() {
+        let mut replay = Replay::Playing {
+            decisions: vec![Decision::Act(Action::Wait), Decision::Stop].into(),
+            step: 0,
+        };
+        assert!(!replay.take_stop());
+        assert_eq!(replay.next(), Some(Decision::Act(Action::Wait)));
+        assert!(replay.take_stop());
+        assert_eq!(replay.next(), None);
+    }
+
+    #[test]
+    fn actions_the_player_could_not_take_are_caught() {
+        let mut game = test_game();
+        game.inventory
+            .push(test_gear("sword", Item::Sword, Slot::RightHand));
+        let mut objects = vec![test_player(10, 10)];
+        assert_eq!(check_action(Action::Move(1, 0), &game, &objects), Ok(()));
+        assert_eq!(check_action(Action::UseItem(0), &game, &objects), Ok(()));
+        assert!(check_action(Action::DropItem(1), &game, &objects).is_err());
+        assert!(check_action(Action::PickUp(7), &game, &objects).is_err());
+        let spell = Action::CastSpell(Spell::MagicMissile);
+        assert!(check_action(spell, &game, &objects).is_err());
+        game.spells.push(Spell::MagicMissile);
+        assert_eq!(check_action(spell, &game, &objects), Ok(()));
+        objects[PLAYER].alive = false;
+        assert!(check_action(Action::Wait, &game, &objects).is_err());
+    }
+
+    /// The example mod in `fixtures/mods`: it reskins the orc and adds a
+    /// potion.
+    fn orc_reskin() -> Vec<PathBuf> {
+        vec![Path::new(env!("CARGO_MANIFEST_DIR")).join("fixtures/mods/orc-reskin")]
+    }
+
+    #[test]
+    fn a_mod_reskins_the_orc_and_adds_a_potion() {
+        let mods = orc_reskin();
+        let config = GameConfig::load(&mods).unwrap();
+        let orc = create_monster("orc", 0, 0, &config.spawns);
+        assert_eq!(orc.char, 'O');
+        let strings = Strings::load("en", &mods);
+        let key = "describe.flask of grog";
+        assert_ne!(strings.get(key), key);
+
+        // it turns up in the dungeon, and works like any healing potion
+        let grog = (0..500)
+            .find_map(|seed| {
+                let mut objects = vec![test_player(0, 0)];
+                let mut rng = seeded_rng(seed);
+                let level = 1 + seed as u32 % FINAL_LEVEL;
+                make_map(&mut objects, level, Difficulty::Hard, &config, &mut rng);
+                objects
+                    .into_iter()
+                    .find(|object| object.name == "flask of grog")
+            })
+            .expect("no grog in 500 levels");
+        assert_eq!(config.spawns.value(&grog), 30);
+        let mut game = test_game();
+        let mut objects = vec![test_player(1, 1)];
+        objects[PLAYER].fighter.as_mut().unwrap().hp = 1;
+        let effect = item_effect(grog.item.unwrap(), &config).unwrap();
+        affect(&effect, PLAYER, &mut game, &mut objects);
+        assert!(objects[PLAYER].fighter.unwrap().hp > 1);
+    }
+
+    #[test]
+    fn mods_change_every_data_file() {
+        let paths = test_paths("mods");
+        let dir = paths.data_dir.join("no-trolls");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("spawns.json"), r#"{"remove": ["troll"]}"#).unwrap();
+        fs::write(dir.join("config.json"), r#"{"heal_amount": 5}"#).unwrap();
+        fs::write(dir.join("keys.json"), r#"{"Wait": ["z"]}"#).unwrap();
+        let mut mods = orc_reskin();
+        mods.push(dir.clone());
+
+        let config = GameConfig::load(&mods).unwrap();
+        assert_eq!(config.heal_amount, 5);
+        let names: Vec<&str> = config
+            .spawns
+            .monsters
+            .iter()
+            .map(|m| m.name.as_str())
+            .collect();
+        assert!(!names.contains(&"troll"));
+        // the orc is replaced where it was, not added again
+        assert_eq!(names.iter().filter(|&&name| name == "orc").count(), 1);
+        assert_eq!(config.spawns.monster("orc").glyph, 'O');
+        let keymap = KeyMap::load(&mods).unwrap();
+        let z = Binding::Text("z".into());
+        assert!(keymap
+            .bindings
+            .iter()
+            .any(|(command, keys)| *command == Command::Wait && keys == &vec![z.clone()]));
+
+        // a mod that breaks the game is named in the error
+        fs::write(dir.join("spawns.json"), r#"{"remove": ["orc"]}"#).unwrap();
+        let error = GameConfig::load(&mods).err().unwrap();
+        assert!(error.contains("mod no-trolls"), "{}", error);
+        assert!(error.contains("spawns.json"), "{}", error);
+        fs::write(dir.join("config.json"), r#"{"heal_amount": "lots"}"#).unwrap();
+        let error = GameConfig::load(&mods).err().unwrap();
+        assert!(error.contains("mod no-trolls"), "{}", error);
+        assert!(error.contains("config.json"), "{}", error);
+    }
+
+    #[test]
+    fn the_morgue_file_names_the_mods() {
+        let mut game = test_game();
+        let objects = vec![test_player(0, 0)];
+        assert!(!morgue_text(&game, &objects).contains("orc-reskin"));
+        game.mods = vec!["orc-reskin".into()];
+        assert!(morgue_text(&game, &objects).contains("orc-reskin"));
+    }
+
+    /// A thief in the corridor that's already stolen a sword.
+    fn thief_with_loot(x: i32, y: i32) -> Object {
+        let mut thief = test_monster("thief", x, y, 10, Ai::Fleeing);
+        thief.carried = Some(Box::new(test_gear("sword", Item::Sword, Slot::RightHand)));
+        thief
+    }
+
+    #[test]
+    fn a_thief_runs_down_the_stairs_with_its_loot() {
+        let mut game = corridor_game();
+        let mut stairs = Object::new(19, 10, '<', "stairs", WHITE, false);
+        stairs.render_order = RenderOrder::Stairs;
+        let mut objects = vec![test_player(10, 10), stairs, thief_with_loot(13, 10)];
+        game.find_actors(&mut objects);
+        run_turns(&mut game, &mut objects, 20);
+        assert!(!objects.iter().any(|object| object.name == "thief"));
+        assert_eq!(game.fled.len(), 1);
+        assert!(game.fled[0].carried.is_some());
+    }
+
+    #[test]
+    fn without_stairs_a_thief_runs_for_the_edge() {
+        let mut game = test_game();
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                if y != 10 || !(2..20).contains(&x) {
+                    game.map[(x, y)] = Tile::wall();
+                }
+            }
+        }
+        let mut objects = vec![test_player(19, 10), thief_with_loot(15, 10)];
+        game.find_actors(&mut objects);
+        run_turns(&mut game, &mut objects, 20);
+        // the left end of the corridor is the closest to the edge
+        let thief = objects
+            .iter()
+            .find(|object| object.name == "thief")
+            .unwrap();
+        assert!(thief.carried.is_some());
+        assert_eq!(thief.pos(), (2, 10));
+    }
+
+    #[test]
+    fn stolen_things_come_along_to_the_next_level() {
+        let mut game = corridor_game();
+        game.fled.push(thief_with_loot(0, 0));
+        let mut objects = vec![test_player(10, 10), thief_with_loot(15, 10)];
+        let thieves = thieves_on_the_run(&mut game, &mut objects);
+        assert_eq!(thieves.len(), 2);
+        assert_eq!(objects.len(), 1);
+
+        // the new level
+        hide_thieves(thieves, &mut game, &mut objects);
+        let thieves: Vec<&Object> = objects
+            .iter()
+            .filter(|object| object.name == "thief")
+            .collect();
+        assert_eq!(thieves.len(), 2);
+        for thief in thieves {
+            assert!(thief.carried.is_some());
+            assert!(!game.map[(thief.x, thief.y)].blocked);
+        }
+    }
+
+    #[test]
+    fn a_thief_with_nowhere_to_hide_drops_its_loot() {
+        let mut game = test_game();
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                if (x, y) != (10, 10) {
+                    game.map[(x, y)] = Tile::wall();
+                }
+            }
+        }
+        let mut objects = vec![test_player(10, 10)];
+        hide_thieves(vec![thief_with_loot(0, 0)], &mut game, &mut objects);
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[1].name, "sword");
+        assert_eq!(objects[1].pos(), (10, 10));
+    }
+
+    /// The AI of a wolf in pack 1.
+    fn pack_wolf(leader: bool) -> Ai {
+        Ai::Pack {
+            pack_id: 1,
+            leader,
+            target: None,
+        }
+    }
+
+    #[test]
+    fn the_pack_hunts_what_its_leader_hunts() {
+        let config = GameConfig::default();
+        let view = View {
+            in_fov: Box::new(in_map),
+            config: &config,
+        };
+        let mut game = test_game();
+        let mut rat = test_monster("rat", 10, 10, 20, Ai::Basic);
+        rat.faction = Faction::Wildlife;
+        let mut objects = vec![
+            test_player(20, 20),
+            rat,
+            test_monster("wolf", 12, 12, 20, pack_wolf(true)),
+            test_monster("wolf", 30, 30, 20, pack_wolf(false)),
+        ];
+        game.find_actors(&mut objects);
+        ai_take_turn(2, &view, &mut game, &mut objects);
+        ai_take_turn(3, &view, &mut game, &mut objects);
+
+        // the player is closer, but the second wolf goes after the rat too
+        let rat_id = objects[1].id;
+        assert_eq!(
+            objects[2].ai,
+            Some(Ai::Pack {
+                pack_id: 1,
+                leader: true,
+                target: Some(rat_id)
+            })
+        );
+        assert_eq!(objects[3].pos(), (29, 29));
+    }
+
+    #[test]
+    fn a_pack_without_a_leader_gets_a_new_one() {
+        let config = GameConfig::default();
+        let view = View {
+            in_fov: Box::new(in_map),
+            config: &config,
+        };
+        let mut game = test_game();
+        let mut objects = vec![
+            test_player(20, 20),
+            test_monster("wolf", 12, 12, 20, pack_wolf(false)),
+            test_monster("wolf", 14, 12, 20, pack_wolf(false)),
+        ];
+        game.find_actors(&mut objects);
+        ai_take_turn(1, &view, &mut game, &mut objects);
+        ai_take_turn(2, &view, &mut game, &mut objects);
+        let leaders = objects
+            .iter()
+            .filter(|object| matches!(object.ai, Some(Ai::Pack { leader: true, .. })))
+            .count();
+        assert_eq!(leaders, 1);
+    }
+
+    #[test]
+    fn flanking_leaves_the_wolves_power_alone() {
+        let config = GameConfig::default();
+        let view = View {
+            in_fov: Box::new(in_map),
+            config: &config,
+        };
+        let mut game = test_game();
+        game.god_mode = true;
+        let mut objects = vec![
+            test_player(20, 20),
+            test_monster("wolf", 19, 20, 20, pack_wolf(true)),
+            test_monster("wolf", 21, 20, 20, pack_wolf(false)),
+        ];
+        game.find_actors(&mut objects);
+        for _ in 0..10 {
+            ai_take_turn(1, &view, &mut game, &mut objects);
+            ai_take_turn(2, &view, &mut game, &mut objects);
+        }
+        for wolf in &objects[1..] {
+            assert_eq!(wolf.fighter.unwrap().base_power, 2);
+        }
+    }
+
+    #[test]
+    fn a_pack_hunts_a_target_in_the_corner_of_the_map() {
+        let config = GameConfig::default();
+        let view = View {
+            in_fov: Box::new(in_map),
+            config: &config,
+        };
+        let mut game = test_game();
+        let mut objects = vec![
+            test_player(0, 0),
+            test_monster("wolf", 3, 3, 20, pack_wolf(true)),
+        ];
+        game.find_actors(&mut objects);
+        ai_take_turn(1, &view, &mut game, &mut objects);
+        assert_eq!(objects[1].pos(), (2, 2));
+    }
+
+    #[test]
+    fn every_pack_gets_its_own_id_and_leader() {
+        let room = Rect::new(10, 10, 10, 10);
+        let map = Map::new(Tile::empty());
+        let spawns = SpawnTables::default();
+        let mut rng = seeded_rng(0);
+        let mut objects = vec![test_player(0, 0)];
+        place_pack("wolf", room, &map, &mut objects, &spawns, &mut rng);
+        // a shop clearing out its room leaves fewer objects than before
+        objects.truncate(2);
+        place_pack("wolf", room, &map, &mut objects, &spawns, &mut rng);
+
+        let mut packs: Vec<(usize, bool)> = objects
+            .iter()
+            .filter_map(|object| match object.ai {
+                Some(Ai::Pack {
+                    pack_id, leader, ..
+                }) => Some((pack_id, leader)),
+                _ => None,
+            })
+            .collect();
+        packs.dedup();
+        let first = packs[0].0;
+        assert!(packs.iter().any(|&(id, _)| id != first));
+        for id in packs.iter().map(|&(id, _)| id) {
+            let leaders = packs
+                .iter()
+                .filter(|&&(other, leader)| other == id && leader)
+                .count();
+            assert_eq!(leaders, 1, "pack {}", id);
+        }
+    }
+
+    #[test]
+    fn enchanted_names_follow_the_catalog() {
+        let mut translations = HashMap::new();
+        translations.insert("item.enchanted".to_string(), "{0} (+{1})".to_string());
+        let strings = Strings { translations };
+        let mut messages = Messages::new();
+        let mut sword = test_gear("sword", Item::Sword, Slot::RightHand);
+        enchant_item(&mut sword, &strings, &mut messages);
+        enchant_item(&mut sword, &strings, &mut messages);
+        assert_eq!(sword.name, "sword (+2)");
+        assert_eq!(sword.base_name(), "sword");
+    }
+
+    #[test]
+    fn enchanted_names_saved_without_the_base_name_still_parse() {
+        let mut sword = test_gear("sword +2", Item::Sword, Slot::RightHand);
+        sword.enchantment = 2;
+        assert_eq!(sword.base_name(), "sword");
+    }
+
+    #[test]
+    fn slot_names_come_from_the_catalog() {
+        let mut translations = HashMap::new();
+        translations.insert("slot.left_hand".to_string(), "linke Hand".to_string());
+        let strings = Strings { translations };
+        assert_eq!(Slot::LeftHand.name(&strings), "linke Hand");
+        assert_eq!(Slot::Back.name(&Strings::default()), "back");
+    }
+
+    #[test]
+    fn settings_are_kept_in_the_data_directory() {
+        let paths = test_paths("settings");
+        let settings = Settings {
+            animations: false,
+            ..Settings::default()
+        };
+        settings.save(&paths);
+        assert!(paths.data_dir.join("settings.json").exists());
+        assert!(!Settings::load(&paths).animations);
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    /// Whether every tile of the FOV map can be seen and walked through.
+    fn fov_properties(fov: &FovMap) -> Vec<((i32, i32), bool, bool)> {
+        (0..MAP_WIDTH)
+            .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+            .map(|(x, y)| ((x, y), fov.is_transparent(x, y), fov.is_walkable(x, y)))
+            .collect()
+    }
+
+    #[test]
+    fn updating_one_fov_tile_leaves_the_others_alone() {
+        let mut map = test_game().map;
+        map[(3, 4)] = Tile::wall();
+        let mut fov = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
+        for x in 0..MAP_WIDTH {
+            for y in 0..MAP_HEIGHT {
+                update_fov_tile(&mut fov, &map, x, y);
+            }
+        }
+        let before = fov_properties(&fov);
+
+        map[(10, 10)] = Tile::wall();
+        update_fov_tile(&mut fov, &map, 10, 10);
+        let changed: Vec<_> = fov_properties(&fov)
+            .into_iter()
+            .zip(before)
+            .filter(|(after, before)| after != before)
+            .map(|(after, _)| after)
+            .collect();
+        assert_eq!(changed, vec![((10, 10), false, false)]);
+        assert!(!fov.is_transparent(3, 4) && fov.is_transparent(4, 3));
+    }
+
+    #[test]
+    fn the_map_cells_map_to_themselves() {
+        assert_eq!(screen_to_map(0, 0), Some((0, 0)));
+        assert_eq!(screen_to_map(17, 9), Some((17, 9)));
+        assert_eq!(
+            screen_to_map(MAP_WIDTH - 1, MAP_HEIGHT - 1),
+            Some((MAP_WIDTH - 1, MAP_HEIGHT - 1))
+        );
+    }
+
+    #[test]
+    fn cells_outside_the_map_have_no_tile() {
+        // the panel is right below the map
+        assert_eq!(screen_to_map(0, MAP_HEIGHT), None);
+        assert_eq!(screen_to_map(5, SCREEN_HEIGHT - 1), None);
+        assert_eq!(screen_to_map(MAP_WIDTH, 0), None);
+        assert_eq!(screen_to_map(-1, 0), None);
+        assert_eq!(screen_to_map(0, -1), None);
+    }
+
+    #[test]
+    fn in_fov_is_safe_just_past_the_edges() {
+        let mut fov = FovMap::new(MAP_WIDTH, MAP_HEIGHT);
+        fov.clear(true, true);
+        fov.compute_fov(MAP_WIDTH / 2, MAP_HEIGHT / 2, 0, true, FovAlgorithm::Basic);
+        let (w, h) = (MAP_WIDTH, MAP_HEIGHT);
+        for &(x, y) in &[(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)] {
+            assert!(in_fov_map(&fov, x, y), "({}, {})", x, y);
+        }
+        for &(x, y) in &[(-1, 0), (w, 0), (0, -1), (0, h), (-1, -1), (w, h)] {
+            assert!(!in_fov_map(&fov, x, y), "({}, {})", x, y);
+        }
+    }
+
+    #[test]
+    fn a_corpse_cant_be_killed_again_for_xp() {
+        let mut game = test_game();
+        let mut objects = vec![
+            test_player(10, 10),
+            test_monster("orc", 11, 10, 5, Ai::Basic),
+        ];
+        assert_eq!(objects[1].take_damage(100, &mut game), Some(10));
+        assert_eq!(objects[1].item, Some(Item::Corpse));
+
+        assert_eq!(objects[1].take_damage(100, &mut game), None);
+        let fireball = item_effect(Item::Fireball, &GameConfig::default()).unwrap();
+        assert_eq!(affect(&fireball, 1, &mut game, &mut objects), None);
+        let (player, corpse) = mut_two(PLAYER, 1, &mut objects);
+        for _ in 0..20 {
+            player.attack(corpse, &mut game);
+        }
+        assert_eq!(player.fighter.unwrap().xp, 10);
+        assert!(corpse.fighter.is_none() && !corpse.alive);
+    }
+
+    #[test]
+    fn ids_survive_objects_going_away_mid_turn() {
+        let mut game = test_game();
+        let mut corpse = Object::new(5, 5, '%', "remains of rat", DARK_RED, false);
+        corpse.item = Some(Item::Corpse);
+        corpse.rots_at = Some(0);
+        let mut objects = vec![
+            test_player(20, 20),
+            corpse,
+            test_monster("orc", 30, 30, 10, Ai::Basic),
+            test_monster("grey wolf", 12, 12, 20, pack_wolf(true)),
+            test_monster("black wolf", 14, 12, 20, pack_wolf(false)),
+        ];
+        game.find_actors(&mut objects);
+        let corpse_id = objects[1].id;
+        let names: Vec<(ObjectId, String)> = objects
+            .iter()
+            .filter(|object| object.id != corpse_id)
+            .map(|object| (object.id, object.name.clone()))
+            .collect();
+
+        run_turns(&mut game, &mut objects, 3);
+
+        assert_eq!(object_index(corpse_id, &objects), None);
+        for (id, name) in &names {
+            let index = object_index(*id, &objects).expect("an object went missing");
+            assert_eq!(&objects[index].name, name);
+        }
+        assert_actors_match(&game, &objects);
+        let player_id = objects[PLAYER].id;
+        for wolf in &objects[2..] {
+            if let Some(Ai::Pack { target, .. }) = wolf.ai {
+                assert_eq!(target, Some(player_id));
+            }
+        }
+    }
+
+    /// A game a few levels in, with something in every part of the save.
+    fn mid_game() -> (Game, Vec<Object>) {
+        let config = GameConfig::default();
+        let mut game = test_game();
+        game.rng = seeded_rng(7);
+        game.god_mode = true;
+        let mut objects = vec![test_player(0, 0)];
+        for level in 1..4 {
+            game.dungeon_level = level;
+            let (map, _) = make_map(&mut objects, level, game.difficulty, &config, &mut game.rng);
+            game.map = map;
+            game.find_actors(&mut objects);
+            run_turns(&mut game, &mut objects, 20);
+        }
+
+        let mut messages = Messages::new();
+        let mut sword = test_gear("sword", Item::Sword, Slot::RightHand);
+        sword.equip(&game.strings, &mut messages);
+        let mut potions = Object::new(0, 0, '!', "healing potion", VIOLET, false);
+        potions.item = Some(Item::Heal);
+        potions.count = 3;
+        game.inventory = vec![sword, potions];
+        objects[PLAYER].effects.push(StatusEffect::Poisoned {
+            damage_per_turn: 1,
+            turns: 5,
+        });
+        let (x, y) = objects[PLAYER].pos();
+        game.fires.push(Fire {
+            x: x + 1,
+            y,
+            turns: 4,
+        });
+        game.gold = 42;
+        game.spells.push(Spell::Heal);
+        game.fled.push(thief_with_loot(0, 0));
+        (game, objects)
+    }
+
+    #[test]
+    fn a_game_in_progress_survives_saving_and_loading() {
+        let paths = test_paths("mid-game");
+        let (game, objects) = mid_game();
+        save_game(&paths.save(0), &game, &objects).unwrap();
+        let (mut loaded, mut loaded_objects) = load_game(&paths.save(0)).unwrap();
+
+        let saved =
+            |game: &Game, objects: &[Object]| serde_json::to_value((game, objects)).unwrap();
+        assert_eq!(saved(&loaded, &loaded_objects), saved(&game, &objects));
+        assert_eq!(loaded.dungeon_level, 3);
+        assert_eq!(loaded.inventory[1].count, 3);
+        assert!(loaded.inventory[0].equipment.unwrap().equipped);
+        assert_eq!(loaded_objects[PLAYER].effects.len(), 1);
+        assert_eq!(loaded.fires.len(), 1);
+
+        // and it carries on from there
+        loaded.find_actors(&mut loaded_objects);
+        run_turns(&mut loaded, &mut loaded_objects, 10);
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    /// Write `contents` to the first save slot and try to load it.
+    fn load_bytes(paths: &Paths, contents: &[u8]) -> Result<(Game, Vec<Object>), LoadError> {
+        fs::write(paths.save(0), contents).unwrap();
+        load_game(&paths.save(0))
+    }
+
+    /// A small game as the current version saves it.
+    fn current_save(paths: &Paths) -> serde_json::Value {
+        let mut stairs = Object::new(12, 10, '<', "stairs", WHITE, false);
+        stairs.render_order = RenderOrder::Stairs;
+        let objects = vec![
+            test_player(10, 10),
+            test_monster("orc", 11, 10, 10, Ai::Basic),
+            stairs,
+        ];
+        save_game(&paths.save(0), &test_game(), &objects).unwrap();
+        serde_json::from_str(&fs::read_to_string(paths.save(0)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn saves_from_older_versions_still_load() {
+        let paths = test_paths("old-saves");
+        let current = current_save(&paths);
+        // version 1 didn't have render orders
+        let mut version_1 = current.clone();
+        version_1[0] = serde_json::Value::from(1);
+        for object in version_1[2].as_array_mut().unwrap() {
+            object.as_object_mut().unwrap().remove("render_order");
+        }
+        // and version 0 didn't have the version either
+        let version_0 = serde_json::Value::from(version_1.as_array().unwrap()[1..].to_vec());
+
+        for old in &[version_1, version_0] {
+            let (game, objects) = load_bytes(&paths, old.to_string().as_bytes()).unwrap();
+            let orders: Vec<RenderOrder> =
+                objects.iter().map(|object| object.render_order).collect();
+            assert_eq!(
+                orders,
+                vec![RenderOrder::Actor, RenderOrder::Actor, RenderOrder::Stairs]
+            );
+            assert!(objects[PLAYER].is_player);
+            assert!(game.validate(&objects).is_empty());
+        }
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn saves_from_newer_versions_are_refused() {
+        let paths = test_paths("new-save");
+        let mut newer = current_save(&paths);
+        newer[0] = serde_json::Value::from(SAVE_VERSION + 1);
+        let result = load_bytes(&paths, newer.to_string().as_bytes());
+        assert!(
+            matches!(result, Err(LoadError::Incompatible(version)) if version == SAVE_VERSION + 1)
+        );
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn garbage_saves_are_an_error_not_a_crash() {
+        let paths = test_paths("garbage");
+        let current = current_save(&paths).to_string();
+        let mut garbage: Vec<Vec<u8>> = vec![
+            b"".to_vec(),
+            b"\xff\xfe\x00\x01".to_vec(),
+            b"null".to_vec(),
+            b"{}".to_vec(),
+            b"[]".to_vec(),
+            b"[1, 2, 3, 4]".to_vec(),
+            b"[\"two\", {}, []]".to_vec(),
+            b"[{}, [1, \"two\", null]]".to_vec(),
+            b"[1, {}, {\"not\": \"a list\"}]".to_vec(),
+            current.as_bytes()[..current.len() / 2].to_vec(),
+        ];
+        let mut rng = seeded_rng(0);
+        for _ in 0..50 {
+            let len = rng.gen_range(1, 200);
+            garbage.push((0..len).map(|_| rng.gen()).collect());
+        }
+
+        for bytes in &garbage {
+            match load_bytes(&paths, bytes) {
+                Err(LoadError::Corrupt(_)) => {}
+                other => panic!(
+                    "{:?} gave {:?}",
+                    String::from_utf8_lossy(bytes),
+                    other.map(|_| ())
+                ),
+            }
+        }
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    #[test]
+    fn migrating_garbage_leaves_it_alone() {
+        let values = vec![
+            serde_json::Value::Null,
+            serde_json::Value::from("save"),
+            serde_json::Value::from(vec![1]),
+            serde_json::json!({ "2": [1] }),
+            serde_json::json!([1, 2, [3, "four", null, []]]),
+        ];
+        for value in values {
+            for version in 0..SAVE_VERSION + 1 {
+                migrate_save(&mut value.clone(), version);
+            }
+        }
+    }
+
+    #[test]
+    fn transient_objects_are_left_out_and_timed_effects_kept() {
+        let paths = test_paths("transient");
+        let mut game = test_game();
+        let mut sparkle = Object::new(11, 11, '*', "sparkle", YELLOW, false);
+        sparkle.transient = true;
+        let confused = Ai::Confused {
+            previous_ai: Box::new(Ai::Basic),
+            num_turns: 4,
+        };
+        let mut objects = vec![
+            test_player(10, 10),
+            sparkle,
+            test_monster("orc", 12, 10, 10, confused.clone()),
+        ];
+        objects[2].effects.push(StatusEffect::Slowed {
+            amount: 50,
+            turns: 3,
+        });
+        game.find_actors(&mut objects);
+        let orc_id = objects[2].id;
+        save_game(&paths.save(0), &game, &objects).unwrap();
+
+        let (mut loaded, mut loaded_objects) = load_game(&paths.save(0)).unwrap();
+        assert_eq!(loaded_objects.len(), 2);
+        assert!(!loaded_objects.iter().any(|object| object.transient));
+        let orc = &loaded_objects[object_index(orc_id, &loaded_objects).unwrap()];
+        assert_eq!(orc.ai, Some(confused));
+        assert_eq!(orc.effects.len(), 1);
+        loaded.find_actors(&mut loaded_objects);
+        assert_actors_match(&loaded, &loaded_objects);
+        assert!(loaded.validate(&loaded_objects).is_empty());
+        fs::remove_dir_all(&paths.data_dir).unwrap();
+    }
+
+    /// Any action at all, including nonsense like dropping an item that
+    /// isn't there.
+    fn random_action(rng: &mut StdRng) -> Action {
+        match rng.gen_range(0, 10) {
+            0 => Action::Wait,
+            1 => Action::PickUp(rng.gen_range(0, 100)),
+            2 => Action::PickUpAll,
+            3 => Action::DropItem(rng.gen_range(0, 30)),
+            4 => Action::UseItem(rng.gen_range(0, 30)),
+            5 => Action::Descend,
+            6 => Action::TakeOut(
+                rng.gen_range(0, 100),
+                rng.gen_range(0, CHEST_ITEMS as usize),
+            ),
+            7 => Action::Butcher(rng.gen_range(0, 100)),
+            _ => Action::Move(rng.gen_range(-1, 2), rng.gen_range(-1, 2)),
+        }
+    }
+
+    #[test]
+    fn a_thousand_random_turns_keep_the_game_sound() {
+        let config = GameConfig::default();
+        let mut game = test_game();
+        game.rng = seeded_rng(11);
+        game.god_mode = true;
+        let mut objects = vec![test_player(0, 0)];
+        let (map, _) = make_map(&mut objects, 4, game.difficulty, &config, &mut game.rng);
+        game.map = map;
+        game.find_actors(&mut objects);
+
+        let mut rng = seeded_rng(12);
+        let mut tries = 0;
+        while game.turns < 1000 {
+            tries += 1;
+            assert!(tries < 100_000, "the player stopped taking turns");
+            let action = random_action(&mut rng);
+            if apply_world_action(action, &mut game, &mut objects) == Some(PlayerAction::TookTurn) {
+                run_turns(&mut game, &mut objects, 1);
+            }
+            game.assign_ids(&mut objects);
+            let problems = game.validate(&objects);
+            assert!(problems.is_empty(), "{:?} after {:?}", problems, action);
+            let (x, y) = objects[PLAYER].pos();
+            assert!(!game.map[(x, y)].blocked, "the player is in a wall");
+        }
+    }
+
+    mod shipped_spawns_tests {
+        use super::*;
+
+        #[test]
+        fn the_built_in_spawn_tables_work() {
+            let spawns = SpawnTables::default();
+            spawns.validate("data/spawns.json").unwrap();
+            assert_eq!(spawns.monster("orc").name, "orc");
+        }
+
+        #[test]
+        fn the_built_in_copy_matches_the_shipped_file() {
+            let shipped = SpawnTables::load(&[]).unwrap();
+            let built_in = SpawnTables::default();
+            assert_eq!(
+                serde_json::to_value(&shipped).unwrap(),
+                serde_json::to_value(&built_in).unwrap()
+            );
+        }
+    }
+
+    mod effects_tests {
+        use super::*;
+        use crate::effects::{apply_effect, caught_in_blast, check_targets, TargetKind};
+
+        /// The first level of a seeded dungeon, with only the player on it.
+        fn effect_game(seed: usize) -> (Game, Vec<Object>) {
+            let (map, mut objects) = generate_level(seed, 1);
+            objects.truncate(1);
+            let mut game = test_game();
+            game.map = map;
+            game.rng = seeded_rng(seed);
+            (game, objects)
+        }
+
+        /// Put a monster `dx` tiles to the right of the player, still in the
+        /// first room.
+        fn add_monster(
+            name: &str,
+            dx: i32,
+            hp: i32,
+            ai: Ai,
+            game: &mut Game,
+            objects: &mut Vec<Object>,
+        ) -> usize {
+            let (x, y) = objects[PLAYER].pos();
+            assert!(!game.map[(x + dx, y)].blocked);
+            objects.push(test_monster(name, x + dx, y, hp, ai));
+            game.assign_ids(objects);
+            game.find_actors(objects);
+            objects.len() - 1
+        }
+
+        /// Use `item` the way `cast_effect` does, aiming at `aim` if it
+        /// needs aiming. Returns whether the item got used up.
+        fn cast(item: Item, aim: (i32, i32), game: &mut Game, objects: &mut [Object]) -> bool {
+            let effect = item_effect(item, &GameConfig::default()).unwrap();
+            let (targets, center) = match effect.target {
+                TargetKind::Caster => (vec![objects[PLAYER].id], None),
+                TargetKind::NearestEnemy(range) => {
+                    let monster = closest_monster(in_map, objects, range);
+                    (monster.into_iter().collect(), None)
+                }
+                TargetKind::ChosenMonster(_) => {
+                    let monster = objects
+                        .iter()
+                        .find(|obj| obj.pos() == aim && obj.fighter.is_some());
+                    (monster.map(|obj| obj.id).into_iter().collect(), None)
+                }
+                TargetKind::ChosenTile(radius) => {
+                    (caught_in_blast(aim, radius, game, objects), Some(aim))
+                }
+            };
+            match check_targets(&effect, targets, center, game, objects) {
+                Some(targets) => {
+                    apply_effect(&effect, &targets, center, game, objects);
+                    true
+                }
+                None => false,
+            }
+        }
+
+        fn messages(game: &Game) -> Vec<String> {
+            game.messages
+                .iter()
+                .map(|message| message.to_string())
+                .collect()
+        }
+
+        #[test]
+        fn a_healing_potion_heals_up_to_the_maximum() {
+            let (mut game, mut objects) = effect_game(1);
+            objects[PLAYER].fighter.as_mut().unwrap().hp = 5;
+            assert!(cast(Item::Heal, (0, 0), &mut game, &mut objects));
+            assert_eq!(objects[PLAYER].fighter.unwrap().hp, 30);
+            assert_eq!(messages(&game), ["Your wounds start to feel better!"]);
+
+            assert!(!cast(Item::Heal, (0, 0), &mut game, &mut objects));
+            assert_eq!(
+                game.messages.last().unwrap().to_string(),
+                "You are already at full health."
+            );
+        }
+
+        #[test]
+        fn lightning_strikes_the_closest_monster() {
+            let (mut game, mut objects) = effect_game(2);
+            let far = add_monster("troll", -2, 100, Ai::Basic, &mut game, &mut objects);
+            let near = add_monster("orc", 1, 100, Ai::Basic, &mut game, &mut objects);
+            assert!(cast(Item::Lightning, (0, 0), &mut game, &mut objects));
+            assert_eq!(objects[near].fighter.unwrap().hp, 60);
+            assert_eq!(objects[far].fighter.unwrap().hp, 100);
+            assert_eq!(
+                messages(&game),
+                ["A lightning bolt strikes the orc with a loud thunder! \
+                  The damage is 40 hit points."]
+            );
+            assert_eq!(game.last_damage_source, "a lightning bolt");
+        }
+
+        #[test]
+        fn lightning_needs_a_monster_in_range() {
+            let (mut game, mut objects) = effect_game(2);
+            assert!(!cast(Item::Lightning, (0, 0), &mut game, &mut objects));
+            assert_eq!(messages(&game), ["No enemy is close enough to strike."]);
+        }
+
+        #[test]
+        fn confusion_wears_off_into_the_old_ai() {
+            let (mut game, mut objects) = effect_game(3);
+            game.god_mode = true;
+            let orc = add_monster("orc", 2, 10, Ai::Basic, &mut game, &mut objects);
+            let aim = objects[orc].pos();
+            assert!(cast(Item::Confuse, aim, &mut game, &mut objects));
+            assert_eq!(
+                objects[orc].ai,
+                Some(Ai::Confused {
+                    previous_ai: Box::new(Ai::Basic),
+                    num_turns: 10,
+                })
+            );
+            assert_eq!(
+                messages(&game),
+                ["The eyes of orc look vacant, as he starts to stumble around!"]
+            );
+            // confused for turns 10 down to 0, back to normal the turn after
+            run_turns(&mut game, &mut objects, 11);
+            assert!(matches!(objects[orc].ai, Some(Ai::Confused { .. })));
+            run_turns(&mut game, &mut objects, 1);
+            assert_eq!(objects[orc].ai, Some(Ai::Basic));
+        }
+
+        #[test]
+        fn bosses_shrug_off_confusion() {
+            let (mut game, mut objects) = effect_game(3);
+            let boss = Ai::Boss {
+                phase: 1,
+                summon_cooldown: 0,
+            };
+            let king = add_monster(
+                "ancient king",
+                2,
+                150,
+                boss.clone(),
+                &mut game,
+                &mut objects,
+            );
+            let aim = objects[king].pos();
+            assert!(cast(Item::Confuse, aim, &mut game, &mut objects));
+            assert_eq!(objects[king].ai, Some(boss));
+            assert_eq!(
+                messages(&game),
+                ["The ancient king shrugs off the confusion!"]
+            );
+        }
+
+        #[test]
+        fn a_fireball_burns_everyone_it_reaches() {
+            let (mut game, mut objects) = effect_game(4);
+            let orc = add_monster("orc", 2, 10, Ai::Basic, &mut game, &mut objects);
+            let troll = add_monster("troll", -2, 100, Ai::Basic, &mut game, &mut objects);
+            let aim = objects[orc].pos();
+            assert!(cast(Item::Fireball, aim, &mut game, &mut objects));
+            // the player is close enough to get burned, the troll isn't
+            let player = objects[PLAYER].fighter.unwrap();
+            assert_eq!(player.hp, 5);
+            assert!(!objects[orc].alive);
+            assert_eq!(objects[troll].fighter.unwrap().hp, 100);
+            // but burning themself doesn't earn the player anything
+            assert_eq!(player.xp, 20);
+            assert!(game
+                .fires
+                .iter()
+                .any(|fire| (fire.x, fire.y) == aim && fire.turns == FIRE_TURNS));
+            assert_eq!(game.last_damage_source, "a fireball");
+            assert_eq!(
+                &messages(&game)[..3],
+                [
+                    "The fireball explodes, burning everything within 3 tiles!",
+                    "The player gets burned for 25 hit points.",
+                    "The orc gets burned for 25 hit points.",
+                ]
+            );
+        }
+
+        #[test]
+        fn haste_speeds_up_the_player_for_a_while() {
+            let (mut game, mut objects) = effect_game(5);
+            assert!(cast(Item::Haste, (0, 0), &mut game, &mut objects));
+            assert_eq!(objects[PLAYER].speed(), NORMAL_SPEED + HASTE_AMOUNT);
+            run_turns(&mut game, &mut objects, HASTE_TURNS as u32);
+            assert_eq!(objects[PLAYER].speed(), NORMAL_SPEED);
+        }
+    }
+
+    /// FNV-1a: a fingerprint that stays the same from one Rust version to
+    /// the next, unlike the standard library's hasher.
+    fn fingerprint(text: &str) -> u64 {
+        text.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        })
+    }
+
+    /// Play the arena for a while and write down everything the monsters
+    /// decided: where everyone went, who got hurt, what behaviour they
+    /// ended up with and what the messages said.
+    fn play_arena(seed: usize) -> u64 {
+        let (mut game, mut objects) = test_arena(seed);
+        let mut record = String::new();
+        for _ in 0..150 {
+            run_turns(&mut game, &mut objects, 1);
+            for object in &objects {
+                record += &format!(
+                    "{} {:?} {:?} {:?}\n",
+                    object.name,
+                    object.pos(),
+                    object.fighter.map(|f| f.hp),
+                    object.ai
+                );
+            }
+        }
+        for message in game.messages.iter() {
+            record += &format!("{}\n", message);
+        }
+        fingerprint(&record)
+    }
+
+    #[test]
+    fn monsters_play_the_same_as_before_the_registry() {
+        // recorded with the `match` in `Ai::take_turn` the registry replaced
+        let expected: [u64; 5] = [
+            0xf8a5_3143_54f3_0d81,
+            0x65d2_a19e_c024_f857,
+            0xd7ee_112f_0365_1b71,
+            0xb14a_d491_6c68_c14c,
+            0xcc38_2db8_35e6_92e6,
+        ];
+        for (seed, &expected) in expected.iter().enumerate() {
+            assert_eq!(play_arena(seed), expected, "seed {}", seed);
+        }
+    }
+
+    /// The registry with a mod whose `ai.json` says `ai_json`.
+    fn ai_with_mods(name: &str, ai_json: &str) -> Result<AiRegistry, String> {
+        let paths = test_paths(name);
+        let dir = paths.data_dir.join("ai-mod");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("ai.json"), ai_json).unwrap();
+        AiRegistry::load(&[dir])
+    }
+
+    #[test]
+    fn a_mod_can_replace_a_behaviour() {
+        let config = GameConfig {
+            ai: ai_with_mods("ai-replace", r#"{"wander": {"Caster": {"cooldown": 3}}}"#).unwrap(),
+            ..GameConfig::default()
+        };
+        let view = View {
+            in_fov: Box::new(in_map),
+            config: &config,
+        };
+        let mut game = test_game();
+        let mut objects = vec![
+            test_player(10, 10),
+            test_monster("bat", 30, 30, 5, Ai::Wander),
+        ];
+        ai_take_turn(1, &view, &mut game, &mut objects);
+        // the replacement took the turn, and the bat keeps it from now on
+        assert_eq!(objects[1].ai, Some(Ai::Caster { cooldown: 2 }));
+    }
+
+    #[test]
+    fn replacing_a_behaviour_that_doesnt_exist_is_an_error() {
+        let error = ai_with_mods("ai-unknown", r#"{"dancing": "Basic"}"#)
+            .err()
+            .unwrap();
+        assert!(error.contains("`dancing`"), "{}", error);
+        assert!(ai_with_mods("ai-broken", r#"{"wander": "Dancing"}"#).is_err());
+    }
+
+    #[test]
+    fn a_new_behaviour_takes_over_its_name() {
+        let mut config = GameConfig::default();
+        // bats that never leave their perch
+        config.ai.register(
+            "wander",
+            |ai, _, _: &View, _: &mut Game, _: &mut [Object]| ai,
+        );
+        let view = View {
+            in_fov: Box::new(in_map),
+            config: &config,
+        };
+        let mut game = test_game();
+        game.rng = seeded_rng(1);
+        let mut objects = vec![
+            test_player(10, 10),
+            test_monster("bat", 30, 30, 5, Ai::Wander),
+        ];
+        for _ in 0..20 {
+            ai_take_turn(1, &view, &mut game, &mut objects);
+        }
+        assert_eq!(objects[1].pos(), (30, 30));
+        assert_eq!(objects[1].ai, Some(Ai::Wander));
+    }
+
+    #[test]
+    fn every_behaviour_is_registered() {
+        let registry = AiRegistry::default();
+        let everything = vec![
+            Ai::Basic,
+            Ai::Confused {
+                previous_ai: Box::new(Ai::Basic),
+                num_turns: 1,
+            },
+            Ai::Chasing {
+                last_seen: (0, 0),
+                num_turns: 1,
+            },
+            pack_wolf(true),
+            Ai::Caster { cooldown: 0 },
+            Ai::Thief,
+            Ai::Fleeing,
+            Ai::Slime,
+            Ai::Boss {
+                phase: 1,
+                summon_cooldown: 0,
+            },
+            Ai::Wander,
+            Ai::Shopkeeper,
+        ];
+        for ai in &everything {
+            assert!(registry.behaviors.contains_key(ai.name()), "{:?}", ai);
+        }
+        assert_eq!(registry.behaviors.len(), everything.len());
+    }
+
+    fn loose_potion(x: i32, y: i32) -> Object {
+        let mut potion = Object::new(x, y, '!', "healing potion", WHITE, false);
+        potion.item = Some(Item::Heal);
+        potion
+    }
+
+    /// The player at (5, 5) with a potion, a chest with a sword and a potion
+    /// in it and an orc's corpse, and another potion on the next tile.
+    fn crowded_tile() -> (Game, Vec<Object>) {
+        let mut game = test_game();
+        let mut orc = test_monster("orc", 5, 5, 10, Ai::Basic);
+        monster_death(&mut orc, &mut game);
+        let contents = vec![
+            test_gear("sword", Item::Sword, Slot::RightHand),
+            loose_potion(0, 0),
+        ];
+        let mut objects = vec![
+            test_player(5, 5),
+            loose_potion(5, 5),
+            create_chest(5, 5, contents),
+            orc,
+            loose_potion(6, 5),
+        ];
+        game.assign_ids(&mut objects);
+        (game, objects)
+    }
+
+    #[test]
+    fn everything_on_the_tile_gets_a_verb() {
+        let (_, objects) = crowded_tile();
+        let verbs = |x, open: &[ObjectId]| -> Vec<(Verb, Option<usize>)> {
+            tile_interactions(x, 5, &objects, open)
+                .iter()
+                .map(|entry| (entry.verb, entry.slot))
+                .collect()
+        };
+        assert_eq!(
+            verbs(5, &[]),
+            vec![
+                (Verb::PickUp, None),
+                (Verb::Open, None),
+                (Verb::Butcher, None)
+            ]
+        );
+        // an open chest lists what's in it
+        assert_eq!(
+            verbs(5, &[objects[2].id]),
+            vec![
+                (Verb::PickUp, None),
+                (Verb::Close, None),
+                (Verb::Take, Some(0)),
+                (Verb::Take, Some(1)),
+                (Verb::Butcher, None)
+            ]
+        );
+        // a lone item is all there is next door: `g` picks it straight up
+        assert_eq!(verbs(6, &[]), vec![(Verb::PickUp, None)]);
+    }
+
+    #[test]
+    fn the_tile_menu_marks_opens_and_butchers() {
+        use Action::*;
+
+        let (_, objects) = crowded_tile();
+        let (potion, chest, corpse) = (objects[1].id, objects[2].id, objects[3].id);
+        let mut menu = TileMenu::new(5, 5);
+        let entries = menu.entries(&objects);
+        assert_eq!(menu.choose(entries[0]), None);
+        assert_eq!(menu.choose(entries[1]), None);
+
+        let entries = menu.entries(&objects);
+        assert_eq!(entries.len(), 5);
+        menu.choose(entries[2]);
+        menu.choose(entries[3]);
+        assert!(menu.is_marked(&entries[2]) && menu.is_marked(&entries[3]));
+        // the chest gets emptied from the back
+        assert_eq!(
+            menu.actions(),
+            vec![TakeOut(chest, 1), TakeOut(chest, 0), PickUp(potion)]
+        );
+
+        // choosing a marked thing again unmarks it
+        menu.choose(entries[2]);
+        assert_eq!(menu.actions(), vec![TakeOut(chest, 1), PickUp(potion)]);
+
+        // butchering ends the menu, and what's marked comes along first
+        assert_eq!(
+            menu.choose(entries[4]),
+            Some(vec![TakeOut(chest, 1), PickUp(potion), Butcher(corpse)])
+        );
+    }
+
+    #[test]
+    fn closing_a_container_unmarks_what_is_in_it() {
+        let (_, objects) = crowded_tile();
+        let mut menu = TileMenu::new(5, 5);
+        menu.choose(menu.entries(&objects)[1]);
+        menu.choose(menu.entries(&objects)[2]);
+        assert_eq!(menu.actions().len(), 1);
+        menu.choose(menu.entries(&objects)[1]);
+        assert!(menu.actions().is_empty());
+
+        // `*` only marks the loose items
+        menu.mark_all(&objects);
+        assert_eq!(menu.actions(), vec![Action::PickUp(objects[1].id)]);
+        let strings = Strings::default();
+        let labels: Vec<String> = menu
+            .entries(&objects)
+            .iter()
+            .map(|entry| menu.label(entry, &objects, &strings))
+            .collect();
+        assert_eq!(
+            labels,
+            vec![
+                "[x] pick up healing potion",
+                "open chest",
+                "butcher remains of orc"
+            ]
+        );
+    }
+
+    #[test]
+    fn taking_things_out_of_a_chest() {
+        let (mut game, mut objects) = crowded_tile();
+        let chest = objects[2].id;
+        for &action in &[Action::TakeOut(chest, 1), Action::TakeOut(chest, 0)] {
+            let result = apply_world_action(action, &mut game, &mut objects);
+            assert_eq!(result, Some(PlayerAction::DidntTakeTurn));
+        }
+        let names: Vec<&str> = game.inventory.iter().map(|item| &item.name[..]).collect();
+        assert_eq!(names, vec!["healing potion", "sword"]);
+        assert_eq!(objects[2].contents.as_ref().map(Vec::len), Some(0));
+        // the empty chest stays where it is
+        assert_eq!(objects.len(), 5);
+    }
+
+    #[test]
+    fn a_full_pack_leaves_things_in_the_chest() {
+        let (mut game, mut objects) = crowded_tile();
+        for number in 0..INVENTORY_SIZE {
+            let mut scroll = Object::new(0, 0, '#', &format!("scroll {}", number), WHITE, false);
+            scroll.item = Some(Item::Teleport);
+            game.inventory.push(scroll);
+        }
+        let chest = objects[2].id;
+        apply_world_action(Action::TakeOut(chest, 0), &mut game, &mut objects);
+        assert_eq!(objects[2].contents.as_ref().map(Vec::len), Some(2));
+        assert_eq!(game.inventory.len(), INVENTORY_SIZE);
+        assert_eq!(objects.len(), 5);
+    }
+
+    #[test]
+    fn butchering_a_corpse_makes_meat() {
+        let (mut game, mut objects) = crowded_tile();
+        let corpse = objects[3].id;
+        let rots_at = objects[3].rots_at;
+        let result = apply_world_action(Action::Butcher(corpse), &mut game, &mut objects);
+        assert_eq!(result, Some(PlayerAction::TookTurn));
+        assert_eq!(object_index(corpse, &objects), None);
+        let meat = &game.inventory[0];
+        assert_eq!(meat.name, "orc meat");
+        assert_eq!(meat.item, Some(Item::Meat));
+        assert_eq!(meat.count, MEAT_PORTIONS);
+        assert_eq!(meat.rots_at, rots_at);
+
+        // there's nothing left to cut up
+        let result = apply_world_action(Action::Butcher(corpse), &mut game, &mut objects);
+        assert_eq!(result, Some(PlayerAction::DidntTakeTurn));
+    }
+
+    #[test]
+    fn replays_check_whats_under_the_player() {
+        let (game, objects) = crowded_tile();
+        let (potion, chest, corpse) = (objects[1].id, objects[2].id, objects[3].id);
+        assert!(check_action(Action::TakeOut(chest, 1), &game, &objects).is_ok());
+        assert!(check_action(Action::TakeOut(chest, 2), &game, &objects).is_err());
+        assert!(check_action(Action::TakeOut(potion, 0), &game, &objects).is_err());
+        assert!(check_action(Action::Butcher(corpse), &game, &objects).is_ok());
+        assert!(check_action(Action::Butcher(potion), &game, &objects).is_err());
+    }
+
+    #[test]
+    fn some_rooms_have_chests() {
+        let chests: Vec<Object> = (0..20)
+            .flat_map(|seed| generate_level(seed, 3).1)
+            .filter(|object| object.contents.is_some())
+            .collect();
+        assert!(!chests.is_empty());
+        for chest in chests {
+            let contents = chest.contents.unwrap();
+            assert!((1..=CHEST_ITEMS as usize).contains(&contents.len()));
+            assert!(contents.iter().all(|item| item.item.is_some()));
+        }
+    }
+
+    #[test]
+    fn a_chest_keeps_its_contents_in_a_save() {
+        let (_, objects) = crowded_tile();
+        let json = serde_json::to_string(&objects[2]).unwrap();
+        let chest: Object = serde_json::from_str(&json).unwrap();
+        let names: Vec<String> = chest
+            .contents
+            .unwrap()
+            .into_iter()
+            .map(|item| item.name)
+            .collect();
+        assert_eq!(names, vec!["sword", "healing potion"]);
+    }
+}
@@ -106,11 +106,22 @@ impl Object {
     }
 }
 
+/// Make the tile at (x, y) passable
+fn carve(x: i32, y: i32, map: &mut Map) {
+    assert!(
+        (0..MAP_WIDTH).contains(&x) && (0..MAP_HEIGHT).contains(&y),
+        "carving outside the map at ({}, {})",
+        x,
+        y
+    );
+    map[x as usize][y as usize] = Tile::empty();
+}
+
 fn create_room(room: Rect, map: &mut Map) {
     // go through the tiles in the rectangle and make them passable
     for x in (room.x1 + 1)..room.x2 {
         for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+            carve(x, y, map);
         }
     }
 }
@@ -118,14 +129,16 @@ fn create_room(room: Rect, map: &mut Map) {
 fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
     // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
     for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        carve(x, y, map);
     }
 }
 
+// not used until the next part
+#[allow(dead_code)]
 fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
     // vertical tunnel
     for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        carve(x, y, map);
     }
 }
 
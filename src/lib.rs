@@ -0,0 +1,69 @@
+//! The parts of the final game that don't need a window: the map and its
+//! rooms, the level progression tables and a couple of helpers.
+//!
+//! Only `part-14-extras` uses this crate. The earlier parts keep all of
+//! their code in one file, the way the tutorial builds it up. Unlike
+//! `src/bin/*.rs`, these files are not generated from the tutorial pages.
+
+pub mod map;
+pub mod progression;
+
+use std::cmp;
+
+pub use crate::map::{Map, Rect, Tile};
+
+/// Mutably borrow two *separate* elements from the given slice.
+/// Panics when the indexes are equal or out of bounds.
+pub fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
+    assert!(first_index != second_index);
+    let split_at_index = cmp::max(first_index, second_index);
+    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
+    if first_index < second_index {
+        (&mut first_slice[first_index], &mut second_slice[0])
+    } else {
+        (&mut second_slice[0], &mut first_slice[second_index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mut_two_borrows_in_either_order() {
+        let mut items = vec![1, 2, 3, 4];
+        {
+            let (first, second) = mut_two(0, 3, &mut items);
+            assert_eq!((*first, *second), (1, 4));
+            *first = 10;
+        }
+        {
+            let (first, second) = mut_two(2, 1, &mut items);
+            assert_eq!((*first, *second), (3, 2));
+            *second = 20;
+        }
+        assert_eq!(items, vec![10, 20, 3, 4]);
+    }
+
+    #[test]
+    fn mut_two_works_on_neighbours() {
+        let mut items = vec!['a', 'b'];
+        let (first, second) = mut_two(1, 0, &mut items);
+        std::mem::swap(first, second);
+        assert_eq!(items, vec!['b', 'a']);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mut_two_refuses_the_same_index() {
+        let mut items = vec![1, 2, 3];
+        mut_two(1, 1, &mut items);
+    }
+
+    #[test]
+    #[should_panic]
+    fn mut_two_refuses_an_index_out_of_bounds() {
+        let mut items = vec![1, 2, 3];
+        mut_two(0, 3, &mut items);
+    }
+}
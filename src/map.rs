@@ -0,0 +1,454 @@
+//! The dungeon map: its tiles, the rooms and the tunnels between them.
+
+use std::cmp;
+use std::ops::{Index, IndexMut};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// size of the map
+pub const MAP_WIDTH: i32 = 80;
+pub const MAP_HEIGHT: i32 = 43;
+
+/// A tile of the map and its properties
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub blocked: bool,
+    pub explored: bool,
+    pub block_sight: bool,
+}
+
+impl Tile {
+    pub fn empty() -> Self {
+        Tile {
+            blocked: false,
+            explored: false,
+            block_sight: false,
+        }
+    }
+
+    pub fn wall() -> Self {
+        Tile {
+            blocked: true,
+            explored: false,
+            block_sight: true,
+        }
+    }
+}
+
+/// The tiles of a level, `MAP_WIDTH` tiles across and `MAP_HEIGHT` down.
+/// `get` and `set` check the position; indexing with `map[(x, y)]` panics
+/// with the position when it's outside the map.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Map(Vec<Vec<Tile>>);
+
+impl Map {
+    /// A map with every tile set to `tile`.
+    pub fn new(tile: Tile) -> Self {
+        Map(vec![vec![tile; MAP_HEIGHT as usize]; MAP_WIDTH as usize])
+    }
+
+    pub fn get(&self, x: i32, y: i32) -> Option<&Tile> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.0.get(x as usize)?.get(y as usize)
+    }
+
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Tile> {
+        if x < 0 || y < 0 {
+            return None;
+        }
+        self.0.get_mut(x as usize)?.get_mut(y as usize)
+    }
+
+    /// Replace the tile at (x, y). Returns `false` and leaves the map
+    /// alone when the position is outside it.
+    #[must_use]
+    pub fn set(&mut self, x: i32, y: i32, tile: Tile) -> bool {
+        match self.get_mut(x, y) {
+            Some(old) => {
+                *old = tile;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Every tile, a column at a time.
+    pub fn tiles(&self) -> impl Iterator<Item = &Tile> {
+        self.0.iter().flatten()
+    }
+
+    pub fn tiles_mut(&mut self) -> impl Iterator<Item = &mut Tile> {
+        self.0.iter_mut().flatten()
+    }
+}
+
+impl Index<(i32, i32)> for Map {
+    type Output = Tile;
+
+    fn index(&self, (x, y): (i32, i32)) -> &Tile {
+        match self.get(x, y) {
+            Some(tile) => tile,
+            None => panic!("({}, {}) is outside the map", x, y),
+        }
+    }
+}
+
+impl IndexMut<(i32, i32)> for Map {
+    fn index_mut(&mut self, (x, y): (i32, i32)) -> &mut Tile {
+        match self.get_mut(x, y) {
+            Some(tile) => tile,
+            None => panic!("({}, {}) is outside the map", x, y),
+        }
+    }
+}
+
+/// A rectangle on the map, used to characterise a room.
+#[derive(Clone, Copy, Debug)]
+pub struct Rect {
+    pub x1: i32,
+    pub y1: i32,
+    pub x2: i32,
+    pub y2: i32,
+}
+
+impl Rect {
+    pub fn new(x: i32, y: i32, w: i32, h: i32) -> Self {
+        Rect {
+            x1: x,
+            y1: y,
+            x2: x + w,
+            y2: y + h,
+        }
+    }
+
+    pub fn center(&self) -> (i32, i32) {
+        let center_x = (self.x1 + self.x2) / 2;
+        let center_y = (self.y1 + self.y2) / 2;
+        (center_x, center_y)
+    }
+
+    pub fn intersects_with(&self, other: &Rect) -> bool {
+        // returns true if this rectangle intersects with another one
+        (self.x1 <= other.x2)
+            && (self.x2 >= other.x1)
+            && (self.y1 <= other.y2)
+            && (self.y2 >= other.y1)
+    }
+}
+
+/// Is the position within the map bounds?
+pub fn in_map(x: i32, y: i32) -> bool {
+    x >= 0 && y >= 0 && x < MAP_WIDTH && y < MAP_HEIGHT
+}
+
+/// Make the tile at (x, y) passable. Carving outside the map is a bug in
+/// the generator, so it stops right there and names the position.
+pub fn carve(x: i32, y: i32, map: &mut Map) {
+    let carved = map.set(x, y, Tile::empty());
+    assert!(carved, "carving outside the map at ({}, {})", x, y);
+}
+
+pub fn create_room(room: Rect, map: &mut Map) {
+    // go through the tiles in the rectangle and make them passable
+    for x in (room.x1 + 1)..room.x2 {
+        for y in (room.y1 + 1)..room.y2 {
+            carve(x, y, map);
+        }
+    }
+}
+
+pub fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
+    // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
+    for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
+        carve(x, y, map);
+    }
+}
+
+pub fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
+    // vertical tunnel
+    for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
+        carve(x, y, map);
+    }
+}
+
+/// Dig up to `max_rooms` rooms that don't overlap, each one joined to the
+/// one before it by a tunnel. `on_room` gets called with every room right
+/// after it's dug, so the caller can fill it with the same random number
+/// generator. Returns the rooms in the order they were dug.
+pub fn dig_rooms<R: Rng>(
+    map: &mut Map,
+    max_rooms: i32,
+    min_size: i32,
+    max_size: i32,
+    rng: &mut R,
+    mut on_room: impl FnMut(usize, Rect, &Map, &mut R),
+) -> Vec<Rect> {
+    let mut rooms: Vec<Rect> = vec![];
+
+    for _ in 0..max_rooms {
+        // random width and height
+        let w = rng.gen_range(min_size, max_size + 1);
+        let h = rng.gen_range(min_size, max_size + 1);
+        // random position without going out of the boundaries of the map
+        let x = rng.gen_range(0, MAP_WIDTH - w);
+        let y = rng.gen_range(0, MAP_HEIGHT - h);
+
+        let new_room = Rect::new(x, y, w, h);
+
+        // run through the other rooms and see if they intersect with this one
+        let failed = rooms
+            .iter()
+            .any(|other_room| new_room.intersects_with(other_room));
+
+        if !failed {
+            // this means there are no intersections, so this room is valid
+
+            // "paint" it to the map's tiles
+            create_room(new_room, map);
+
+            // all rooms after the first:
+            // connect it to the previous room with a tunnel
+            if let Some(prev_room) = rooms.last() {
+                let (new_x, new_y) = new_room.center();
+                let (prev_x, prev_y) = prev_room.center();
+
+                // toss a coin (random bool value -- either true or false)
+                if rng.gen() {
+                    // first move horizontally, then vertically
+                    create_h_tunnel(prev_x, new_x, prev_y, map);
+                    create_v_tunnel(prev_y, new_y, new_x, map);
+                } else {
+                    // first move vertically, then horizontally
+                    create_v_tunnel(prev_y, new_y, prev_x, map);
+                    create_h_tunnel(prev_x, new_x, new_y, map);
+                }
+            }
+
+            on_room(rooms.len(), new_room, map, rng);
+
+            // finally, append the new room to the list
+            rooms.push(new_room);
+        }
+    }
+
+    rooms
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, StdRng};
+
+    fn generate(seed: usize) -> (Map, Vec<Rect>) {
+        let mut map = Map::new(Tile::wall());
+        let mut rng = StdRng::from_seed(&[seed][..]);
+        let rooms = dig_rooms(&mut map, 30, 6, 10, &mut rng, |_, _, _, _| {});
+        (map, rooms)
+    }
+
+    /// All the tiles you can walk to from (x, y).
+    fn reachable(map: &Map, x: i32, y: i32) -> Vec<Vec<bool>> {
+        let mut seen = vec![vec![false; MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+        let mut todo = vec![(x, y)];
+        while let Some((x, y)) = todo.pop() {
+            if !in_map(x, y) || seen[x as usize][y as usize] {
+                continue;
+            }
+            if map[(x, y)].blocked {
+                continue;
+            }
+            seen[x as usize][y as usize] = true;
+            todo.extend(&[(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)]);
+        }
+        seen
+    }
+
+    #[test]
+    fn map_checks_the_position() {
+        let mut map = Map::new(Tile::wall());
+        assert!(map.get(0, 0).is_some());
+        assert!(map.get(MAP_WIDTH - 1, MAP_HEIGHT - 1).is_some());
+        for &(x, y) in &[(-1, 0), (0, -1), (MAP_WIDTH, 0), (0, MAP_HEIGHT)] {
+            assert!(map.get(x, y).is_none(), "({}, {})", x, y);
+            assert!(!map.set(x, y, Tile::empty()), "({}, {})", x, y);
+        }
+        assert!(map.tiles().all(|tile| tile.blocked));
+
+        assert!(map.set(3, 4, Tile::empty()));
+        assert!(!map[(3, 4)].blocked);
+        assert_eq!(map.tiles().filter(|tile| !tile.blocked).count(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "(80, 5) is outside the map")]
+    fn indexing_outside_the_map_names_the_position() {
+        let map = Map::new(Tile::wall());
+        let _ = map[(MAP_WIDTH, 5)];
+    }
+
+    #[test]
+    #[should_panic(expected = "carving outside the map at (-1, 5)")]
+    fn carving_outside_the_map_names_the_position() {
+        let mut map = Map::new(Tile::wall());
+        create_h_tunnel(-1, 5, 5, &mut map);
+    }
+
+    #[test]
+    fn a_map_from_an_old_save_is_still_checked() {
+        // saves store the columns as they are, so a broken one can have
+        // any shape at all
+        let map: Map = serde_json::from_str(
+            r#"[[{"blocked": true, "explored": false, "block_sight": true}], []]"#,
+        )
+        .unwrap();
+        assert!(map.get(0, 0).is_some());
+        assert!(map.get(0, 1).is_none());
+        assert!(map.get(1, 0).is_none());
+        assert_eq!(
+            serde_json::to_string(&map).unwrap().chars().next(),
+            Some('[')
+        );
+    }
+
+    #[test]
+    fn overlapping_rooms_intersect() {
+        let room = Rect::new(10, 10, 10, 5);
+        assert!(room.intersects_with(&Rect::new(15, 12, 10, 10)));
+        // either way round
+        assert!(Rect::new(15, 12, 10, 10).intersects_with(&room));
+        // one inside the other
+        assert!(room.intersects_with(&Rect::new(12, 11, 2, 2)));
+        assert!(room.intersects_with(&room));
+    }
+
+    #[test]
+    fn rooms_sharing_a_wall_intersect() {
+        // the outer tiles are walls, so rooms that share them would merge
+        let room = Rect::new(10, 10, 10, 5);
+        assert!(room.intersects_with(&Rect::new(20, 10, 5, 5)));
+        assert!(room.intersects_with(&Rect::new(10, 15, 5, 5)));
+        assert!(room.intersects_with(&Rect::new(20, 15, 5, 5)));
+    }
+
+    #[test]
+    fn separate_rooms_dont_intersect() {
+        let room = Rect::new(10, 10, 10, 5);
+        assert!(!room.intersects_with(&Rect::new(21, 10, 5, 5)));
+        assert!(!room.intersects_with(&Rect::new(10, 16, 5, 5)));
+        assert!(!room.intersects_with(&Rect::new(0, 0, 9, 9)));
+        // lined up on one axis, apart on the other
+        assert!(!room.intersects_with(&Rect::new(30, 10, 5, 5)));
+    }
+
+    #[test]
+    fn center_rounds_down() {
+        assert_eq!(Rect::new(10, 10, 10, 5).center(), (15, 12));
+        assert_eq!(Rect::new(0, 0, 1, 1).center(), (0, 0));
+    }
+
+    #[test]
+    fn tunnels_go_either_way() {
+        let mut map = Map::new(Tile::wall());
+        create_h_tunnel(7, 3, 5, &mut map);
+        create_v_tunnel(9, 5, 3, &mut map);
+        let open: Vec<(i32, i32)> = (0..MAP_WIDTH)
+            .flat_map(|x| (0..MAP_HEIGHT).map(move |y| (x, y)))
+            .filter(|&(x, y)| !map[(x, y)].blocked)
+            .collect();
+        let mut expected: Vec<(i32, i32)> = (3..=7).map(|x| (x, 5)).collect();
+        expected.extend((6..=9).map(|y| (3, y)));
+        expected.sort();
+        assert_eq!(open, expected);
+    }
+
+    #[test]
+    fn rooms_keep_their_walls() {
+        let mut map = Map::new(Tile::wall());
+        let room = Rect::new(2, 3, 4, 4);
+        create_room(room, &mut map);
+        for x in room.x1..=room.x2 {
+            for y in room.y1..=room.y2 {
+                let inside = x > room.x1 && x < room.x2 && y > room.y1 && y < room.y2;
+                assert_eq!(map[(x, y)].blocked, !inside, "({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn generated_rooms_dont_overlap() {
+        for seed in 0..500 {
+            let (map, rooms) = generate(seed);
+            assert!(!rooms.is_empty(), "seed {}: no rooms", seed);
+            for (index, room) in rooms.iter().enumerate() {
+                for other in &rooms[index + 1..] {
+                    assert!(!room.intersects_with(other), "seed {}", seed);
+                }
+                let (x, y) = room.center();
+                assert!(!map[(x, y)].blocked, "seed {}", seed);
+            }
+        }
+    }
+
+    #[test]
+    fn generated_maps_keep_their_outer_wall() {
+        for seed in 0..500 {
+            let (map, _) = generate(seed);
+            for x in 0..MAP_WIDTH {
+                for y in 0..MAP_HEIGHT {
+                    let edge = x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1;
+                    if edge {
+                        assert!(map[(x, y)].blocked, "seed {}", seed);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn every_room_can_be_reached_from_the_first() {
+        for seed in 0..500 {
+            let (map, rooms) = generate(seed);
+            let (start_x, start_y) = rooms[0].center();
+            let seen = reachable(&map, start_x, start_y);
+            for room in &rooms {
+                let (x, y) = room.center();
+                assert!(seen[x as usize][y as usize], "seed {}: {:?}", seed, room);
+            }
+        }
+    }
+
+    #[test]
+    fn rooms_are_handed_over_as_they_are_dug() {
+        let mut map = Map::new(Tile::wall());
+        let mut rng = StdRng::from_seed(&[7][..]);
+        let mut seen = vec![];
+        let rooms = dig_rooms(&mut map, 30, 6, 10, &mut rng, |index, room, map, _| {
+            // the room is already carved out by the time we see it
+            let (x, y) = room.center();
+            assert!(!map[(x, y)].blocked);
+            seen.push((index, room.x1, room.y1));
+        });
+        let expected: Vec<_> = rooms
+            .iter()
+            .enumerate()
+            .map(|(index, room)| (index, room.x1, room.y1))
+            .collect();
+        assert_eq!(seen, expected);
+    }
+
+    #[test]
+    fn the_same_seed_digs_the_same_rooms() {
+        let describe = |seed| {
+            let (map, rooms) = generate(seed);
+            let walls: Vec<bool> = map.tiles().map(|tile| tile.blocked).collect();
+            let rooms: Vec<_> = rooms.iter().map(|room| (room.x1, room.y1)).collect();
+            (walls, rooms)
+        };
+        assert_eq!(describe(3), describe(3));
+        assert_ne!(describe(3), describe(4));
+    }
+}
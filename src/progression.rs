@@ -0,0 +1,115 @@
+//! Values that change as the player goes deeper into the dungeon.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
+}
+
+/// Returns a value that depends on level. the table specifies what
+/// value occurs after each level, default is 0.
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    table
+        .iter()
+        .filter(|transition| level >= transition.level)
+        .max_by_key(|transition| transition.level)
+        .map_or(0, |transition| transition.value)
+}
+
+/// Like `from_dungeon_level`, but the value changes gradually between
+/// the levels in the table instead of all at once.
+pub fn interpolate_dungeon_level(table: &[Transition], level: u32) -> u32 {
+    let below = table
+        .iter()
+        .filter(|transition| level >= transition.level)
+        .max_by_key(|transition| transition.level);
+    let above = table
+        .iter()
+        .filter(|transition| level < transition.level)
+        .min_by_key(|transition| transition.level);
+    match (below, above) {
+        (Some(below), Some(above)) => {
+            let progress = (level - below.level) as f32 / (above.level - below.level) as f32;
+            let change = (above.value as f32 - below.value as f32) * progress;
+            (below.value as f32 + change).round() as u32
+        }
+        (Some(below), None) => below.value,
+        (None, _) => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transitions(table: &[(u32, u32)]) -> Vec<Transition> {
+        table
+            .iter()
+            .map(|&(level, value)| Transition { level, value })
+            .collect()
+    }
+
+    #[test]
+    fn empty_table_is_always_zero() {
+        for &level in &[0, 1, 10] {
+            assert_eq!(from_dungeon_level(&[], level), 0);
+            assert_eq!(interpolate_dungeon_level(&[], level), 0);
+        }
+    }
+
+    #[test]
+    fn before_the_first_entry_is_zero() {
+        let table = transitions(&[(1, 10), (3, 20)]);
+        assert_eq!(from_dungeon_level(&table, 0), 0);
+        assert_eq!(interpolate_dungeon_level(&table, 0), 0);
+
+        // unless the table starts at level 0
+        let table = transitions(&[(0, 5), (2, 15)]);
+        assert_eq!(from_dungeon_level(&table, 0), 5);
+        assert_eq!(interpolate_dungeon_level(&table, 0), 5);
+    }
+
+    #[test]
+    fn dungeon_level_steps_and_interpolates() {
+        let table = transitions(&[(1, 10), (3, 20), (5, 30)]);
+        let steps: Vec<u32> = (1..6)
+            .map(|level| from_dungeon_level(&table, level))
+            .collect();
+        assert_eq!(steps, vec![10, 10, 20, 20, 30]);
+        let gradual: Vec<u32> = (1..6)
+            .map(|level| interpolate_dungeon_level(&table, level))
+            .collect();
+        assert_eq!(gradual, vec![10, 15, 20, 25, 30]);
+    }
+
+    #[test]
+    fn dungeon_level_table_order_does_not_matter() {
+        let table = transitions(&[(5, 30), (1, 10), (3, 20)]);
+        assert_eq!(from_dungeon_level(&table, 4), 20);
+        assert_eq!(interpolate_dungeon_level(&table, 4), 25);
+    }
+
+    #[test]
+    fn beyond_the_last_entry_stays_put() {
+        let table = transitions(&[(1, 10), (3, 20)]);
+        for &level in &[3, 4, 100] {
+            assert_eq!(from_dungeon_level(&table, level), 20);
+            assert_eq!(interpolate_dungeon_level(&table, level), 20);
+        }
+        // a falling curve levels off too
+        let table = transitions(&[(1, 30), (4, 0)]);
+        assert_eq!(interpolate_dungeon_level(&table, 2), 20);
+        assert_eq!(interpolate_dungeon_level(&table, 9), 0);
+    }
+
+    #[test]
+    fn the_same_level_twice_keeps_the_last_value() {
+        // `max_by_key` picks the last of equal keys
+        let table = transitions(&[(2, 10), (2, 40)]);
+        assert_eq!(from_dungeon_level(&table, 1), 0);
+        assert_eq!(from_dungeon_level(&table, 2), 40);
+        assert_eq!(interpolate_dungeon_level(&table, 3), 40);
+    }
+}